@@ -0,0 +1,220 @@
+use super::*;
+use md5::{Digest, Md5};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use stunny_core::attributes::*;
+use tokio::time::Instant;
+
+/// Authentication parameters from a 401 (Unauthenticated) or 438 (Stale Nonce) error response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub realm: String,
+    pub nonce: String,
+    pub password_algorithms: Vec<PasswordAlgorithm>,
+}
+
+pub const UNAUTHENTICATED: u16 = 401;
+pub const STALE_NONCE: u16 = 438;
+
+/// Prefix of nonces that carry the STUN security feature set (RFC 8489).
+pub const NONCE_COOKIE: &str = "obMatJos2";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SecurityFeatures {
+    pub password_algorithms: bool,
+    pub username_anonymity: bool,
+}
+
+impl Challenge {
+    /// Feature bits encoded in the nonce, `None` if the nonce doesn't start with the cookie.
+    pub fn security_features(&self) -> Option<SecurityFeatures> {
+        let encoded = self.nonce.strip_prefix(NONCE_COOKIE)?.get(..4)?;
+        let mut bits = 0u32;
+        for c in encoded.bytes() {
+            let sextet = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            bits = (bits << 6) | sextet as u32;
+        }
+        Some(SecurityFeatures {
+            password_algorithms: bits & 0b01 != 0,
+            username_anonymity: bits & 0b10 != 0,
+        })
+    }
+}
+
+impl Response {
+    /// Decode the authentication challenge, if this is a 401 or 438 error response carrying
+    /// REALM and NONCE.
+    pub fn challenge(&self) -> Option<Challenge> {
+        if self.success {
+            return None;
+        }
+        let mut attributes = self.attributes.clone();
+        let error_code = attributes.extract_attribute::<ErrorCode>().ok()?;
+        if error_code.code != UNAUTHENTICATED && error_code.code != STALE_NONCE {
+            return None;
+        }
+        Some(Challenge {
+            realm: attributes.extract_attribute::<Realm>().ok()?.0,
+            nonce: attributes.extract_attribute::<Nonce>().ok()?.0,
+            password_algorithms: attributes
+                .extract_attribute::<PasswordAlgorithms>()
+                .map(|attr| attr.0)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Sends requests using the long-term credential mechanism. The realm and nonce obtained from
+/// each server are cached and reused until the server reports a stale nonce (or, if configured,
+/// until they expire), so that only the first request to a server incurs a 401 round-trip.
+/// Only the MD5 password algorithm is supported.
+#[derive(Clone)]
+pub struct AuthenticatingSender {
+    sender: RequestSender,
+    username: String,
+    password: String,
+    nonce_validity: Option<Duration>,
+    nonces: Arc<Mutex<HashMap<SocketAddr, CachedNonce>>>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedNonce {
+    realm: String,
+    nonce: String,
+    password_algorithms: Option<Vec<PasswordAlgorithm>>,
+    key: Vec<u8>,
+    obtained_at: Instant,
+}
+
+impl AuthenticatingSender {
+    pub fn new(sender: RequestSender, username: String, password: String) -> Self {
+        Self {
+            sender,
+            username,
+            password,
+            nonce_validity: None,
+            nonces: Default::default(),
+        }
+    }
+
+    /// Discard cached nonces older than `validity` instead of waiting for a 438 from the server.
+    pub fn with_nonce_validity(mut self, validity: Duration) -> Self {
+        self.nonce_validity = Some(validity);
+        self
+    }
+
+    /// Send a request, answering at most one challenge from the server. Error responses that
+    /// can't be answered, including a challenge to the retried request, are returned as is.
+    pub async fn send_request(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> Result<Response, TransactionError> {
+        let mut nonce = self.cached_nonce(destination);
+        let mut challenged = false;
+        loop {
+            let response = match &nonce {
+                Some(nonce) => {
+                    let mut attributes = attributes.clone();
+                    self.append_credentials(&mut attributes, nonce);
+                    self.sender
+                        .send_request_with_integrity(
+                            destination,
+                            method,
+                            attributes,
+                            nonce.key.clone(),
+                        )
+                        .await?
+                }
+                None => {
+                    self.sender
+                        .send_request(destination, method, attributes.clone())
+                        .await?
+                }
+            };
+            let challenge = match response.challenge() {
+                Some(challenge) if !challenged => challenge,
+                _ => return Ok(response),
+            };
+            challenged = true;
+            nonce = self.accept_challenge(destination, challenge);
+            if nonce.is_none() {
+                return Ok(response);
+            }
+        }
+    }
+
+    fn cached_nonce(&self, destination: SocketAddr) -> Option<CachedNonce> {
+        let mut nonces = self.nonces.lock().unwrap();
+        let cached = nonces.get(&destination)?;
+        if let Some(validity) = self.nonce_validity {
+            if cached.obtained_at.elapsed() >= validity {
+                nonces.remove(&destination);
+                return None;
+            }
+        }
+        Some(cached.clone())
+    }
+
+    fn accept_challenge(
+        &self,
+        destination: SocketAddr,
+        challenge: Challenge,
+    ) -> Option<CachedNonce> {
+        let features = challenge.security_features().unwrap_or_default();
+        let password_algorithms =
+            if features.password_algorithms && !challenge.password_algorithms.is_empty() {
+                if !challenge
+                    .password_algorithms
+                    .iter()
+                    .any(|entry| entry.algorithm == PasswordAlgorithm::MD5)
+                {
+                    log::warn!("No supported password algorithm offered by {destination}");
+                    return None;
+                }
+                Some(challenge.password_algorithms)
+            } else {
+                None
+            };
+        let key = Md5::digest(format!(
+            "{}:{}:{}",
+            self.username, challenge.realm, self.password
+        ))
+        .to_vec();
+        let nonce = CachedNonce {
+            realm: challenge.realm,
+            nonce: challenge.nonce,
+            password_algorithms,
+            key,
+            obtained_at: Instant::now(),
+        };
+        self.nonces
+            .lock()
+            .unwrap()
+            .insert(destination, nonce.clone());
+        Some(nonce)
+    }
+
+    fn append_credentials(&self, attributes: &mut Vec<Tlv>, nonce: &CachedNonce) {
+        attributes.append_attribute(Username(self.username.clone()));
+        attributes.append_attribute(Realm(nonce.realm.clone()));
+        attributes.append_attribute(Nonce(nonce.nonce.clone()));
+        if let Some(password_algorithms) = &nonce.password_algorithms {
+            attributes.append_attribute(PasswordAlgorithms(password_algorithms.clone()));
+            attributes.append_attribute(PasswordAlgorithm {
+                algorithm: PasswordAlgorithm::MD5,
+                parameters: Vec::new(),
+            });
+        }
+    }
+}
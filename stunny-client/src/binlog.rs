@@ -0,0 +1,174 @@
+use super::*;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Sent = 0,
+    Retransmitted = 1,
+    Response = 2,
+    ErrorResponse = 3,
+    Timeout = 4,
+}
+
+/// A single transaction event in the binary log. Encoded as the event kind (1 byte), the
+/// timestamp in microseconds (8 bytes), the transaction id (12 bytes), the IP version (1 byte),
+/// the IP address (4 or 16 bytes) and the port (2 bytes), all in network byte order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// Time since the log was created.
+    pub timestamp: Duration,
+    pub kind: EventKind,
+    pub transaction_id: [u8; 12],
+    /// Destination of the request, or source of the response.
+    pub address: SocketAddr,
+}
+
+impl LogRecord {
+    pub fn encode_into(&self, buffer: &mut Vec<u8>) {
+        buffer.push(self.kind as u8);
+        buffer.extend_from_slice(&(self.timestamp.as_micros() as u64).to_be_bytes());
+        buffer.extend_from_slice(&self.transaction_id);
+        match self.address.ip() {
+            IpAddr::V4(ip) => {
+                buffer.push(4);
+                buffer.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                buffer.push(6);
+                buffer.extend_from_slice(&ip.octets());
+            }
+        }
+        buffer.extend_from_slice(&self.address.port().to_be_bytes());
+    }
+
+    /// Decode the record at the start of `buffer` and advance past it. `None` if the record is
+    /// truncated or malformed.
+    pub fn decode_from(buffer: &mut &[u8]) -> Option<Self> {
+        fn take<const N: usize>(buffer: &mut &[u8]) -> Option<[u8; N]> {
+            if buffer.len() < N {
+                return None;
+            }
+            let (head, tail) = buffer.split_at(N);
+            *buffer = tail;
+            head.try_into().ok()
+        }
+
+        let kind = match take::<1>(buffer)?[0] {
+            0 => EventKind::Sent,
+            1 => EventKind::Retransmitted,
+            2 => EventKind::Response,
+            3 => EventKind::ErrorResponse,
+            4 => EventKind::Timeout,
+            _ => return None,
+        };
+        let timestamp = Duration::from_micros(u64::from_be_bytes(take(buffer)?));
+        let transaction_id = take(buffer)?;
+        let ip = match take::<1>(buffer)?[0] {
+            4 => IpAddr::V4(Ipv4Addr::from(take::<4>(buffer)?)),
+            6 => IpAddr::V6(Ipv6Addr::from(take::<16>(buffer)?)),
+            _ => return None,
+        };
+        let port = u16::from_be_bytes(take(buffer)?);
+        Some(Self {
+            timestamp,
+            kind,
+            transaction_id,
+            address: SocketAddr::new(ip, port),
+        })
+    }
+}
+
+/// Create a transaction observer that records every transmission, response and timeout, and the
+/// writer task that serializes the records to `writer`. Up to `capacity` records are buffered in
+/// between, further records are dropped until the writer catches up.
+pub fn binary_log<W: AsyncWrite + Unpin>(
+    writer: W,
+    capacity: usize,
+) -> (BinaryLog, BinaryLogWriter<W>) {
+    let (sink, source) = mpsc::channel(capacity);
+    (
+        BinaryLog {
+            start_time: Instant::now(),
+            sink,
+        },
+        BinaryLogWriter { source, writer },
+    )
+}
+
+/// To be passed to `Processor::set_observer()`.
+pub struct BinaryLog {
+    start_time: Instant,
+    sink: mpsc::Sender<LogRecord>,
+}
+
+impl BinaryLog {
+    fn record(&self, kind: EventKind, transaction_id: &[u8; 12], address: SocketAddr) {
+        let record = LogRecord {
+            timestamp: self.start_time.elapsed(),
+            kind,
+            transaction_id: *transaction_id,
+            address,
+        };
+        if self.sink.try_send(record).is_err() {
+            log::warn!("Dropping binary log record: writer is lagging behind");
+        }
+    }
+}
+
+impl TransactionObserver for BinaryLog {
+    fn on_transmission(
+        &mut self,
+        destination: SocketAddr,
+        transaction_id: &[u8; 12],
+        attempt: usize,
+    ) {
+        let kind = if attempt == 1 {
+            EventKind::Sent
+        } else {
+            EventKind::Retransmitted
+        };
+        self.record(kind, transaction_id, destination);
+    }
+
+    fn on_response(&mut self, source: SocketAddr, transaction_id: &[u8; 12], success: bool) {
+        let kind = if success {
+            EventKind::Response
+        } else {
+            EventKind::ErrorResponse
+        };
+        self.record(kind, transaction_id, source);
+    }
+
+    fn on_timeout(
+        &mut self,
+        destination: SocketAddr,
+        transaction_id: &[u8; 12],
+        _attempts: &[Attempt],
+    ) {
+        self.record(EventKind::Timeout, transaction_id, destination);
+    }
+}
+
+pub struct BinaryLogWriter<W> {
+    source: mpsc::Receiver<LogRecord>,
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> BinaryLogWriter<W> {
+    /// Write records until the `BinaryLog` is dropped, then flush and return the writer.
+    pub async fn run(mut self) -> io::Result<W> {
+        let mut buffer = Vec::new();
+        while let Some(record) = self.source.recv().await {
+            buffer.clear();
+            record.encode_into(&mut buffer);
+            self.writer.write_all(&buffer).await?;
+        }
+        self.writer.flush().await?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,44 @@
+use stunny_core::message::Tlv;
+
+/// Source of the buffers that hold attribute values of outgoing requests. The manager keeps a
+/// copy of every outstanding request for retransmissions, and releases it back to the pool once
+/// the transaction is over.
+pub trait BufferPool {
+    /// Return an empty buffer, preferably one with at least `capacity` bytes already allocated.
+    fn acquire(&mut self, capacity: usize) -> Vec<u8>;
+
+    fn release(&mut self, buffer: Vec<u8>);
+}
+
+/// Allocates a fresh buffer every time.
+#[derive(Default)]
+pub struct HeapAllocator;
+
+impl BufferPool for HeapAllocator {
+    fn acquire(&mut self, capacity: usize) -> Vec<u8> {
+        Vec::with_capacity(capacity)
+    }
+
+    fn release(&mut self, _buffer: Vec<u8>) {}
+}
+
+pub(super) fn copy_attributes(pool: &mut dyn BufferPool, attributes: &[Tlv]) -> Vec<Tlv> {
+    attributes
+        .iter()
+        .map(|tlv| {
+            let mut value = pool.acquire(tlv.value.len());
+            value.clear();
+            value.extend_from_slice(&tlv.value);
+            Tlv {
+                attribute_type: tlv.attribute_type,
+                value,
+            }
+        })
+        .collect()
+}
+
+pub(super) fn release_attributes(pool: &mut dyn BufferPool, attributes: Vec<Tlv>) {
+    for tlv in attributes {
+        pool.release(tlv.value);
+    }
+}
@@ -0,0 +1,87 @@
+use tokio::time::Instant;
+
+#[cfg(any(test, feature = "test-util"))]
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+#[cfg(any(test, feature = "test-util"))]
+use tokio::sync::Notify;
+
+/// Source of time for the transaction manager.
+#[derive(Clone, Default)]
+pub(super) enum Clock {
+    #[default]
+    System,
+    #[cfg(any(test, feature = "test-util"))]
+    Manual(ManualClock),
+}
+
+impl Clock {
+    pub(super) fn now(&self) -> Instant {
+        match self {
+            Clock::System => Instant::now(),
+            #[cfg(any(test, feature = "test-util"))]
+            Clock::Manual(clock) => clock.now(),
+        }
+    }
+
+    /// When a message was received, preferring the transport timestamp. The transport always uses
+    /// the system time, so it's ignored when the clock is manual.
+    pub(super) fn received_at(&self, transport_timestamp: Option<Instant>) -> Instant {
+        match (self, transport_timestamp) {
+            (Clock::System, Some(timestamp)) => timestamp,
+            _ => self.now(),
+        }
+    }
+
+    /// Wait until `deadline` has been reached according to this clock.
+    pub(super) async fn sleep_until(&self, deadline: Instant) {
+        match self {
+            Clock::System => tokio::time::sleep_until(deadline).await,
+            #[cfg(any(test, feature = "test-util"))]
+            Clock::Manual(clock) => loop {
+                // register before checking so that a concurrent advance() is not missed
+                let ticked = clock.0.ticks.notified();
+                if clock.now() >= deadline {
+                    break;
+                }
+                ticked.await;
+            },
+        }
+    }
+}
+
+/// Time controlled by the test rather than by the runtime. Obtained via
+/// `Processor::use_manual_clock()`, the processor fires retransmissions and timeouts
+/// only when the clock is advanced past their deadlines.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Clone)]
+pub struct ManualClock(Arc<ManualClockState>);
+
+#[cfg(any(test, feature = "test-util"))]
+struct ManualClockState {
+    now: Mutex<Instant>,
+    ticks: Notify,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl ManualClock {
+    pub(super) fn new() -> Self {
+        Self(Arc::new(ManualClockState {
+            now: Mutex::new(Instant::now()),
+            ticks: Notify::new(),
+        }))
+    }
+
+    pub fn now(&self) -> Instant {
+        *self.0.now.lock().unwrap()
+    }
+
+    /// Move the clock forward and wake up the processor. The processor handles any expired
+    /// timeouts the next time it is polled.
+    pub fn advance(&self, duration: Duration) {
+        *self.0.now.lock().unwrap() += duration;
+        self.0.ticks.notify_waiters();
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+use stunny_core::attributes::AttributeNames;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Max number of requests in progress at a time, not counting keepalives. Requests submitted
+    /// once it's reached are handled according to `backpressure`.
+    pub max_outstanding_requests: usize,
+    pub backpressure: Backpressure,
+    /// Hard limit on scheduled retransmission timeouts, on top of `max_outstanding_requests`
+    /// (which keepalives don't count towards). New requests fail with
+    /// `TransactionError::Overloaded` while it's reached.
+    pub max_pending_timeouts: usize,
+    /// Drop incoming messages with an invalid FINGERPRINT instead of only logging a warning.
+    /// Should be enabled when STUN is multiplexed with other protocols, e.g. in ICE.
+    pub reject_invalid_fingerprint: bool,
+    /// Append FINGERPRINT to all outgoing requests and indications, not only to those signed
+    /// with MESSAGE-INTEGRITY. Implies `reject_invalid_fingerprint`.
+    pub append_fingerprint: bool,
+    /// Max encoded size of outgoing indications, larger ones are rejected before reaching the
+    /// transport. Includes FINGERPRINT if `append_fingerprint` is set. Should match the
+    /// transport, e.g. `transport::udp::MAX_MESSAGE_SIZE`.
+    pub max_indication_size: Option<usize>,
+    /// The transport emits `TransportEvent::Delivered` for indications, e.g. a connection pool
+    /// with `confirm_delivery` set. Otherwise `IndicationSender::send_indication_confirmed()`
+    /// fails immediately.
+    pub transport_confirms_delivery: bool,
+    /// Max total encoded size of the attributes supplied with a request, larger ones fail with
+    /// `TransactionError::AttributesTooLarge`. Bounds the memory held by each outstanding
+    /// transaction, independently of what the transport could carry. SOFTWARE,
+    /// MESSAGE-INTEGRITY and FINGERPRINT appended by the client itself are not counted.
+    pub max_request_attributes_size: Option<usize>,
+    /// Resend requests answered with a 5xx error response, which is usually transient.
+    pub server_error_retry: Option<ServerErrorRetry>,
+    /// Pace out new transactions, e.g. to avoid tripping rate limits of servers when probing
+    /// many of them. Retransmissions and keepalives are not affected.
+    pub rate_limit: Option<RateLimit>,
+    /// Fail setup if the transaction id generator isn't a CSPRNG, see `TransactionIdRng`.
+    pub require_cryptographic_rng: bool,
+    /// SOFTWARE attribute appended to every outgoing request that doesn't have one.
+    pub software: Option<String>,
+    /// Max number of received messages handled in a row before checking timeouts and other
+    /// inputs again. Larger values drain bursts of responses faster.
+    pub ingress_batch_size: usize,
+    /// Names of vendor-specific attributes on top of the standard ones, for diagnostics such as
+    /// the trace log of received messages.
+    pub attribute_names: AttributeNames,
+}
+
+/// What `RequestSender` does with a new request while `Config::max_outstanding_requests`
+/// transactions are in progress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait until one of them completes.
+    #[default]
+    Wait,
+    /// Fail immediately with `TransactionError::TooManyOutstanding`.
+    Reject,
+}
+
+/// New transactions are started at most every `1s / requests_per_second`, those submitted in
+/// between wait in a queue.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub requests_per_second: u32,
+    /// New requests fail with `TransactionError::Overloaded` while this many are queued.
+    pub max_queued: usize,
+}
+
+impl RateLimit {
+    /// Min time between the starts of two transactions.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(1) / self.requests_per_second.max(1)
+    }
+}
+
+/// Each retry is a new transaction, sent after a delay that doubles every time.
+#[derive(Debug, Clone)]
+pub struct ServerErrorRetry {
+    pub max_retries: usize,
+    pub initial_backoff: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_outstanding_requests: 64,
+            backpressure: Backpressure::Wait,
+            max_pending_timeouts: 4096,
+            reject_invalid_fingerprint: false,
+            append_fingerprint: false,
+            max_indication_size: None,
+            transport_confirms_delivery: false,
+            max_request_attributes_size: None,
+            server_error_retry: None,
+            rate_limit: None,
+            require_cryptographic_rng: false,
+            software: None,
+            ingress_batch_size: 1,
+            attribute_names: AttributeNames::default(),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use super::*;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use stunny_core::attributes::*;
+
+pub const BINDING_METHOD: u16 = 0x0001;
+
+/// Everything a STUN server reports in a successful Binding response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingResult {
+    /// Server-reflexive address, from XOR-MAPPED-ADDRESS or, for older servers, MAPPED-ADDRESS.
+    pub reflexive_address: SocketAddr,
+    /// Raw XOR-MAPPED-ADDRESS and MAPPED-ADDRESS values. If both are present and differ, a
+    /// middlebox has likely rewritten the plain one (application-layer gateway).
+    pub xor_mapped_address: Option<SocketAddr>,
+    pub mapped_address: Option<SocketAddr>,
+    pub other_address: Option<SocketAddr>,
+    pub response_origin: Option<SocketAddr>,
+    pub software: Option<String>,
+}
+
+impl BindingResult {
+    pub fn from_response(response: Response) -> Result<Self, TransactionError> {
+        let mut attributes = response.attributes;
+        if !response.success {
+            return Err(match attributes.clone().extract_attribute::<ErrorCode>() {
+                Ok(code) => TransactionError::ErrorResponse { code, attributes },
+                Err(e) => e.into(),
+            });
+        }
+        let xor_mapped_address =
+            optional(attributes.extract_attribute::<XorMappedAddress>())?.map(|attr| attr.0);
+        let mapped_address =
+            optional(attributes.extract_attribute::<MappedAddress>())?.map(|attr| attr.0);
+        let reflexive_address = xor_mapped_address
+            .or(mapped_address)
+            .ok_or(LookupError::NotFound(XorMappedAddress::ID))?;
+        Ok(Self {
+            reflexive_address,
+            xor_mapped_address,
+            mapped_address,
+            other_address: optional(attributes.extract_attribute::<OtherAddress>())?
+                .map(|attr| attr.0),
+            response_origin: optional(attributes.extract_attribute::<ResponseOrigin>())?
+                .map(|attr| attr.0),
+            software: optional(attributes.extract_attribute::<Software>())?.map(|attr| attr.0),
+        })
+    }
+}
+
+fn optional<A>(result: Result<A, LookupError>) -> Result<Option<A>, LookupError> {
+    match result {
+        Ok(attribute) => Ok(Some(attribute)),
+        Err(LookupError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl RequestSender {
+    /// Send a Binding request with no attributes and decode the response.
+    pub async fn binding_request(
+        &self,
+        destination: SocketAddr,
+    ) -> Result<BindingResult, TransactionError> {
+        let response = self
+            .send_request(destination, BINDING_METHOD, Vec::new())
+            .await?;
+        BindingResult::from_response(response)
+    }
+
+    /// Send a Binding request with no attributes and return the server-reflexive address, i.e.
+    /// our public address as seen by `server`. See `binding_request()` for the other details.
+    pub async fn discover_mapped_address(
+        &self,
+        server: SocketAddr,
+    ) -> Result<SocketAddr, TransactionError> {
+        Ok(self.binding_request(server).await?.reflexive_address)
+    }
+
+    /// Send a Binding request to each of `servers` at once. The returned stream yields the
+    /// server-reflexive address reported by each server in the order the responses arrive, and
+    /// skips servers that fail or don't respond.
+    pub async fn discover_reflexive(
+        &self,
+        servers: impl IntoIterator<Item = SocketAddr>,
+    ) -> ReflexiveAddresses {
+        let mut pending = Vec::new();
+        for server in servers {
+            match self
+                .submit_request(server, BINDING_METHOD, Vec::new())
+                .await
+            {
+                Ok(response) => pending.push(response),
+                Err(e) => log::debug!("Skipping {server}: {e}"),
+            }
+        }
+        ReflexiveAddresses { pending }
+    }
+}
+
+/// Stream of (server, reflexive address) pairs returned by `RequestSender::discover_reflexive()`.
+pub struct ReflexiveAddresses {
+    pending: Vec<PendingResponse>,
+}
+
+impl futures_util::Stream for ReflexiveAddresses {
+    type Item = (SocketAddr, SocketAddr);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let Poll::Ready(result) = Pin::new(&mut self.pending[i]).poll(cx) else {
+                i += 1;
+                continue;
+            };
+            let server = self.pending.swap_remove(i).destination();
+            match result.and_then(BindingResult::from_response) {
+                Ok(binding) => return Poll::Ready(Some((server, binding.reflexive_address))),
+                Err(e) => log::debug!("No reflexive address from {server}: {e}"),
+            }
+        }
+        if self.pending.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
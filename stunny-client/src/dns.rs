@@ -1,22 +1,34 @@
 #![allow(dead_code)]
+use crate::uri::{Scheme, ServerUri};
+use std::collections::HashMap;
+use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
-
-const URI_PREFIX: &str = "stun:";
-const URI_SECURE_PREFIX: &str = "stuns:";
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
 
 pub(crate) async fn resolve_uri(uri: impl AsRef<str>) -> io::Result<Vec<SocketAddr>> {
-    let uri = uri
-        .as_ref()
-        .strip_prefix(URI_PREFIX)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "stun URI prefix missing"))?;
+    resolve_uri_with_scheme(uri.as_ref(), Scheme::Stun).await
+}
 
-    let uri = if has_port(uri) {
-        uri.to_owned()
-    } else {
-        format!("{uri}:3478")
-    };
-    let addrs: Vec<_> = tokio::net::lookup_host(uri).await?.collect();
+pub(crate) async fn resolve_secure_uri(uri: impl AsRef<str>) -> io::Result<Vec<SocketAddr>> {
+    resolve_uri_with_scheme(uri.as_ref(), Scheme::Stuns).await
+}
+
+async fn resolve_uri_with_scheme(uri: &str, scheme: Scheme) -> io::Result<Vec<SocketAddr>> {
+    let uri: ServerUri = uri
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if uri.scheme != scheme {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{scheme} URI expected"),
+        ));
+    }
+    let addrs: Vec<_> = tokio::net::lookup_host((uri.host.as_str(), uri.port))
+        .await?
+        .collect();
     if addrs.is_empty() {
         Err(io::Error::from(io::ErrorKind::HostUnreachable))
     } else {
@@ -24,34 +36,179 @@ pub(crate) async fn resolve_uri(uri: impl AsRef<str>) -> io::Result<Vec<SocketAd
     }
 }
 
-pub(crate) async fn resolve_secure_uri(uri: impl AsRef<str>) -> io::Result<Vec<SocketAddr>> {
-    let uri = uri
-        .as_ref()
-        .strip_prefix(URI_SECURE_PREFIX)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "stuns URI prefix missing"))?;
+/// Addresses a hostname resolved to, and for how long they may be cached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub addrs: Vec<SocketAddr>,
+    pub ttl: Duration,
+}
 
-    let uri = if has_port(uri) {
-        uri.to_owned()
-    } else {
-        format!("{uri}:5349")
-    };
-    let addrs: Vec<_> = tokio::net::lookup_host(uri).await?.collect();
-    if addrs.is_empty() {
-        Err(io::Error::from(io::ErrorKind::HostUnreachable))
-    } else {
-        Ok(addrs)
+pub trait Resolver {
+    /// Resolve `host` in the "hostname:port" form.
+    fn resolve(&self, host: &str) -> impl Future<Output = io::Result<Resolution>> + Send;
+}
+
+/// Resolver of the operating system. It doesn't report TTLs, so every answer is cached for the
+/// same fixed duration.
+pub struct SystemResolver {
+    ttl: Duration,
+}
+
+impl SystemResolver {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl }
     }
 }
 
-fn has_port(uri: &str) -> bool {
-    uri.rsplit_once(':')
-        .and_then(|(_hostname, port)| port.parse::<u16>().ok())
-        .is_some()
+impl Default for SystemResolver {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(300))
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str) -> impl Future<Output = io::Result<Resolution>> + Send {
+        let host = host.to_owned();
+        let ttl = self.ttl;
+        async move {
+            Ok(Resolution {
+                addrs: tokio::net::lookup_host(host).await?.collect(),
+                ttl,
+            })
+        }
+    }
+}
+
+/// Remembers the addresses of STUN servers until the TTL of the DNS answer expires, so that a
+/// long-running client follows address changes without resolving on every request.
+pub struct AddressCache<R> {
+    resolver: R,
+    entries: Mutex<HashMap<String, CachedAddrs>>,
+}
+
+struct CachedAddrs {
+    addrs: Vec<SocketAddr>,
+    expires_at: Instant,
+}
+
+impl<R: Resolver> AddressCache<R> {
+    pub fn new(resolver: R) -> Self {
+        Self {
+            resolver,
+            entries: Default::default(),
+        }
+    }
+
+    /// Addresses of `host` ("hostname:port"), resolved again if the cached ones have expired.
+    /// If resolving fails, expired addresses are returned rather than none at all.
+    pub async fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        if let Some(cached) = self.entries.lock().unwrap().get(host) {
+            if cached.expires_at > Instant::now() {
+                return Ok(cached.addrs.clone());
+            }
+        }
+        let resolved = match self.resolver.resolve(host).await {
+            Ok(Resolution { addrs, .. }) if addrs.is_empty() => {
+                Err(io::Error::from(io::ErrorKind::HostUnreachable))
+            }
+            result => result,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        let Resolution { addrs, ttl } = match resolved {
+            Ok(resolution) => resolution,
+            Err(e) => {
+                let stale = entries.get(host).ok_or(e)?;
+                log::warn!("Failed to refresh addresses of {host}, using expired ones");
+                return Ok(stale.addrs.clone());
+            }
+        };
+        if entries
+            .get(host)
+            .is_some_and(|cached| cached.addrs != addrs)
+        {
+            log::info!("Addresses of {host} changed to {addrs:?}");
+        }
+        entries.insert(
+            host.to_owned(),
+            CachedAddrs {
+                addrs: addrs.clone(),
+                expires_at: Instant::now() + ttl,
+            },
+        );
+        Ok(addrs)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use local_async_utils::sec;
+    use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct MockResolver {
+        calls: AtomicUsize,
+    }
+
+    impl Resolver for MockResolver {
+        fn resolve(&self, host: &str) -> impl Future<Output = io::Result<Resolution>> + Send {
+            assert_eq!(host, "stun.example.org:3478");
+            let call = self.calls.fetch_add(1, Ordering::Relaxed);
+            async move {
+                match call {
+                    // the address changes after the first TTL
+                    0 | 1 => Ok(Resolution {
+                        addrs: vec![SocketAddr::V4(SocketAddrV4::new(
+                            Ipv4Addr::new(192, 0, 2, call as u8 + 1),
+                            3478,
+                        ))],
+                        ttl: sec!(60),
+                    }),
+                    _ => Err(io::Error::from(io::ErrorKind::TimedOut)),
+                }
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refresh_cached_addresses_after_ttl() {
+        let cache = AddressCache::new(MockResolver {
+            calls: AtomicUsize::new(0),
+        });
+        let first: SocketAddr = "192.0.2.1:3478".parse().unwrap();
+        let second: SocketAddr = "192.0.2.2:3478".parse().unwrap();
+
+        assert_eq!(
+            cache.lookup("stun.example.org:3478").await.unwrap(),
+            [first]
+        );
+        tokio::time::sleep(sec!(59)).await;
+        assert_eq!(
+            cache.lookup("stun.example.org:3478").await.unwrap(),
+            [first]
+        );
+        assert_eq!(cache.resolver.calls.load(Ordering::Relaxed), 1);
+
+        // when
+        tokio::time::sleep(sec!(1)).await;
+
+        // then
+        assert_eq!(
+            cache.lookup("stun.example.org:3478").await.unwrap(),
+            [second]
+        );
+        assert_eq!(cache.resolver.calls.load(Ordering::Relaxed), 2);
+
+        // when: refreshing fails
+        tokio::time::sleep(sec!(60)).await;
+
+        // then
+        assert_eq!(
+            cache.lookup("stun.example.org:3478").await.unwrap(),
+            [second]
+        );
+        assert_eq!(cache.resolver.calls.load(Ordering::Relaxed), 3);
+    }
 
     #[tokio::test]
     async fn resolve_stun_uri() {
@@ -1,4 +1,6 @@
 use std::io;
+use stunny_core::attributes::{ErrorCode, LookupError};
+use stunny_core::message::Tlv;
 use thiserror::Error;
 use tokio::sync::mpsc;
 
@@ -10,6 +12,12 @@ pub enum TransactionError {
     #[error("transaction channel is closed")]
     ChannelClosed,
 
+    #[error("transaction has been cancelled")]
+    Cancelled,
+
+    #[error("transaction processor has been shut down")]
+    Shutdown,
+
     #[error(
         "transaction method mismatch (request={request_method:#x}, response={response_method:#x}"
     )]
@@ -18,10 +26,69 @@ pub enum TransactionError {
         response_method: u16,
     },
 
+    #[error("message size {size} exceeds limit {limit}")]
+    PayloadTooLarge { size: usize, limit: usize },
+
+    /// The attributes of a request exceed `Config::max_request_attributes_size`.
+    #[error("attributes size {size} exceeds limit {limit}")]
+    AttributesTooLarge { size: usize, limit: usize },
+
+    #[error("connection limit reached")]
+    ConnectionLimitReached,
+
+    #[error("connection closed")]
+    ConnectionClosed,
+
+    #[error("connection lost")]
+    ConnectionLost,
+
+    #[error("destination unreachable")]
+    Unreachable,
+
+    /// The response didn't fit into a datagram, the request should be resent over TCP.
+    #[error("response truncated")]
+    Truncated,
+
+    /// `Config::transport_confirms_delivery` is not set.
+    #[error("transport doesn't confirm delivery")]
+    DeliveryConfirmationUnsupported,
+
+    #[error("too many transactions in progress")]
+    Overloaded,
+
+    #[error("too many outstanding requests")]
+    TooManyOutstanding,
+
+    /// The caller supplied an attribute that is appended automatically, i.e. MESSAGE-INTEGRITY
+    /// to a signed request or FINGERPRINT when it's enabled.
+    #[error("attribute {0:#06x} is reserved")]
+    ReservedAttribute(u16),
+
+    /// `attributes` are all attributes of the response as received, including ERROR-CODE, for
+    /// details not covered by `code` (e.g. vendor-specific attributes).
+    #[error("error response received (code={}, reason={})", .code.code, .code.reason)]
+    ErrorResponse {
+        code: ErrorCode,
+        attributes: Vec<Tlv>,
+    },
+
+    #[error("malformed response ({0})")]
+    MalformedResponse(#[from] LookupError),
+
+    #[cfg(feature = "ice")]
+    #[error(transparent)]
+    InvalidUfrag(#[from] crate::InvalidUfrag),
+
     #[error(transparent)]
     Io(#[from] io::Error),
 }
 
+#[derive(Error, Debug)]
+pub enum SetupError {
+    #[error("transaction id RNG is not cryptographically secure")]
+    InsecureRng,
+}
+
 impl<T> From<mpsc::error::SendError<T>> for TransactionError {
     fn from(_value: mpsc::error::SendError<T>) -> Self {
         TransactionError::ChannelClosed
@@ -0,0 +1,104 @@
+use super::*;
+use std::net::SocketAddr;
+use stunny_core::attributes::*;
+use thiserror::Error;
+
+/// Type preference of peer-reflexive candidates, as recommended by RFC 8445.
+pub const PEER_REFLEXIVE_PREFERENCE: u32 = 110;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid ICE ufrag {0:?}")]
+pub struct InvalidUfrag(pub String);
+
+/// USERNAME of a connectivity check, `remote-ufrag:local-ufrag`. Each ufrag must be 4 to 256
+/// characters from the ice-char set (ALPHA / DIGIT / "+" / "/").
+pub fn ice_username(remote_ufrag: &str, local_ufrag: &str) -> Result<Username, InvalidUfrag> {
+    for ufrag in [remote_ufrag, local_ufrag] {
+        let valid_chars = ufrag
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'+' || c == b'/');
+        if !valid_chars || !(4..=256).contains(&ufrag.len()) {
+            return Err(InvalidUfrag(ufrag.to_owned()));
+        }
+    }
+    Ok(Username(format!("{remote_ufrag}:{local_ufrag}")))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    pub address: SocketAddr,
+    pub priority: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Controlling { tie_breaker: u64 },
+    Controlled { tie_breaker: u64 },
+}
+
+/// Short-term credentials of both agents, as exchanged in the offer/answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IceCredentials {
+    pub local_ufrag: String,
+    pub remote_ufrag: String,
+    pub remote_password: String,
+}
+
+/// A single ICE connectivity check. Sent from whatever local address the transport is bound to,
+/// `local` only determines the advertised PRIORITY.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectivityCheck {
+    pub local: Candidate,
+    pub remote: Candidate,
+    pub credentials: IceCredentials,
+    pub role: Role,
+    pub nominate: bool,
+}
+
+impl ConnectivityCheck {
+    /// Attributes of the Binding request, excluding MESSAGE-INTEGRITY and FINGERPRINT which are
+    /// appended when the request is sent.
+    pub fn attributes(&self) -> Result<Vec<Tlv>, InvalidUfrag> {
+        let username = ice_username(
+            &self.credentials.remote_ufrag,
+            &self.credentials.local_ufrag,
+        )?;
+        // the priority a peer-reflexive candidate learned from this check would have
+        let priority = (PEER_REFLEXIVE_PREFERENCE << 24) | (self.local.priority & 0x00ff_ffff);
+
+        let mut attributes = Vec::new();
+        attributes.append_attribute(username);
+        attributes.append_attribute(Priority(priority));
+        match self.role {
+            Role::Controlling { tie_breaker } => {
+                attributes.append_attribute(IceControlling(tie_breaker));
+                if self.nominate {
+                    attributes.append_attribute(UseCandidate);
+                }
+            }
+            Role::Controlled { tie_breaker } => {
+                attributes.append_attribute(IceControlled(tie_breaker));
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+impl RequestSender {
+    /// Perform a connectivity check and return the mapped address reported by the remote agent.
+    /// Only the controlling agent can nominate.
+    pub async fn connectivity_check(
+        &self,
+        check: &ConnectivityCheck,
+    ) -> Result<SocketAddr, TransactionError> {
+        let response = self
+            .send_request_with_integrity(
+                check.remote.address,
+                BINDING_METHOD,
+                check.attributes()?,
+                check.credentials.remote_password.clone().into_bytes(),
+            )
+            .await?;
+        Ok(BindingResult::from_response(response)?.reflexive_address)
+    }
+}
@@ -1,17 +1,58 @@
 use super::*;
 use derive_more::Debug;
+use std::future::Future;
 use std::io;
 use std::pin::Pin;
-use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::{net::SocketAddr, time::Duration};
-use tokio::sync::{mpsc, oneshot, Semaphore};
+use stunny_core::attributes::{
+    unknown_required_attributes, AttributeCollection, Data, ErrorCode, XorPeerAddress,
+};
+use tokio::sync::{mpsc, oneshot, Semaphore, TryAcquireError};
+use tokio::time::{self, Instant};
 
 #[derive(Debug)]
 pub struct Response {
     pub success: bool,
     pub attributes: Vec<Tlv>,
     pub time_elapsed: Duration,
+    /// Time between submission and the first transmission, spent waiting for a free slot when
+    /// `max_outstanding_requests` is reached. Not included in `time_elapsed`.
+    pub queue_wait: Duration,
+    /// RTO the request was initially sent with, as calculated by the `RtoPolicy`.
+    pub initial_rto: Duration,
+}
+
+impl Response {
+    /// SOFTWARE of the server, found in error responses as well as in success ones.
+    pub fn software(&self) -> Option<String> {
+        self.attributes.iter().find_map(Tlv::as_software)
+    }
+
+    /// Types of the comprehension-required attributes (below 0x8000) not in `known`, in order of
+    /// appearance. RFC 8489 requires a response carrying any to be treated as unusable. Use
+    /// `AttributeClass` for the optional ones.
+    pub fn comprehension_required_unknown(&self, known: &[u16]) -> Vec<u16> {
+        unknown_required_attributes(&self.attributes, known)
+    }
+
+    /// ERROR-CODE of an error response. Looked up regardless of `success`, so that a malformed
+    /// success response carrying one can be diagnosed too.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.attributes.iter().find_map(Tlv::as_error_code)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionInfo {
+    pub destination: SocketAddr,
+    #[debug("{method:#06x}")]
+    pub method: u16,
+    pub transaction_id: [u8; 12],
+    pub attempts_made: usize,
+    pub time_elapsed: Duration,
 }
 
 #[derive(Debug)]
@@ -22,36 +63,474 @@ pub struct Indication {
     pub attributes: Vec<Tlv>,
 }
 
+/// TURN (RFC 8656) Send indication, from the client to the server.
+pub const SEND_METHOD: u16 = 0x0006;
+/// TURN (RFC 8656) Data indication, from the server to the client.
+pub const DATA_METHOD: u16 = 0x0007;
+
+impl Indication {
+    /// Send indication relaying `data` to `peer` through the TURN server at `server`.
+    pub fn send(server: SocketAddr, peer: SocketAddr, data: Vec<u8>) -> Self {
+        Self::relayed(SEND_METHOD, server, peer, data)
+    }
+
+    /// Data indication delivering `data` received from `peer` to the client at `client`.
+    pub fn data(client: SocketAddr, peer: SocketAddr, data: Vec<u8>) -> Self {
+        Self::relayed(DATA_METHOD, client, peer, data)
+    }
+
+    fn relayed(method: u16, farend_addr: SocketAddr, peer: SocketAddr, data: Vec<u8>) -> Self {
+        let mut attributes = Vec::new();
+        attributes.append_attribute(XorPeerAddress(peer));
+        attributes.append_attribute(Data(data));
+        Self {
+            farend_addr,
+            method,
+            attributes,
+        }
+    }
+
+    pub fn is_send(&self) -> bool {
+        self.method == SEND_METHOD
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.method == DATA_METHOD
+    }
+
+    /// XOR-PEER-ADDRESS of a Send or Data indication.
+    pub fn peer_address(&self) -> Option<SocketAddr> {
+        self.attributes
+            .clone()
+            .extract_attribute::<XorPeerAddress>()
+            .ok()
+            .map(|XorPeerAddress(addr)| addr)
+    }
+
+    /// DATA of a Send or Data indication.
+    pub fn payload(&self) -> Option<Vec<u8>> {
+        self.attributes
+            .clone()
+            .extract_attribute::<Data>()
+            .ok()
+            .map(|Data(data)| data)
+    }
+}
+
+/// How a single request is signed, overriding the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct Signing {
+    /// Append MESSAGE-INTEGRITY computed with this key.
+    #[debug(skip)]
+    pub integrity_key: Option<Vec<u8>>,
+    /// Append FINGERPRINT or not. If `None`, it's appended if the request has MESSAGE-INTEGRITY
+    /// or `Config::append_fingerprint` is set.
+    pub fingerprint: Option<bool>,
+}
+
+#[derive(Default)]
+struct RequestOptions {
+    integrity_key: Option<Vec<u8>>,
+    fingerprint: Option<bool>,
+    deadline: Option<Instant>,
+    timeout: Option<Duration>,
+    progress: Option<ProgressCallback>,
+}
+
 #[derive(Clone)]
 pub struct RequestSender {
-    sink: mpsc::Sender<Request>,
-    request_slots: Rc<Semaphore>,
+    sink: mpsc::Sender<Command>,
+    request_slots: Arc<Semaphore>,
+    backpressure: Backpressure,
+    server_error_retry: Option<ServerErrorRetry>,
+    rate_limit: Option<RateLimit>,
+    next_request_id: Arc<AtomicU64>,
 }
 
 impl RequestSender {
     pub(super) fn new(
-        sink: mpsc::Sender<Request>,
+        sink: mpsc::Sender<Command>,
         max_outstanding_requests: usize,
+        backpressure: Backpressure,
+        server_error_retry: Option<ServerErrorRetry>,
+        rate_limit: Option<RateLimit>,
     ) -> RequestSender {
         RequestSender {
             sink,
-            request_slots: Rc::new(Semaphore::const_new(max_outstanding_requests)),
+            request_slots: Arc::new(Semaphore::const_new(max_outstanding_requests)),
+            backpressure,
+            server_error_retry,
+            rate_limit,
+            next_request_id: Default::default(),
         }
     }
 
+    /// `Config::rate_limit` the processor has been set up with.
+    pub fn rate_limit(&self) -> Option<&RateLimit> {
+        self.rate_limit.as_ref()
+    }
+
+    /// Send a request and wait for the response. If `Config::server_error_retry` is set, 5xx
+    /// error responses are retried, and the last one is returned once retries are exhausted.
     pub async fn send_request(
         &self,
         destination: SocketAddr,
         method: u16,
         attributes: Vec<Tlv>,
     ) -> Result<Response, TransactionError> {
-        let _slot = self.request_slots.acquire().await;
+        self.send_with_retries(destination, method, attributes, Default::default())
+            .await
+    }
+
+    /// Same as `send_request()`, but invokes `callback` with the result instead of returning a
+    /// future. The transaction is driven by a task spawned on the current tokio runtime, so this
+    /// must be called from within one.
+    pub fn send_request_cb(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        callback: impl FnOnce(Result<Response, TransactionError>) + Send + 'static,
+    ) {
+        let sender = self.clone();
+        tokio::spawn(async move {
+            callback(sender.send_request(destination, method, attributes).await);
+        });
+    }
+
+    /// Send a request without waiting for its response. The transaction proceeds (and times out)
+    /// in the background, and the result can be collected later by awaiting the returned handle.
+    pub async fn submit_request(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> Result<PendingResponse, TransactionError> {
+        self.submit(destination, method, attributes, Default::default())
+            .await
+    }
+
+    /// Same as `send_request()`, but the transaction fails with `TransactionError::Timeout`
+    /// exactly at `deadline`, regardless of the RTO schedule. Useful for coordinating several
+    /// requests against one absolute deadline. 5xx responses are not retried.
+    pub async fn send_request_until(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        deadline: Instant,
+    ) -> Result<Response, TransactionError> {
+        let options = RequestOptions {
+            deadline: Some(deadline),
+            ..Default::default()
+        };
+        self.submit(destination, method, attributes, options)
+            .await?
+            .await
+    }
+
+    /// Same as `send_request_until()` with the deadline `timeout` from now, e.g. to give up early
+    /// on some destinations. The RTO policy still applies, so whichever of the two times out
+    /// first ends the transaction. The deadline follows the processor's clock, including
+    /// `Processor::use_manual_clock()`.
+    pub async fn send_request_with_timeout(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        timeout: Duration,
+    ) -> Result<Response, TransactionError> {
+        let options = RequestOptions {
+            timeout: Some(timeout),
+            ..Default::default()
+        };
+        self.submit(destination, method, attributes, options)
+            .await?
+            .await
+    }
+
+    /// Same as `send_request()`, but invokes `progress` with the attempt number (starting at 2)
+    /// every time the request is retransmitted. The callback runs on the processor, so it must
+    /// not block. 5xx responses are not retried.
+    pub async fn send_request_with_progress(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        progress: impl FnMut(usize) + Send + 'static,
+    ) -> Result<Response, TransactionError> {
+        let options = RequestOptions {
+            progress: Some(Box::new(progress)),
+            ..Default::default()
+        };
+        self.submit(destination, method, attributes, options)
+            .await?
+            .await
+    }
+
+    /// Same as `send_request()`, but signs the request with MESSAGE-INTEGRITY and FINGERPRINT
+    /// using `key` (e.g. the remote ICE password, or the long-term credential key).
+    pub async fn send_request_with_integrity(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        key: Vec<u8>,
+    ) -> Result<Response, TransactionError> {
+        let signing = Signing {
+            integrity_key: Some(key),
+            fingerprint: None,
+        };
+        self.send_with_retries(destination, method, attributes, signing)
+            .await
+    }
+
+    /// Same as `send_request()`, but signed according to `signing` instead of `Config`, e.g. to
+    /// omit FINGERPRINT for a server that rejects it.
+    pub async fn send_request_signed(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        signing: Signing,
+    ) -> Result<Response, TransactionError> {
+        self.send_with_retries(destination, method, attributes, signing)
+            .await
+    }
+
+    async fn send_with_retries(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        signing: Signing,
+    ) -> Result<Response, TransactionError> {
+        let Some(retry) = &self.server_error_retry else {
+            let options = RequestOptions {
+                integrity_key: signing.integrity_key,
+                fingerprint: signing.fingerprint,
+                ..Default::default()
+            };
+            return self
+                .submit(destination, method, attributes, options)
+                .await?
+                .await;
+        };
+        let mut backoff = retry.initial_backoff;
+        let mut retries_left = retry.max_retries;
+        loop {
+            let response = self
+                .submit(
+                    destination,
+                    method,
+                    attributes.clone(),
+                    RequestOptions {
+                        integrity_key: signing.integrity_key.clone(),
+                        fingerprint: signing.fingerprint,
+                        ..Default::default()
+                    },
+                )
+                .await?
+                .await?;
+            if retries_left == 0 || !is_server_error(&response) {
+                return Ok(response);
+            }
+            log::debug!("Server error from {destination}, retrying in {backoff:?}");
+            time::sleep(backoff).await;
+            backoff *= 2;
+            retries_left -= 1;
+        }
+    }
+
+    async fn submit(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        options: RequestOptions,
+    ) -> Result<PendingResponse, TransactionError> {
+        let submitted_at = Instant::now();
+        let slots = self.request_slots.clone();
+        let slot = match self.backpressure {
+            Backpressure::Wait => slots
+                .acquire_owned()
+                .await
+                .map_err(|_e| TransactionError::Shutdown)?,
+            Backpressure::Reject => slots.try_acquire_owned().map_err(|e| match e {
+                TryAcquireError::Closed => TransactionError::Shutdown,
+                TryAcquireError::NoPermits => TransactionError::TooManyOutstanding,
+            })?,
+        };
         let (tx, rx) = oneshot::channel();
-        self.sink
-            .send(Request::new(destination, method, attributes, tx))
-            .await?;
-        let response = rx.await.map_err(|_e| TransactionError::Timeout)??;
-        Ok(response)
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let queued_for = submitted_at.elapsed();
+        let mut request = Request::new(id, destination, method, attributes, tx, queued_for, slot);
+        if let Some(key) = options.integrity_key {
+            request = request.with_integrity(key);
+        }
+        if let Some(fingerprint) = options.fingerprint {
+            request = request.with_fingerprint(fingerprint);
+        }
+        if let Some(deadline) = options.deadline {
+            request = request.with_deadline(deadline);
+        }
+        if let Some(timeout) = options.timeout {
+            request = request.with_timeout(timeout);
+        }
+        if let Some(progress) = options.progress {
+            request = request.with_progress(progress);
+        }
+        if self.sink.send(Command::Request(request)).await.is_err() {
+            return Err(self.closed_error());
+        }
+        Ok(PendingResponse {
+            destination,
+            receiver: rx,
+            cancel_handle: CancelHandle {
+                sink: self.sink.clone(),
+                id,
+            },
+        })
+    }
+
+    /// Fail all outstanding transactions with `TransactionError::Shutdown` and stop the processor.
+    /// Any request submitted once this has been called fails immediately with the same error.
+    /// The transport (UDP driver or connection pool) stops as well once the processor has exited.
+    pub async fn shutdown(&self) {
+        // closing synchronously ensures no request can slip in after the shutdown command
+        self.request_slots.close();
+        let _ = self.sink.send(Command::Shutdown).await;
+    }
+
+    /// Fail all outstanding transactions with `TransactionError::Cancelled`. Requests submitted
+    /// after this call are not affected.
+    pub async fn cancel_all(&self) -> Result<(), TransactionError> {
+        if self.sink.send(Command::CancelAll).await.is_err() {
+            return Err(self.closed_error());
+        }
+        Ok(())
+    }
+
+    /// Temporarily stop sending requests, e.g. during congestion. New requests are queued and
+    /// retransmissions are suspended, but responses to outstanding requests are still handled.
+    /// Timeouts are extended by the duration of the pause.
+    pub async fn pause(&self) -> Result<(), TransactionError> {
+        if self.sink.send(Command::Pause).await.is_err() {
+            return Err(self.closed_error());
+        }
+        Ok(())
+    }
+
+    /// Undo `pause()`: send the queued requests and resume retransmissions where they left off.
+    pub async fn resume(&self) -> Result<(), TransactionError> {
+        if self.sink.send(Command::Resume).await.is_err() {
+            return Err(self.closed_error());
+        }
+        Ok(())
+    }
+
+    /// Snapshot of all transactions currently awaiting a response. Empty after shutdown.
+    pub async fn outstanding_transactions(&self) -> Result<Vec<TransactionInfo>, TransactionError> {
+        if self.request_slots.is_closed() {
+            return Ok(Vec::new());
+        }
+        let (tx, rx) = oneshot::channel();
+        if self.sink.send(Command::ListOutstanding(tx)).await.is_err() {
+            return Err(self.closed_error());
+        }
+        rx.await.map_err(|_e| self.closed_error())
+    }
+
+    /// Send a request that isn't expected to be answered, e.g. with a custom method that the
+    /// server may silently ignore. Completes as soon as the request has been handed over to the
+    /// transport, with no retransmissions or timeout, and any response is discarded. Unlike an
+    /// indication, the message has request class. Not held back by `pause()`.
+    pub async fn send_request_without_response(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> Result<(), TransactionError> {
+        if !self.confirms_delivery {
+            return Err(TransactionError::DeliveryConfirmationUnsupported);
+        }
+        let (tx, rx) = oneshot::channel();
+        let command = Command::SendUnanswered {
+            destination,
+            method,
+            attributes,
+            result_sink: tx,
+        };
+        if self.sink.send(command).await.is_err() {
+            return Err(self.closed_error());
+        }
+        rx.await.map_err(|_e| self.closed_error())?
+    }
+
+    /// Dry run: encode a request exactly as `send_request()` would put it on the wire, including
+    /// a fresh transaction id, without sending it or starting a transaction. Useful for
+    /// golden-testing attribute construction.
+    pub async fn serialize_request(
+        &self,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let (tx, rx) = oneshot::channel();
+        let command = Command::Serialize {
+            method,
+            attributes,
+            result_sink: tx,
+        };
+        if self.sink.send(command).await.is_err() {
+            return Err(self.closed_error());
+        }
+        rx.await.map_err(|_e| self.closed_error())
+    }
+
+    fn closed_error(&self) -> TransactionError {
+        if self.request_slots.is_closed() {
+            TransactionError::Shutdown
+        } else {
+            TransactionError::ChannelClosed
+        }
+    }
+
+    /// Create an empty batch for submitting many requests and collecting all responses at once.
+    pub fn batch(&self) -> RequestBatch {
+        RequestBatch {
+            sender: self.clone(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Create a handle for a request that can be re-issued with the same attributes after a timeout.
+    pub fn retryable_request(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> RetryableRequest {
+        RetryableRequest {
+            sender: self.clone(),
+            destination,
+            method,
+            attributes,
+            in_flight: None,
+        }
+    }
+
+    /// Same as `send_request()`, to the addresses of `host` ("hostname:port") looked up in
+    /// `cache`, trying the next one on timeout. Requests issued after the cached addresses have
+    /// expired go to the freshly resolved ones.
+    pub async fn send_request_to_host<R: Resolver>(
+        &self,
+        cache: &AddressCache<R>,
+        host: &str,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> Result<Response, TransactionError> {
+        let addrs = cache.lookup(host).await?;
+        self.send_request_to_addrs(addrs, method, attributes).await
     }
 
     async fn send_request_to_addrs(
@@ -70,6 +549,127 @@ impl RequestSender {
     }
 }
 
+pub struct PendingResponse {
+    destination: SocketAddr,
+    receiver: oneshot::Receiver<Result<Response, TransactionError>>,
+    cancel_handle: CancelHandle,
+}
+
+impl PendingResponse {
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// Handle for aborting the transaction from elsewhere while this is being awaited.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+}
+
+/// Aborts a single request submitted with `RequestSender::submit_request()`.
+#[derive(Clone)]
+pub struct CancelHandle {
+    sink: mpsc::Sender<Command>,
+    id: RequestId,
+}
+
+impl CancelHandle {
+    /// Stop retransmitting the request and fail it with `TransactionError::Cancelled`. Does
+    /// nothing if the transaction has already completed, e.g. a response arrived first.
+    pub async fn cancel(&self) -> Result<(), TransactionError> {
+        self.sink
+            .send(Command::Cancel(self.id))
+            .await
+            .map_err(|_| TransactionError::ChannelClosed)
+    }
+}
+
+impl Future for PendingResponse {
+    type Output = Result<Response, TransactionError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| result.unwrap_or(Err(TransactionError::Timeout)))
+    }
+}
+
+pub struct RequestBatch {
+    sender: RequestSender,
+    pending: Vec<PendingResponse>,
+}
+
+impl RequestBatch {
+    /// Submit a request as part of this batch. Waits only if the max number of outstanding
+    /// requests has been reached, not for the response.
+    pub async fn submit(
+        &mut self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> Result<(), TransactionError> {
+        let pending = self
+            .sender
+            .submit_request(destination, method, attributes)
+            .await?;
+        self.pending.push(pending);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Wait for all submitted transactions to complete, returning results in submission order.
+    pub async fn collect(self) -> Vec<(SocketAddr, Result<Response, TransactionError>)> {
+        let mut results = Vec::with_capacity(self.pending.len());
+        for pending in self.pending {
+            let destination = pending.destination();
+            results.push((destination, pending.await));
+        }
+        results
+    }
+}
+
+pub struct RetryableRequest {
+    sender: RequestSender,
+    destination: SocketAddr,
+    method: u16,
+    attributes: Vec<Tlv>,
+    in_flight: Option<PendingResponse>,
+}
+
+impl RetryableRequest {
+    /// Wait for the current transaction, starting one if none is in flight. Cancellation-safe:
+    /// if the returned future is dropped, the next call resumes waiting for the same transaction.
+    pub async fn send(&mut self) -> Result<Response, TransactionError> {
+        if self.in_flight.is_none() {
+            let pending = self
+                .sender
+                .submit_request(self.destination, self.method, self.attributes.clone())
+                .await?;
+            self.in_flight = Some(pending);
+        }
+        let result = self.in_flight.as_mut().unwrap().await;
+        self.in_flight = None;
+        result
+    }
+
+    /// Re-issue the request with a fresh transaction id. A transaction that is still in flight is
+    /// cancelled first, so that it neither gets retransmitted any further nor holds on to its
+    /// slot in `Config::max_outstanding_requests`.
+    pub async fn retry(&mut self) -> Result<Response, TransactionError> {
+        if let Some(in_flight) = self.in_flight.take() {
+            in_flight.cancel_handle.cancel().await?;
+        }
+        self.send().await
+    }
+}
+
 pub struct CompositeRequestSender {
     pub udp: RequestSender,
     pub tcp: RequestSender,
@@ -77,6 +677,7 @@ pub struct CompositeRequestSender {
 }
 
 impl CompositeRequestSender {
+    /// If the response is truncated, the request is resent over TCP to the same server.
     pub async fn udp_request<U: AsRef<str>>(
         &self,
         stun_uri: U,
@@ -84,9 +685,21 @@ impl CompositeRequestSender {
         attributes: Vec<Tlv>,
     ) -> Result<Response, TransactionError> {
         let addrs = dns::resolve_uri(stun_uri).await?;
-        self.udp
-            .send_request_to_addrs(addrs, method, attributes)
-            .await
+        for addr in addrs {
+            match self
+                .udp
+                .send_request(addr, method, attributes.clone())
+                .await
+            {
+                Err(TransactionError::Timeout) => continue,
+                Err(TransactionError::Truncated) => {
+                    log::debug!("Truncated response from {addr}, retrying over TCP");
+                    return self.tcp.send_request(addr, method, attributes).await;
+                }
+                result => return result,
+            }
+        }
+        Err(TransactionError::Timeout)
     }
 
     pub async fn tcp_request<U: AsRef<str>>(
@@ -110,33 +723,192 @@ impl CompositeRequestSender {
     }
 }
 
+fn is_server_error(response: &Response) -> bool {
+    !response.success
+        && response
+            .attributes
+            .clone()
+            .extract_attribute::<ErrorCode>()
+            .is_ok_and(|error_code| (500..600).contains(&error_code.code))
+}
+
 #[derive(Clone)]
 pub struct IndicationSender {
-    sink: mpsc::Sender<Indication>,
+    sink: mpsc::Sender<OutgoingIndication>,
+    max_size: Option<usize>,
+    append_fingerprint: bool,
+    confirms_delivery: bool,
 }
 
+/// How long `send_indication_confirmed()` waits for the transport to report delivery.
+pub const DELIVERY_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(39);
+
 impl IndicationSender {
-    pub(super) fn new(sink: mpsc::Sender<Indication>) -> IndicationSender {
-        IndicationSender { sink }
+    pub(super) fn new(
+        sink: mpsc::Sender<OutgoingIndication>,
+        max_size: Option<usize>,
+        append_fingerprint: bool,
+        confirms_delivery: bool,
+    ) -> IndicationSender {
+        IndicationSender {
+            sink,
+            max_size,
+            append_fingerprint,
+            confirms_delivery,
+        }
     }
 
+    /// Best-effort send, returns as soon as the indication has been handed to the processor.
     pub async fn send_indication(
         &self,
         destination: SocketAddr,
         method: u16,
         attributes: Vec<Tlv>,
     ) -> Result<(), TransactionError> {
+        self.submit(destination, method, attributes, None, None)
+            .await
+    }
+
+    /// Same as `send_indication()`, but the indication is dropped instead of being sent late if
+    /// the transport can't accept it by `deadline`, e.g. because of back-pressure. Dropped
+    /// indications are counted in `Metrics::dropped_indications()`.
+    pub async fn send_indication_until(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        deadline: Instant,
+    ) -> Result<(), TransactionError> {
+        self.submit(destination, method, attributes, None, Some(deadline))
+            .await
+    }
+
+    /// Same as `send_indication()`, for an already constructed indication such as
+    /// `Indication::send()`.
+    pub async fn send(&self, indication: Indication) -> Result<(), TransactionError> {
+        let Indication {
+            farend_addr,
+            method,
+            attributes,
+        } = indication;
+        self.submit(farend_addr, method, attributes, None, None)
+            .await
+    }
+
+    /// Send an indication and wait until it has been written to the socket. Only connection
+    /// oriented transports (TCP, TLS) confirm delivery, without
+    /// `Config::transport_confirms_delivery` this fails immediately with
+    /// `TransactionError::DeliveryConfirmationUnsupported` and nothing is sent.
+    pub async fn send_indication_confirmed(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+    ) -> Result<(), TransactionError> {
+        let (tx, rx) = oneshot::channel();
+        self.submit(destination, method, attributes, Some(tx), None)
+            .await?;
+        match time::timeout(DELIVERY_CONFIRMATION_TIMEOUT, rx).await {
+            Ok(result) => result.unwrap_or(Err(TransactionError::ChannelClosed)),
+            Err(_elapsed) => Err(TransactionError::Timeout),
+        }
+    }
+
+    async fn submit(
+        &self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        delivery_sink: Option<oneshot::Sender<Result<(), TransactionError>>>,
+        deadline: Option<Instant>,
+    ) -> Result<(), TransactionError> {
+        if let Some(limit) = self.max_size {
+            let mut size = Header::SIZE + attributes.iter().map(Tlv::encoded_size).sum::<usize>();
+            if self.append_fingerprint {
+                size += Tlv::HEADER_SIZE + 4;
+            }
+            if size > limit {
+                return Err(TransactionError::PayloadTooLarge { size, limit });
+            }
+        }
         self.sink
-            .send(Indication {
-                farend_addr: destination,
-                method,
-                attributes,
+            .send(OutgoingIndication {
+                indication: Indication {
+                    farend_addr: destination,
+                    method,
+                    attributes,
+                },
+                delivery_sink,
+                deadline,
             })
             .await?;
         Ok(())
     }
 }
 
+/// Request received from a remote peer, see `Processor::receive_requests()`. XOR-ed addresses
+/// have already been decoded.
+#[derive(Debug)]
+pub struct IncomingRequest {
+    pub source_addr: SocketAddr,
+    #[debug("{method:#06x}")]
+    pub method: u16,
+    pub transaction_id: [u8; 12],
+    pub attributes: Vec<Tlv>,
+    #[debug(skip)]
+    pub(super) reply_sink: mpsc::Sender<Reply>,
+}
+
+impl IncomingRequest {
+    /// Send a success response with `attributes` back to `source_addr`.
+    pub async fn respond(self, attributes: Vec<Tlv>) -> Result<(), TransactionError> {
+        self.reply(true, attributes).await
+    }
+
+    /// Send an error response with `attributes` (normally including ERROR-CODE) back to
+    /// `source_addr`.
+    pub async fn respond_error(self, attributes: Vec<Tlv>) -> Result<(), TransactionError> {
+        self.reply(false, attributes).await
+    }
+
+    async fn reply(self, success: bool, attributes: Vec<Tlv>) -> Result<(), TransactionError> {
+        let reply = Reply {
+            destination: self.source_addr,
+            method: self.method,
+            transaction_id: self.transaction_id,
+            success,
+            attributes,
+        };
+        self.reply_sink.send(reply).await?;
+        Ok(())
+    }
+}
+
+pub struct IncomingRequestReceiver {
+    source: mpsc::Receiver<IncomingRequest>,
+}
+
+impl IncomingRequestReceiver {
+    pub(super) fn new(source: mpsc::Receiver<IncomingRequest>) -> IncomingRequestReceiver {
+        IncomingRequestReceiver { source }
+    }
+
+    pub async fn receive_next(&mut self) -> Result<IncomingRequest, TransactionError> {
+        self.source
+            .recv()
+            .await
+            .ok_or(TransactionError::ChannelClosed)
+    }
+}
+
+impl futures_util::Stream for IncomingRequestReceiver {
+    type Item = IncomingRequest;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.source.poll_recv(cx)
+    }
+}
+
 pub struct IndicationReceiver {
     source: mpsc::Receiver<Indication>,
 }
@@ -1,24 +1,58 @@
-use manager::{Manager, Request};
+use clock::Clock;
+use manager::{
+    same_address, Command, Manager, OutgoingIndication, ProgressCallback, Reply, Request, RequestId,
+};
 use std::future::pending;
 use std::net::SocketAddr;
+use std::time::Duration;
 use stunny_core::message::*;
-use stunny_core::transport::MessageChannels;
+use stunny_core::transport::{MessageChannels, TransportEvent};
 use tokio::select;
 use tokio::sync::mpsc;
-use tokio::time::{sleep_until, Instant};
+use tokio::time::Instant;
 
+mod auth;
+#[cfg(feature = "binary-log")]
+mod binlog;
+mod buffer;
+mod clock;
+mod config;
+mod discovery;
 mod dns;
 mod error;
+#[cfg(feature = "ice")]
+mod ice;
 mod interface;
 mod manager;
+mod metrics;
+mod observer;
+mod rng;
 mod rto;
+mod uri;
+mod validator;
 
 #[cfg(test)]
 mod tests;
 
+pub use auth::*;
+#[cfg(feature = "binary-log")]
+pub use binlog::*;
+pub use buffer::*;
+#[cfg(any(test, feature = "test-util"))]
+pub use clock::ManualClock;
+pub use config::*;
+pub use discovery::*;
+pub use dns::{AddressCache, Resolution, Resolver, SystemResolver};
 pub use error::*;
+#[cfg(feature = "ice")]
+pub use ice::*;
 pub use interface::*;
+pub use metrics::*;
+pub use observer::*;
+pub use rng::*;
 pub use rto::*;
+pub use uri::*;
+pub use validator::*;
 
 // re-export core
 pub use stunny_core::*;
@@ -33,64 +67,234 @@ pub fn setup_transactions<P: RtoPolicy>(
     IndicationReceiver,
     Processor<P>,
 ) {
+    setup_transactions_with_config(
+        message_channels,
+        Config {
+            max_outstanding_requests,
+            ..Default::default()
+        },
+        rto_policy,
+    )
+}
+
+pub fn setup_transactions_with_config<P: RtoPolicy>(
+    message_channels: MessageChannels,
+    config: Config,
+    rto_policy: P,
+) -> (
+    RequestSender,
+    IndicationSender,
+    IndicationReceiver,
+    Processor<P>,
+) {
+    try_setup_transactions_with_rng(
+        message_channels,
+        config,
+        rto_policy,
+        TransactionIdRng::default(),
+    )
+    .expect("default RNG is cryptographic")
+}
+
+/// Like `setup_transactions_with_config()`, but with a custom source of transaction ids. Fails if
+/// `Config::require_cryptographic_rng` is set and `rng` is not cryptographic.
+pub fn try_setup_transactions_with_rng<P: RtoPolicy>(
+    message_channels: MessageChannels,
+    config: Config,
+    rto_policy: P,
+    rng: TransactionIdRng,
+) -> Result<
+    (
+        RequestSender,
+        IndicationSender,
+        IndicationReceiver,
+        Processor<P>,
+    ),
+    SetupError,
+> {
+    if config.require_cryptographic_rng && !rng.is_cryptographic() {
+        return Err(SetupError::InsecureRng);
+    }
     let (inbound_ind_sink, inbound_ind_source) = mpsc::channel(1);
     let (outbound_ind_sink, outbound_ind_source) = mpsc::channel(1);
-    let (outbound_req_sink, outbound_req_source) = mpsc::channel(1);
+    let (command_sink, command_source) = mpsc::channel(1);
+    let (reply_sink, reply_source) = mpsc::channel(1);
 
-    let manager = Manager::new(rto_policy, message_channels.egress_sink, inbound_ind_sink);
-    (
-        RequestSender::new(outbound_req_sink, max_outstanding_requests),
-        IndicationSender::new(outbound_ind_sink),
+    let max_outstanding_requests = config.max_outstanding_requests;
+    let backpressure = config.backpressure;
+    let max_indication_size = config.max_indication_size;
+    let append_fingerprint = config.append_fingerprint;
+    let confirms_delivery = config.transport_confirms_delivery;
+    let server_error_retry = config.server_error_retry.clone();
+    let rate_limit = config.rate_limit.clone();
+    let ingress_batch_size = config.ingress_batch_size.max(1);
+    let manager = Manager::new(
+        config,
+        rto_policy,
+        message_channels.egress_sink,
+        inbound_ind_sink,
+        reply_sink,
+        rng.into_inner(),
+    );
+    Ok((
+        RequestSender::new(
+            command_sink,
+            max_outstanding_requests,
+            backpressure,
+            server_error_retry,
+            rate_limit,
+        ),
+        IndicationSender::new(
+            outbound_ind_sink,
+            max_indication_size,
+            append_fingerprint,
+            confirms_delivery,
+        ),
         IndicationReceiver::new(inbound_ind_source),
         Processor {
             manager,
             ingress_source: message_channels.ingress_source,
-            outbound_req_source,
+            event_source: message_channels.event_source,
+            command_source,
             outbound_ind_source,
+            reply_source,
+            ingress_batch_size,
+            #[cfg(test)]
+            iterations: Default::default(),
         },
-    )
+    ))
 }
 
 pub struct Processor<P> {
     manager: Manager<P>,
     ingress_source: mpsc::Receiver<(Message, SocketAddr)>,
-    outbound_req_source: mpsc::Receiver<Request>,
-    outbound_ind_source: mpsc::Receiver<Indication>,
+    event_source: mpsc::Receiver<TransportEvent>,
+    command_source: mpsc::Receiver<Command>,
+    outbound_ind_source: mpsc::Receiver<OutgoingIndication>,
+    reply_source: mpsc::Receiver<Reply>,
+    ingress_batch_size: usize,
+    #[cfg(test)]
+    iterations: std::rc::Rc<std::cell::Cell<usize>>,
 }
 
 impl<P: RtoPolicy> Processor<P> {
+    pub fn metrics(&self) -> Metrics {
+        self.manager.metrics().clone()
+    }
+
+    /// Replace the runtime timer with a clock that only moves when told to. Must be called
+    /// before the processor is started.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn use_manual_clock(&mut self) -> ManualClock {
+        let clock = ManualClock::new();
+        self.manager.set_clock(Clock::Manual(clock.clone()));
+        clock
+    }
+
+    /// Use `buffer_pool` for the copies of outgoing requests kept for retransmissions.
+    pub fn set_buffer_pool(&mut self, buffer_pool: impl BufferPool + 'static) {
+        self.manager.set_buffer_pool(Box::new(buffer_pool));
+    }
+
+    /// Keep the NAT mapping towards `server` open by sending it a Binding request every
+    /// `interval`, starting as soon as the processor runs. Responses are discarded, except that
+    /// changes of the mapped address are reported to `TransactionObserver::on_mapping_changed()`.
+    pub fn add_keepalive(&mut self, server: SocketAddr, interval: Duration) {
+        self.manager.add_keepalive(server, interval);
+    }
+
+    /// Replace `DefaultResponseValidator` with custom rules for accepting responses.
+    pub fn set_response_validator(&mut self, validator: impl ResponseValidator + 'static) {
+        self.manager.set_response_validator(Box::new(validator));
+    }
+
+    /// Deliver incoming requests to the returned receiver instead of dropping them, so that they
+    /// can be answered with `IncomingRequest::respond()`. Must be called before the processor is
+    /// started.
+    pub fn receive_requests(&mut self) -> IncomingRequestReceiver {
+        let (sink, source) = mpsc::channel(1);
+        self.manager.set_incoming_requests_sink(sink);
+        IncomingRequestReceiver::new(source)
+    }
+
+    pub fn set_observer(&mut self, observer: impl TransactionObserver + 'static) {
+        self.manager.set_observer(Box::new(observer));
+    }
+
     pub async fn run(mut self) -> Result<(), TransactionError> {
         loop {
+            #[cfg(test)]
+            self.iterations.set(self.iterations.get() + 1);
             let next_timeout = self.manager.next_timeout();
+            let clock = self.manager.clock().clone();
             select! {
                 biased;
+                // before ingress, so that events emitted right before the transport exits are
+                // not lost
+                Some(event) = self.event_source.recv() => {
+                    self.manager.handle_transport_event(event);
+                }
                 inbound = self.ingress_source.recv() => {
-                    let msg_and_src = inbound.ok_or(TransactionError::ChannelClosed)?;
+                    let Some(msg_and_src) = inbound else {
+                        self.manager.fail_all(|| TransactionError::ChannelClosed);
+                        return Err(TransactionError::ChannelClosed);
+                    };
                     self.manager.handle_incoming_message(msg_and_src).await?;
+                    for _ in 1..self.ingress_batch_size {
+                        let Ok(msg_and_src) = self.ingress_source.try_recv() else {
+                            break;
+                        };
+                        self.manager.handle_incoming_message(msg_and_src).await?;
+                    }
                 }
-                Some(request) = self.outbound_req_source.recv() => {
-                    self.manager.handle_outgoing_request(request).await?;
+                Some(command) = self.command_source.recv() => match command {
+                    Command::Request(request) => {
+                        self.manager.handle_submitted_request(request).await?;
+                    }
+                    Command::ListOutstanding(result_sink) => {
+                        let _ = result_sink.send(self.manager.outstanding_transactions());
+                    }
+                    Command::Serialize { method, attributes, result_sink } => {
+                        let _ = result_sink.send(self.manager.serialize_request(method, attributes));
+                    }
+                    Command::SendUnanswered { destination, method, attributes, result_sink } => {
+                        self.manager
+                            .handle_unanswered_request(destination, method, attributes, result_sink)
+                            .await?;
+                    }
+                    Command::Cancel(id) => {
+                        self.manager.cancel(id);
+                    }
+                    Command::CancelAll => {
+                        self.manager.fail_all(|| TransactionError::Cancelled);
+                    }
+                    Command::Pause => {
+                        self.manager.pause();
+                    }
+                    Command::Resume => {
+                        self.manager.resume().await?;
+                    }
+                    Command::Shutdown => {
+                        self.manager.fail_all(|| TransactionError::Shutdown);
+                        return Ok(());
+                    }
+                },
+                Some(reply) = self.reply_source.recv() => {
+                    self.manager.handle_reply(reply).await?;
                 }
                 Some(indication) = self.outbound_ind_source.recv() => {
                     self.manager.handle_outgoing_indication(indication).await?;
                 }
-                _ = Self::sleep_until(next_timeout), if next_timeout.is_some() => {
+                _ = Self::sleep_until(clock, next_timeout), if next_timeout.is_some() => {
                     self.manager.handle_timeouts().await?;
                 }
             }
         }
     }
 
-    async fn sleep_until(deadline: Option<Instant>) {
-        #[cfg(not(test))]
-        match deadline {
-            Some(deadline) => sleep_until(deadline).await,
-            _ => pending::<()>().await,
-        }
-
-        #[cfg(test)]
+    async fn sleep_until(clock: Clock, deadline: Option<Instant>) {
         match deadline {
-            Some(deadline) if tests::SLEEP_ENABLED.get() => sleep_until(deadline).await,
+            Some(deadline) => clock.sleep_until(deadline).await,
             _ => pending::<()>().await,
         }
     }
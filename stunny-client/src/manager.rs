@@ -1,84 +1,411 @@
 use super::*;
-use rand::Rng;
+use rand::{Rng, RngCore};
 use std::cmp::Ordering;
 use std::collections::binary_heap::PeekMut;
 use std::collections::hash_map::Entry;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 use std::mem;
 use std::net::SocketAddr;
 use std::time::Duration;
-use stunny_core::attributes::{Attribute, XorMappedAddress};
-use tokio::sync::{mpsc, oneshot};
+use stunny_core::attributes::{
+    Attribute, Fingerprint, MessageIntegrity, Software, XorMappedAddress, XorPeerAddress,
+};
+use tokio::select;
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit};
 use tokio::time::Instant;
 
 pub(super) struct Request {
+    id: Option<RequestId>,
     destination_addr: SocketAddr,
     method: u16,
     attributes: Vec<Tlv>,
     response_sink: oneshot::Sender<Result<Response, TransactionError>>,
     attempts_made: usize,
+    history: Vec<Attempt>,
+    /// Time spent in `RequestSender` before reaching the manager, e.g. waiting for a slot.
+    queued_for: Duration,
+    /// Derived from `queued_for` with the manager's clock, see `on_submitted()`.
+    enqueued_at: Instant,
     start_time: Instant,
+    initial_rto: Duration,
+    integrity_key: Option<Vec<u8>>,
+    fingerprint: Option<bool>,
+    /// Relative to `enqueued_at`, see `with_timeout()`.
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    progress: Option<ProgressCallback>,
+    keepalive: bool,
+    // released when the transaction completes, even if nobody is awaiting the response
+    _slot: Option<OwnedSemaphorePermit>,
 }
 
 impl Request {
     pub(super) fn new(
+        id: RequestId,
         destination_addr: SocketAddr,
         method: u16,
         attributes: Vec<Tlv>,
         response_sink: oneshot::Sender<Result<Response, TransactionError>>,
+        queued_for: Duration,
+        slot: OwnedSemaphorePermit,
     ) -> Self {
+        let now = Instant::now();
         Self {
+            id: Some(id),
             destination_addr,
             method,
             attributes,
             response_sink,
             attempts_made: 0,
-            start_time: Instant::now(),
+            history: Vec::new(),
+            queued_for,
+            enqueued_at: now,
+            start_time: now,
+            initial_rto: DEFAULT_RTO,
+            integrity_key: None,
+            fingerprint: None,
+            timeout: None,
+            deadline: None,
+            progress: None,
+            keepalive: false,
+            _slot: Some(slot),
         }
     }
+
+    /// Binding request sent by the processor itself to keep a NAT mapping open. Doesn't take up
+    /// a slot.
+    fn keepalive(
+        destination_addr: SocketAddr,
+        response_sink: oneshot::Sender<Result<Response, TransactionError>>,
+        now: Instant,
+    ) -> Self {
+        Self {
+            id: None,
+            destination_addr,
+            method: BINDING_METHOD,
+            attributes: Vec::new(),
+            response_sink,
+            attempts_made: 0,
+            history: Vec::new(),
+            queued_for: Duration::ZERO,
+            enqueued_at: now,
+            start_time: now,
+            initial_rto: DEFAULT_RTO,
+            integrity_key: None,
+            fingerprint: None,
+            timeout: None,
+            deadline: None,
+            progress: None,
+            keepalive: true,
+            _slot: None,
+        }
+    }
+
+    /// Sign the request with MESSAGE-INTEGRITY and FINGERPRINT when sending.
+    pub(super) fn with_integrity(mut self, key: Vec<u8>) -> Self {
+        self.integrity_key = Some(key);
+        self
+    }
+
+    /// Append FINGERPRINT or not regardless of `Config::append_fingerprint` and of whether the
+    /// request is signed.
+    pub(super) fn with_fingerprint(mut self, fingerprint: bool) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// Fail the transaction with `TransactionError::Timeout` at `deadline`, even if the RTO
+    /// policy would keep retransmitting.
+    pub(super) fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Same as `with_deadline()`, but `timeout` after the request was submitted as measured by
+    /// the manager's clock.
+    pub(super) fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Invoke `progress` with the attempt number on every retransmission.
+    pub(super) fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Anchor the times the request was submitted with to `now`, when the manager received it.
+    fn on_submitted(&mut self, now: Instant) {
+        self.enqueued_at = now.checked_sub(self.queued_for).unwrap_or(now);
+        self.start_time = now;
+        if let Some(timeout) = self.timeout {
+            let deadline = self.enqueued_at + timeout;
+            self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+        }
+    }
+
+    fn deadline_reached(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+
+    /// When the next timeout should fire, given that the current RTO expires at `rto_expiry`.
+    fn next_timeout_at(&self, rto_expiry: Instant) -> Instant {
+        self.deadline
+            .map_or(rto_expiry, |deadline| rto_expiry.min(deadline))
+    }
+}
+
+pub(super) type ProgressCallback = Box<dyn FnMut(usize) + Send>;
+
+/// Identifies a request submitted by `RequestSender` before it's been assigned a transaction id.
+pub(super) type RequestId = u64;
+
+pub(super) struct OutgoingIndication {
+    pub(super) indication: Indication,
+    pub(super) delivery_sink: Option<DeliverySink>,
+    pub(super) deadline: Option<Instant>,
+}
+
+/// Response to an `IncomingRequest`, encoded by the manager.
+pub(super) struct Reply {
+    pub(super) destination: SocketAddr,
+    pub(super) method: u16,
+    pub(super) transaction_id: TransactionId,
+    pub(super) success: bool,
+    pub(super) attributes: Vec<Tlv>,
+}
+
+pub(super) enum Command {
+    Request(Request),
+    ListOutstanding(oneshot::Sender<Vec<TransactionInfo>>),
+    Serialize {
+        method: u16,
+        attributes: Vec<Tlv>,
+        result_sink: oneshot::Sender<Vec<u8>>,
+    },
+    SendUnanswered {
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        result_sink: DeliverySink,
+    },
+    Cancel(RequestId),
+    CancelAll,
+    Pause,
+    Resume,
+    Shutdown,
 }
 
 type TransactionId = [u8; 12];
 
+/// Compare addresses treating IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) as their IPv4 form,
+/// as dual-stack sockets may report either.
+pub(super) fn same_address(lhs: SocketAddr, rhs: SocketAddr) -> bool {
+    lhs.ip().to_canonical() == rhs.ip().to_canonical() && lhs.port() == rhs.port()
+}
+
+type DeliverySink = oneshot::Sender<Result<(), TransactionError>>;
+
+struct Keepalive {
+    interval: Duration,
+    next_at: Instant,
+    mapped_address: Option<SocketAddr>,
+    // keeps the latest request from being abandoned, until it's replaced by the next one
+    _pending: Option<oneshot::Receiver<Result<Response, TransactionError>>>,
+}
+
 struct PendingTimeout {
     timeout_at: Instant,
     tid: TransactionId,
 }
 
 pub(super) struct Manager<P> {
+    config: Config,
     pending_timeouts: BinaryHeap<PendingTimeout>,
     outstanding_requests: HashMap<TransactionId, Request>,
+    pending_deliveries: HashMap<TransactionId, (SocketAddr, DeliverySink)>,
     egress_sink: mpsc::Sender<(Message, SocketAddr)>,
     incoming_indications_sink: mpsc::Sender<Indication>,
+    incoming_requests_sink: Option<mpsc::Sender<IncomingRequest>>,
+    reply_sink: mpsc::Sender<Reply>,
     rto_policy: P,
-    rand_gen: rand::rngs::ThreadRng,
+    rand_gen: Box<dyn RngCore>,
+    observer: Option<Box<dyn TransactionObserver>>,
+    metrics: Metrics,
+    clock: Clock,
+    buffer_pool: Box<dyn BufferPool>,
+    validator: Box<dyn ResponseValidator>,
+    paused_at: Option<Instant>,
+    deferred_requests: VecDeque<Request>,
+    throttled_requests: VecDeque<Request>,
+    next_request_at: Option<Instant>,
+    keepalives: HashMap<SocketAddr, Keepalive>,
 }
 
 impl<P: RtoPolicy> Manager<P> {
     pub(super) fn new(
+        config: Config,
         rto_policy: P,
         egress_sink: mpsc::Sender<(Message, SocketAddr)>,
         incoming_indications_sink: mpsc::Sender<Indication>,
+        reply_sink: mpsc::Sender<Reply>,
+        rand_gen: Box<dyn RngCore>,
     ) -> Self {
         Self {
+            config,
             pending_timeouts: Default::default(),
             outstanding_requests: Default::default(),
+            pending_deliveries: Default::default(),
             egress_sink,
             incoming_indications_sink,
+            incoming_requests_sink: None,
+            reply_sink,
             rto_policy,
-            rand_gen: rand::thread_rng(),
+            rand_gen,
+            observer: None,
+            metrics: Default::default(),
+            clock: Default::default(),
+            buffer_pool: Box::new(HeapAllocator),
+            validator: Box::new(DefaultResponseValidator),
+            paused_at: None,
+            deferred_requests: Default::default(),
+            throttled_requests: Default::default(),
+            next_request_at: None,
+            keepalives: Default::default(),
         }
     }
 
+    pub(super) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub(super) fn set_observer(&mut self, observer: Box<dyn TransactionObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub(super) fn set_buffer_pool(&mut self, buffer_pool: Box<dyn BufferPool>) {
+        self.buffer_pool = buffer_pool;
+    }
+
+    pub(super) fn set_incoming_requests_sink(&mut self, sink: mpsc::Sender<IncomingRequest>) {
+        self.incoming_requests_sink = Some(sink);
+    }
+
+    pub(super) fn set_response_validator(&mut self, validator: Box<dyn ResponseValidator>) {
+        self.validator = validator;
+    }
+
+    pub(super) fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    #[cfg(any(test, feature = "test-util"))]
+    pub(super) fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
     pub(super) fn next_timeout(&self) -> Option<Instant> {
-        self.pending_timeouts.peek().map(|pt| pt.timeout_at)
+        if self.paused_at.is_some() {
+            return None;
+        }
+        let next_keepalive = self.keepalives.values().map(|ka| ka.next_at).min();
+        let next_retransmission = self.pending_timeouts.peek().map(|pt| pt.timeout_at);
+        let next_throttled = self
+            .next_request_at
+            .filter(|_| !self.throttled_requests.is_empty());
+        next_keepalive
+            .into_iter()
+            .chain(next_retransmission)
+            .chain(next_throttled)
+            .min()
+    }
+
+    /// Send the first keepalive right away, and then every `interval`.
+    pub(super) fn add_keepalive(&mut self, server: SocketAddr, interval: Duration) {
+        self.keepalives.insert(
+            server,
+            Keepalive {
+                interval,
+                next_at: self.clock.now(),
+                mapped_address: None,
+                _pending: None,
+            },
+        );
+    }
+
+    async fn send_due_keepalives(&mut self) -> Result<(), TransactionError> {
+        let now = self.clock.now();
+        let due_servers: Vec<SocketAddr> = self
+            .keepalives
+            .iter()
+            .filter(|(_, keepalive)| keepalive.next_at <= now)
+            .map(|(server, _)| *server)
+            .collect();
+        for server in due_servers {
+            let (tx, rx) = oneshot::channel();
+            if let Some(keepalive) = self.keepalives.get_mut(&server) {
+                keepalive.next_at = now + keepalive.interval;
+                // an unanswered previous keepalive is abandoned
+                keepalive._pending = Some(rx);
+            }
+            log::trace!("Sending keepalive to {server}");
+            self.handle_outgoing_request(Request::keepalive(server, tx, now))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn update_mapping(&mut self, server: SocketAddr, result: Result<Response, TransactionError>) {
+        let mapped_address = match result.and_then(BindingResult::from_response) {
+            Ok(binding) => binding.reflexive_address,
+            Err(e) => {
+                log::warn!("Keepalive to {server} failed: {e}");
+                return;
+            }
+        };
+        let Some(keepalive) = self.keepalives.get_mut(&server) else {
+            return;
+        };
+        if keepalive.mapped_address.replace(mapped_address) != Some(mapped_address) {
+            log::debug!("Mapped address reported by {server} is now {mapped_address}");
+            if let Some(observer) = &mut self.observer {
+                observer.on_mapping_changed(server, mapped_address);
+            }
+        }
+    }
+
+    /// Stop sending new requests and retransmissions until `resume()`. Responses are still handled.
+    pub(super) fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(self.clock.now());
+        }
+    }
+
+    /// Push all pending timeouts back by the time spent paused, and send the requests submitted
+    /// in the meantime.
+    pub(super) async fn resume(&mut self) -> Result<(), TransactionError> {
+        let Some(paused_at) = self.paused_at.take() else {
+            return Ok(());
+        };
+        let pause_duration = self.clock.now() - paused_at;
+        self.pending_timeouts = mem::take(&mut self.pending_timeouts)
+            .into_iter()
+            .map(|mut pt| {
+                pt.timeout_at += pause_duration;
+                pt
+            })
+            .collect();
+        while let Some(request) = self.deferred_requests.pop_front() {
+            self.handle_outgoing_request(request).await?;
+        }
+        Ok(())
     }
 
     pub(super) async fn handle_timeouts(&mut self) -> Result<(), TransactionError> {
         loop {
             // extract the earliest timeout, exit if it's in the future
             let mut timeout = match self.pending_timeouts.peek_mut() {
-                Some(timeout) if timeout.timeout_at <= Instant::now() => PeekMut::pop(timeout),
+                Some(timeout) if timeout.timeout_at <= self.clock.now() => PeekMut::pop(timeout),
                 _ => break,
             };
             // fetch the corresponding request entry
@@ -87,72 +414,294 @@ impl<P: RtoPolicy> Manager<P> {
                 Entry::Vacant(_) => unreachable!("no request for pending timeout"),
             };
             let request = outstanding.get();
-            match self
-                .rto_policy
-                .calculate_rto(request.destination_addr, request.attempts_made)
-            {
+            if request.response_sink.is_closed() {
+                // the caller gave up on this transaction, don't keep retransmitting
+                log::debug!("Abandoning request to {:?}", request.destination_addr);
+                release_attributes(&mut *self.buffer_pool, outstanding.remove().attributes);
+                continue;
+            }
+            let next_rto =
+                if request.deadline_reached(self.clock.now()) || !self.rto_policy.retransmits() {
+                    None
+                } else {
+                    self.rto_policy
+                        .calculate_rto(request.destination_addr, request.attempts_made)
+                };
+            match next_rto {
                 None => {
                     // erase entry and invoke callback with error
-                    let _ = outstanding
-                        .remove()
-                        .response_sink
-                        .send(Err(TransactionError::Timeout));
+                    let request = outstanding.remove();
+                    self.metrics.record_timeout();
+                    self.metrics.record_attempt_outcome(
+                        request.destination_addr,
+                        true,
+                        self.clock.now(),
+                    );
+                    log::warn!(
+                        "Request to {} timed out after {} attempt(s): {}",
+                        request.destination_addr,
+                        request.history.len(),
+                        format_history(&request.history)
+                    );
+                    if let Some(observer) = &mut self.observer {
+                        observer.on_timeout(
+                            request.destination_addr,
+                            &timeout.tid,
+                            &request.history,
+                        );
+                    }
+                    let _ = request.response_sink.send(Err(TransactionError::Timeout));
+                    release_attributes(&mut *self.buffer_pool, request.attributes);
                 }
                 Some(next_rto) => {
                     let request = outstanding.get_mut();
-                    // retransmit request
+                    if let Some(observer) = &mut self.observer {
+                        if self.rto_policy.is_final_retransmission(
+                            request.destination_addr,
+                            request.attempts_made,
+                        ) {
+                            observer
+                                .on_final_retransmission(request.destination_addr, &timeout.tid);
+                        }
+                    }
+                    // retransmit request, the copy handed to the transport never comes back so it
+                    // mustn't be taken from the pool
                     let msg =
                         Message::request(request.method, timeout.tid, request.attributes.clone());
                     log::trace!("Re-sending request to {:?}", request.destination_addr);
                     self.egress_sink
                         .send((msg, request.destination_addr))
                         .await?;
+                    self.metrics.record_retransmission();
+                    self.metrics.record_attempt_outcome(
+                        request.destination_addr,
+                        true,
+                        self.clock.now(),
+                    );
                     // schedule next timeout
                     request.attempts_made += 1;
-                    timeout.timeout_at = Instant::now() + next_rto;
+                    request.history.push(Attempt {
+                        sent_at: self.clock.now(),
+                        rto: next_rto,
+                    });
+                    if let Some(progress) = &mut request.progress {
+                        progress(request.attempts_made);
+                    }
+                    if let Some(observer) = &mut self.observer {
+                        observer.on_transmission(
+                            request.destination_addr,
+                            &timeout.tid,
+                            request.attempts_made,
+                        );
+                    }
+                    timeout.timeout_at = request.next_timeout_at(self.clock.now() + next_rto);
                     self.pending_timeouts.push(timeout);
                 }
             }
         }
+        self.send_throttled_requests().await?;
+        self.send_due_keepalives().await
+    }
+
+    /// Start as many queued requests as `Config::rate_limit` allows by now.
+    async fn send_throttled_requests(&mut self) -> Result<(), TransactionError> {
+        let Some(rate_limit) = &self.config.rate_limit else {
+            return Ok(());
+        };
+        let interval = rate_limit.interval();
+        let now = self.clock.now();
+        while !self.next_request_at.is_some_and(|at| at > now) {
+            let Some(request) = self.throttled_requests.pop_front() else {
+                break;
+            };
+            if request.response_sink.is_closed() {
+                continue;
+            }
+            if request.deadline_reached(now) {
+                let _ = request.response_sink.send(Err(TransactionError::Timeout));
+                continue;
+            }
+            self.next_request_at = Some(now + interval);
+            self.start_transaction(request).await?;
+        }
         Ok(())
     }
 
     pub(super) async fn handle_outgoing_indication(
         &mut self,
-        indication: Indication,
+        OutgoingIndication {
+            indication,
+            delivery_sink,
+            deadline,
+        }: OutgoingIndication,
     ) -> Result<(), TransactionError> {
+        if let Err(e) = check_reserved_attributes(
+            &indication.attributes,
+            false,
+            self.config.append_fingerprint,
+        ) {
+            log::warn!("Dropping indication to {}: {e}", indication.farend_addr);
+            if let Some(delivery_sink) = delivery_sink {
+                let _ = delivery_sink.send(Err(e));
+            }
+            return Ok(());
+        }
+        let permit = match deadline {
+            None => self.egress_sink.reserve().await?,
+            Some(deadline) => {
+                let clock = self.clock.clone();
+                let reserved = select! {
+                    biased;
+                    _ = clock.sleep_until(deadline) => None,
+                    permit = self.egress_sink.reserve() => Some(permit?),
+                };
+                let Some(permit) = reserved else {
+                    log::debug!(
+                        "Dropping indication to {}: egress blocked past deadline",
+                        indication.farend_addr
+                    );
+                    self.metrics.record_dropped_indication();
+                    if let Some(delivery_sink) = delivery_sink {
+                        let _ = delivery_sink.send(Err(TransactionError::Timeout));
+                    }
+                    return Ok(());
+                };
+                permit
+            }
+        };
         let tid = self.rand_gen.gen::<TransactionId>();
-        let msg = Message::indication(indication.method, tid, indication.attributes)
-            .xor_socket_addr(XorMappedAddress::ID);
+        let mut msg = Message::indication(indication.method, tid, indication.attributes)
+            .xor_socket_addr(XorMappedAddress::ID)
+            .xor_socket_addr(XorPeerAddress::ID);
+        if self.config.append_fingerprint {
+            msg.append_fingerprint();
+        }
+        if let Some(delivery_sink) = delivery_sink {
+            // forget about callers who gave up waiting
+            self.pending_deliveries
+                .retain(|_, (_, sink)| !sink.is_closed());
+            self.pending_deliveries
+                .insert(tid, (indication.farend_addr, delivery_sink));
+        }
         log::trace!("Sending indication to {:?}", indication.farend_addr);
-        self.egress_sink.send((msg, indication.farend_addr)).await?;
+        permit.send((msg, indication.farend_addr));
         Ok(())
     }
 
-    pub(super) async fn handle_outgoing_request(
+    /// Entry point for requests from `RequestSender`, as opposed to keepalives and requests that
+    /// have been held back.
+    pub(super) async fn handle_submitted_request(
         &mut self,
         mut request: Request,
     ) -> Result<(), TransactionError> {
-        let tid = self.rand_gen.gen::<TransactionId>();
-        let msg = Message::request(request.method, tid, mem::take(&mut request.attributes))
-            .xor_socket_addr(XorMappedAddress::ID);
-        request.attributes = msg.attributes.clone();
+        request.on_submitted(self.clock.now());
+        self.handle_outgoing_request(request).await
+    }
+
+    pub(super) async fn handle_outgoing_request(
+        &mut self,
+        request: Request,
+    ) -> Result<(), TransactionError> {
+        if let Err(e) = check_reserved_attributes(
+            &request.attributes,
+            request.integrity_key.is_some(),
+            self.appends_fingerprint(request.integrity_key.is_some(), request.fingerprint),
+        )
+        .and_then(|_| self.check_attributes_size(&request.attributes))
+        {
+            let _ = request.response_sink.send(Err(e));
+            return Ok(());
+        }
+        if self.paused_at.is_some() {
+            self.deferred_requests.push_back(request);
+            return Ok(());
+        }
+        if request.deadline_reached(self.clock.now()) {
+            let _ = request.response_sink.send(Err(TransactionError::Timeout));
+            return Ok(());
+        }
+        // `RequestSender` normally holds requests back before they get here, but the limit must
+        // hold regardless of how the request was submitted
+        if !request.keepalive && self.outstanding_count() >= self.config.max_outstanding_requests {
+            let _ = request
+                .response_sink
+                .send(Err(TransactionError::TooManyOutstanding));
+            return Ok(());
+        }
+        if self.pending_timeouts.len() >= self.config.max_pending_timeouts {
+            log::warn!(
+                "Rejecting request to {}: {} timeouts pending",
+                request.destination_addr,
+                self.pending_timeouts.len()
+            );
+            let _ = request
+                .response_sink
+                .send(Err(TransactionError::Overloaded));
+            return Ok(());
+        }
+        if let Some(rate_limit) = self
+            .config
+            .rate_limit
+            .as_ref()
+            .filter(|_| !request.keepalive)
+        {
+            let now = self.clock.now();
+            if self.throttled_requests.is_empty()
+                && !self.next_request_at.is_some_and(|at| at > now)
+            {
+                self.next_request_at = Some(now + rate_limit.interval());
+            } else if self.throttled_requests.len() < rate_limit.max_queued {
+                self.throttled_requests.push_back(request);
+                return Ok(());
+            } else {
+                log::warn!(
+                    "Rejecting request to {}: {} requests queued",
+                    request.destination_addr,
+                    self.throttled_requests.len()
+                );
+                let _ = request
+                    .response_sink
+                    .send(Err(TransactionError::Overloaded));
+                return Ok(());
+            }
+        }
+        self.start_transaction(request).await
+    }
+
+    async fn start_transaction(&mut self, mut request: Request) -> Result<(), TransactionError> {
+        let (tid, msg) = self.build_request(
+            request.method,
+            mem::take(&mut request.attributes),
+            request.integrity_key.as_deref(),
+            request.fingerprint,
+        );
+        // retransmissions reuse the transaction id, so the signature stays valid
+        request.attributes = copy_attributes(&mut *self.buffer_pool, &msg.attributes);
         log::trace!("Sending request to {:?}", request.destination_addr);
         match self.egress_sink.send((msg, request.destination_addr)).await {
             Ok(_) => {
-                let now = Instant::now();
+                let now = self.clock.now();
+                self.metrics.record_request_sent();
 
                 let initial_rto = self
                     .rto_policy
                     .calculate_rto(request.destination_addr, 0)
                     .unwrap_or(DEFAULT_RTO);
                 self.pending_timeouts.push(PendingTimeout {
-                    timeout_at: now + initial_rto,
+                    timeout_at: request.next_timeout_at(now + initial_rto),
                     tid,
                 });
 
                 request.attempts_made = 1;
+                request.history.push(Attempt {
+                    sent_at: now,
+                    rto: initial_rto,
+                });
                 request.start_time = now;
+                request.initial_rto = initial_rto;
+                if let Some(observer) = &mut self.observer {
+                    observer.on_transmission(request.destination_addr, &tid, 1);
+                }
                 self.outstanding_requests.insert(tid, request);
                 Ok(())
             }
@@ -165,14 +714,306 @@ impl<P: RtoPolicy> Manager<P> {
         }
     }
 
+    pub(super) async fn handle_reply(&mut self, reply: Reply) -> Result<(), TransactionError> {
+        let message = if reply.success {
+            Message::response(reply.method, reply.transaction_id, reply.attributes)
+        } else {
+            Message::error(reply.method, reply.transaction_id, reply.attributes)
+        };
+        let mut message = message
+            .xor_socket_addr(XorMappedAddress::ID)
+            .xor_socket_addr(XorPeerAddress::ID);
+        if self.config.append_fingerprint {
+            message.append_fingerprint();
+        }
+        log::trace!("Sending response to {:?}", reply.destination);
+        self.egress_sink.send((message, reply.destination)).await?;
+        Ok(())
+    }
+
+    /// Send a request without starting a transaction, i.e. any response to it will be orphaned.
+    pub(super) async fn handle_unanswered_request(
+        &mut self,
+        destination: SocketAddr,
+        method: u16,
+        attributes: Vec<Tlv>,
+        result_sink: DeliverySink,
+    ) -> Result<(), TransactionError> {
+        if let Err(e) =
+            check_reserved_attributes(&attributes, false, self.appends_fingerprint(false, None))
+                .and_then(|_| self.check_attributes_size(&attributes))
+        {
+            let _ = result_sink.send(Err(e));
+            return Ok(());
+        }
+        let (_, msg) = self.build_request(method, attributes, None, None);
+        log::trace!("Sending unanswered request to {destination:?}");
+        match self.egress_sink.send((msg, destination)).await {
+            Ok(_) => {
+                let _ = result_sink.send(Ok(()));
+                Ok(())
+            }
+            Err(e) => {
+                let _ = result_sink.send(Err(TransactionError::ChannelClosed));
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Encode a request the same way as `handle_outgoing_request()`, without sending it.
+    pub(super) fn serialize_request(&mut self, method: u16, attributes: Vec<Tlv>) -> Vec<u8> {
+        let (_, msg) = self.build_request(method, attributes, None, None);
+        let mut buffer = Vec::with_capacity(Header::SIZE + msg.header.length as usize);
+        // writing to a Vec can't fail
+        let _ = msg.header.encode_into(&mut buffer);
+        let _ = msg.attributes.encode_into(&mut buffer);
+        buffer
+    }
+
+    fn build_request(
+        &mut self,
+        method: u16,
+        mut attributes: Vec<Tlv>,
+        integrity_key: Option<&[u8]>,
+        fingerprint: Option<bool>,
+    ) -> (TransactionId, Message) {
+        if let Some(software) = &self.config.software {
+            if !attributes
+                .iter()
+                .any(|tlv| tlv.attribute_type == Software::ID)
+            {
+                attributes.push(Software::tlv(software));
+            }
+        }
+        let tid = self.rand_gen.gen::<TransactionId>();
+        let mut msg = Message::request(method, tid, attributes)
+            .xor_socket_addr(XorMappedAddress::ID)
+            .xor_socket_addr(XorPeerAddress::ID);
+        if let Some(key) = integrity_key {
+            msg.append_message_integrity(key);
+        }
+        if self.appends_fingerprint(integrity_key.is_some(), fingerprint) {
+            msg.append_fingerprint();
+        }
+        (tid, msg)
+    }
+
+    fn appends_fingerprint(&self, signed: bool, fingerprint: Option<bool>) -> bool {
+        fingerprint.unwrap_or(signed || self.config.append_fingerprint)
+    }
+
+    fn check_attributes_size(&self, attributes: &[Tlv]) -> Result<(), TransactionError> {
+        let Some(limit) = self.config.max_request_attributes_size else {
+            return Ok(());
+        };
+        let size = attributes.iter().map(Tlv::encoded_size).sum::<usize>();
+        if size > limit {
+            return Err(TransactionError::AttributesTooLarge { size, limit });
+        }
+        Ok(())
+    }
+
+    pub(super) fn handle_transport_event(&mut self, event: TransportEvent) {
+        match event {
+            TransportEvent::ConnectionLimitReached(remote_addr) => {
+                self.fail_requests_to(remote_addr, || TransactionError::ConnectionLimitReached);
+            }
+            TransportEvent::ConnectionClosed(remote_addr) => {
+                self.fail_requests_to(remote_addr, || TransactionError::ConnectionClosed);
+            }
+            TransportEvent::ConnectionLost(remote_addr) => {
+                self.fail_requests_to(remote_addr, || TransactionError::ConnectionLost);
+            }
+            TransportEvent::Unreachable(remote_addr) => {
+                self.fail_requests_to(remote_addr, || TransactionError::Unreachable);
+            }
+            TransportEvent::Truncated {
+                remote_addr,
+                transaction_id,
+            } => {
+                let Entry::Occupied(entry) = self.outstanding_requests.entry(transaction_id) else {
+                    return;
+                };
+                if !same_address(entry.get().destination_addr, remote_addr) {
+                    return;
+                }
+                let request = entry.remove();
+                let _ = request.response_sink.send(Err(TransactionError::Truncated));
+                release_attributes(&mut *self.buffer_pool, request.attributes);
+                self.pending_timeouts.retain(|pt| pt.tid != transaction_id);
+            }
+            TransportEvent::Delivered { transaction_id, .. } => {
+                if let Some((_, sink)) = self.pending_deliveries.remove(&transaction_id) {
+                    let _ = sink.send(Ok(()));
+                }
+            }
+        }
+    }
+
+    fn outstanding_count(&self) -> usize {
+        self.outstanding_requests
+            .values()
+            .filter(|request| !request.keepalive)
+            .count()
+    }
+
+    pub(super) fn outstanding_transactions(&self) -> Vec<TransactionInfo> {
+        self.outstanding_requests
+            .iter()
+            .map(|(tid, request)| TransactionInfo {
+                destination: request.destination_addr,
+                method: request.method,
+                transaction_id: *tid,
+                attempts_made: request.attempts_made,
+                time_elapsed: self.clock.now() - request.start_time,
+            })
+            .collect()
+    }
+
+    /// Fail the request with `TransactionError::Cancelled`, unless it has already completed.
+    pub(super) fn cancel(&mut self, id: RequestId) {
+        let tid = self
+            .outstanding_requests
+            .iter()
+            .find(|(_, request)| request.id == Some(id))
+            .map(|(tid, _)| *tid);
+        if let Some(tid) = tid {
+            if let Some(request) = self.outstanding_requests.remove(&tid) {
+                log::debug!("Cancelling request to {}", request.destination_addr);
+                let _ = request.response_sink.send(Err(TransactionError::Cancelled));
+                release_attributes(&mut *self.buffer_pool, request.attributes);
+            }
+            self.pending_timeouts.retain(|pt| pt.tid != tid);
+        } else if let Some(position) = self
+            .deferred_requests
+            .iter()
+            .position(|request| request.id == Some(id))
+        {
+            if let Some(request) = self.deferred_requests.remove(position) {
+                let _ = request.response_sink.send(Err(TransactionError::Cancelled));
+            }
+        } else if let Some(position) = self
+            .throttled_requests
+            .iter()
+            .position(|request| request.id == Some(id))
+        {
+            if let Some(request) = self.throttled_requests.remove(position) {
+                let _ = request.response_sink.send(Err(TransactionError::Cancelled));
+            }
+        }
+    }
+
+    pub(super) fn fail_all(&mut self, error: impl Fn() -> TransactionError) {
+        for (_, request) in self.outstanding_requests.drain() {
+            let _ = request.response_sink.send(Err(error()));
+            release_attributes(&mut *self.buffer_pool, request.attributes);
+        }
+        self.pending_timeouts.clear();
+        for request in self
+            .deferred_requests
+            .drain(..)
+            .chain(self.throttled_requests.drain(..))
+        {
+            let _ = request.response_sink.send(Err(error()));
+        }
+        for (_, (_, sink)) in self.pending_deliveries.drain() {
+            let _ = sink.send(Err(error()));
+        }
+    }
+
+    fn fail_requests_to(&mut self, remote_addr: SocketAddr, error: impl Fn() -> TransactionError) {
+        let failed_tids: Vec<TransactionId> = self
+            .outstanding_requests
+            .iter()
+            .filter(|(_, request)| same_address(request.destination_addr, remote_addr))
+            .map(|(tid, _)| *tid)
+            .collect();
+        for tid in &failed_tids {
+            if let Some(request) = self.outstanding_requests.remove(tid) {
+                let _ = request.response_sink.send(Err(error()));
+                release_attributes(&mut *self.buffer_pool, request.attributes);
+            }
+        }
+        self.pending_timeouts
+            .retain(|pt| !failed_tids.contains(&pt.tid));
+
+        let failed_tids: Vec<TransactionId> = self
+            .pending_deliveries
+            .iter()
+            .filter(|(_, (destination, _))| same_address(*destination, remote_addr))
+            .map(|(tid, _)| *tid)
+            .collect();
+        for tid in &failed_tids {
+            if let Some((_, sink)) = self.pending_deliveries.remove(tid) {
+                let _ = sink.send(Err(error()));
+            }
+        }
+    }
+
     pub(super) async fn handle_incoming_message(
         &mut self,
         (message, source_addr): (Message, SocketAddr),
     ) -> Result<(), TransactionError> {
-        let message = message.xor_socket_addr(XorMappedAddress::ID);
+        log::trace!(
+            "Received {} from {source_addr}",
+            message.display_with(&self.config.attribute_names)
+        );
+        if message.verify_fingerprint() == Some(false) {
+            if self.config.reject_invalid_fingerprint || self.config.append_fingerprint {
+                log::warn!("Dropping message from {source_addr}: invalid FINGERPRINT");
+                return Ok(());
+            }
+            log::warn!("Received message with invalid FINGERPRINT from {source_addr}");
+        }
+        // validated before xor_socket_addr(), which would invalidate MESSAGE-INTEGRITY
+        let mut validation = Validation::Accept;
+        if matches!(message.header.class, Class::Response | Class::Error) {
+            if let Some(request) = self
+                .outstanding_requests
+                .get(&message.header.transaction_id)
+            {
+                let details = RequestDetails {
+                    destination: request.destination_addr,
+                    method: request.method,
+                    integrity_key: request.integrity_key.as_deref(),
+                };
+                validation = self.validator.validate(&details, &message, source_addr);
+                if matches!(validation, Validation::Ignore) {
+                    return Ok(());
+                }
+            }
+        }
+        let message = message
+            .xor_socket_addr(XorMappedAddress::ID)
+            .xor_socket_addr(XorPeerAddress::ID);
+        if matches!(message.header.class, Class::Request | Class::Indication)
+            && self
+                .outstanding_requests
+                .contains_key(&message.header.transaction_id)
+        {
+            // only responses may complete a transaction, whatever the transaction id
+            log::warn!(
+                "Received {:?} from {source_addr} with the id of an outstanding request",
+                message.header.class
+            );
+        }
         match message.header.class {
             Class::Request => {
-                log::error!("Ignoring incoming request: handling of requests is not supported");
+                let Some(sink) = &self.incoming_requests_sink else {
+                    log::error!("Ignoring incoming request: handling of requests is not enabled");
+                    return Ok(());
+                };
+                if let Ok(sender) = sink.reserve().await {
+                    sender.send(IncomingRequest {
+                        source_addr,
+                        method: message.header.method,
+                        transaction_id: message.header.transaction_id,
+                        attributes: message.attributes,
+                        reply_sink: self.reply_sink.clone(),
+                    });
+                } else {
+                    log::debug!("Dropping received request: no listener");
+                }
             }
             Class::Indication => {
                 if let Ok(sender) = self.incoming_indications_sink.reserve().await {
@@ -193,39 +1034,95 @@ impl<P: RtoPolicy> Manager<P> {
                     Some(request) => request,
                     None => {
                         log::warn!("Received orphaned response from {source_addr}");
+                        self.metrics.record_orphaned_response();
                         return Ok(());
                     }
                 };
                 self.pending_timeouts
                     .retain(|pt| pt.tid != message.header.transaction_id);
-
+                self.metrics
+                    .record_response(matches!(message.header.class, Class::Response));
+                self.metrics.record_attempt_outcome(
+                    request.destination_addr,
+                    false,
+                    self.clock.now(),
+                );
+                if let Some(observer) = &mut self.observer {
+                    observer.on_response(
+                        source_addr,
+                        &message.header.transaction_id,
+                        matches!(message.header.class, Class::Response),
+                    );
+                }
+                // measured from when the transport received the response, so that any backlog in
+                // the processor doesn't inflate it
+                let time_elapsed = self
+                    .clock
+                    .received_at(message.received_at)
+                    .saturating_duration_since(request.start_time);
                 if request.attempts_made == 1 {
                     self.rto_policy
-                        .submit_rtt(source_addr, request.start_time.elapsed());
+                        .submit_rtt(request.destination_addr, time_elapsed);
+                    self.metrics.record_rtt(time_elapsed);
                 }
 
-                let request_method = request.method;
-                let response_method = message.header.method;
-
-                let result = if request_method != response_method {
-                    Err(TransactionError::MethodMismatch {
-                        request_method,
-                        response_method,
-                    })
-                } else {
-                    Ok(Response {
+                let result = match validation {
+                    Validation::Reject(error) => Err(error),
+                    _ => Ok(Response {
                         success: matches!(message.header.class, Class::Response),
                         attributes: message.attributes,
-                        time_elapsed: request.start_time.elapsed(),
-                    })
+                        time_elapsed,
+                        queue_wait: request
+                            .start_time
+                            .saturating_duration_since(request.enqueued_at),
+                        initial_rto: request.initial_rto,
+                    }),
                 };
-                let _ = request.response_sink.send(result);
+                if request.keepalive {
+                    self.update_mapping(request.destination_addr, result);
+                } else {
+                    let _ = request.response_sink.send(result);
+                }
+                release_attributes(&mut *self.buffer_pool, request.attributes);
             }
         }
         Ok(())
     }
 }
 
+/// Attributes that the processor appends itself mustn't also be supplied by the caller.
+fn check_reserved_attributes(
+    attributes: &[Tlv],
+    appends_integrity: bool,
+    appends_fingerprint: bool,
+) -> Result<(), TransactionError> {
+    match attributes.iter().find(|tlv| {
+        (appends_integrity && tlv.attribute_type == MessageIntegrity::ID)
+            || (appends_fingerprint && tlv.attribute_type == Fingerprint::ID)
+    }) {
+        Some(tlv) => Err(TransactionError::ReservedAttribute(tlv.attribute_type)),
+        None => Ok(()),
+    }
+}
+
+/// E.g. "+0ms (rto 500ms), +500ms (rto 1s)"
+fn format_history(history: &[Attempt]) -> String {
+    let Some(first) = history.first() else {
+        return String::new();
+    };
+    history
+        .iter()
+        .map(|attempt| {
+            format!(
+                "+{:?} (rto {:?})",
+                attempt.sent_at - first.sent_at,
+                attempt.rto
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 const DEFAULT_RTO: Duration = Duration::from_millis(1500);
 
 impl PartialEq for PendingTimeout {
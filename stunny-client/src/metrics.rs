@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Counters updated by the `Processor`, cheap to clone and safe to read from any thread.
+///
+/// With the `metrics` feature enabled they're also reported to the `metrics` crate facade, along
+/// with an RTT histogram, under the names listed in `metric_names`.
+#[derive(Clone, Default, Debug)]
+pub struct Metrics(Arc<Counters>);
+
+#[derive(Default, Debug)]
+struct Counters {
+    requests_sent: AtomicU64,
+    retransmissions: AtomicU64,
+    success_responses: AtomicU64,
+    error_responses: AtomicU64,
+    timeouts: AtomicU64,
+    orphaned_responses: AtomicU64,
+    dropped_indications: AtomicU64,
+    losses: Mutex<HashMap<SocketAddr, LossEstimate>>,
+}
+
+/// Samples older than this weigh half as much as new ones in `Metrics::loss_rate()`.
+pub const LOSS_HALF_LIFE: Duration = Duration::from_secs(60);
+
+/// Exponentially decaying counts of transmissions and of those that went unanswered.
+#[derive(Debug)]
+struct LossEstimate {
+    attempts: f64,
+    losses: f64,
+    updated_at: Instant,
+}
+
+impl LossEstimate {
+    fn record(&mut self, lost: bool, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        let decay = 0.5f64.powf(elapsed.as_secs_f64() / LOSS_HALF_LIFE.as_secs_f64());
+        self.attempts = self.attempts * decay + 1.0;
+        self.losses = self.losses * decay + if lost { 1.0 } else { 0.0 };
+        self.updated_at = now;
+    }
+}
+
+/// Names of the counters and histograms reported to the `metrics` facade.
+#[cfg(feature = "metrics")]
+pub mod metric_names {
+    pub const REQUESTS_SENT: &str = "stunny_client_requests_sent_total";
+    pub const RETRANSMISSIONS: &str = "stunny_client_retransmissions_total";
+    /// Labelled with `class` being either "success" or "error".
+    pub const RESPONSES: &str = "stunny_client_responses_total";
+    pub const TIMEOUTS: &str = "stunny_client_timeouts_total";
+    pub const ORPHANED_RESPONSES: &str = "stunny_client_orphaned_responses_total";
+    pub const DROPPED_INDICATIONS: &str = "stunny_client_dropped_indications_total";
+    /// Histogram of round-trip times in seconds, sampled from requests answered on the first
+    /// attempt.
+    pub const RTT: &str = "stunny_client_rtt_seconds";
+}
+
+impl Metrics {
+    /// Number of requests sent, not counting retransmissions.
+    pub fn requests_sent(&self) -> u64 {
+        self.0.requests_sent.load(Ordering::Relaxed)
+    }
+
+    /// Total number of request retransmissions across all transactions.
+    pub fn retransmissions(&self) -> u64 {
+        self.0.retransmissions.load(Ordering::Relaxed)
+    }
+
+    pub fn success_responses(&self) -> u64 {
+        self.0.success_responses.load(Ordering::Relaxed)
+    }
+
+    pub fn error_responses(&self) -> u64 {
+        self.0.error_responses.load(Ordering::Relaxed)
+    }
+
+    /// Number of transactions that timed out without a response.
+    pub fn timeouts(&self) -> u64 {
+        self.0.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Number of responses dropped because they didn't match any outstanding request.
+    pub fn orphaned_responses(&self) -> u64 {
+        self.0.orphaned_responses.load(Ordering::Relaxed)
+    }
+
+    /// Number of indications dropped because the transport couldn't accept them before their
+    /// deadline.
+    pub fn dropped_indications(&self) -> u64 {
+        self.0.dropped_indications.load(Ordering::Relaxed)
+    }
+
+    /// Estimated fraction of requests to `destination` that go unanswered, i.e. are followed by
+    /// a retransmission or a timeout, from 0.0 to 1.0. Recent transmissions weigh more, see
+    /// `LOSS_HALF_LIFE`. `None` if no request to `destination` has completed an attempt yet.
+    pub fn loss_rate(&self, destination: SocketAddr) -> Option<f64> {
+        let losses = self.0.losses.lock().unwrap();
+        let estimate = losses.get(&destination)?;
+        Some(estimate.losses / estimate.attempts)
+    }
+
+    pub(super) fn record_request_sent(&self) {
+        self.0.requests_sent.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!(metric_names::REQUESTS_SENT).increment(1);
+    }
+
+    pub(super) fn record_retransmission(&self) {
+        self.0.retransmissions.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!(metric_names::RETRANSMISSIONS).increment(1);
+    }
+
+    pub(super) fn record_response(&self, success: bool) {
+        let counter = if success {
+            &self.0.success_responses
+        } else {
+            &self.0.error_responses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!(
+            metric_names::RESPONSES,
+            "class" => if success { "success" } else { "error" }
+        )
+        .increment(1);
+    }
+
+    pub(super) fn record_timeout(&self) {
+        self.0.timeouts.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!(metric_names::TIMEOUTS).increment(1);
+    }
+
+    pub(super) fn record_orphaned_response(&self) {
+        self.0.orphaned_responses.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!(metric_names::ORPHANED_RESPONSES).increment(1);
+    }
+
+    pub(super) fn record_dropped_indication(&self) {
+        self.0.dropped_indications.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!(metric_names::DROPPED_INDICATIONS).increment(1);
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(super) fn record_rtt(&self, rtt: Duration) {
+        #[cfg(feature = "metrics")]
+        ::metrics::histogram!(metric_names::RTT).record(rtt.as_secs_f64());
+    }
+
+    /// Outcome of a single transmission to `destination`: `lost` if it was retransmitted or timed
+    /// out, otherwise it was answered.
+    pub(super) fn record_attempt_outcome(&self, destination: SocketAddr, lost: bool, now: Instant) {
+        self.0
+            .losses
+            .lock()
+            .unwrap()
+            .entry(destination)
+            .or_insert(LossEstimate {
+                attempts: 0.0,
+                losses: 0.0,
+                updated_at: now,
+            })
+            .record(lost, now);
+    }
+}
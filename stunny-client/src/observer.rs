@@ -0,0 +1,46 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A single transmission of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attempt {
+    pub sent_at: Instant,
+    /// How long the processor waited for a response before the next attempt.
+    pub rto: Duration,
+}
+
+/// Hooks into the transaction lifecycle. All methods are invoked synchronously from within
+/// `Processor::run()`, so they must not block.
+pub trait TransactionObserver {
+    /// Called after a request has been handed to the transport. `attempt` is 1 for the initial
+    /// transmission and increases with every retransmission.
+    fn on_transmission(
+        &mut self,
+        _destination: SocketAddr,
+        _transaction_id: &[u8; 12],
+        _attempt: usize,
+    ) {
+    }
+
+    /// Called when a success or error response to an outstanding request is received.
+    fn on_response(&mut self, _source: SocketAddr, _transaction_id: &[u8; 12], _success: bool) {}
+
+    /// Called right before the last retransmission of a request is sent, i.e. when the RTO policy
+    /// will not allow any further attempts after this one.
+    fn on_final_retransmission(&mut self, _destination: SocketAddr, _transaction_id: &[u8; 12]) {}
+
+    /// Called when a keepalive response (see `Processor::add_keepalive()`) reports a different
+    /// mapped address than the previous one, including the very first response.
+    fn on_mapping_changed(&mut self, _server: SocketAddr, _mapped_address: SocketAddr) {}
+
+    /// Called when a request fails with `TransactionError::Timeout`, with all transmissions of it
+    /// in the order they were sent.
+    fn on_timeout(
+        &mut self,
+        _destination: SocketAddr,
+        _transaction_id: &[u8; 12],
+        _attempts: &[Attempt],
+    ) {
+    }
+}
@@ -0,0 +1,51 @@
+use rand::{CryptoRng, RngCore};
+use std::fmt;
+
+/// Source of transaction ids. Off-path attackers who can predict them can spoof responses, e.g.
+/// forge the mapped address of a Binding response, so they must come from a cryptographically
+/// secure generator unless the client is only used in a trusted environment (or in tests).
+pub struct TransactionIdRng {
+    rng: Box<dyn RngCore>,
+    cryptographic: bool,
+}
+
+impl TransactionIdRng {
+    pub fn cryptographic(rng: impl RngCore + CryptoRng + 'static) -> Self {
+        Self {
+            rng: Box::new(rng),
+            cryptographic: true,
+        }
+    }
+
+    /// E.g. a seeded generator for reproducible transaction ids. Rejected by setup if
+    /// `Config::require_cryptographic_rng` is set.
+    pub fn non_cryptographic(rng: impl RngCore + 'static) -> Self {
+        Self {
+            rng: Box::new(rng),
+            cryptographic: false,
+        }
+    }
+
+    pub fn is_cryptographic(&self) -> bool {
+        self.cryptographic
+    }
+
+    pub(super) fn into_inner(self) -> Box<dyn RngCore> {
+        self.rng
+    }
+}
+
+/// The thread-local generator of `rand`, which is a CSPRNG.
+impl Default for TransactionIdRng {
+    fn default() -> Self {
+        Self::cryptographic(rand::thread_rng())
+    }
+}
+
+impl fmt::Debug for TransactionIdRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionIdRng")
+            .field("cryptographic", &self.cryptographic)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,4 +1,5 @@
 use std::cmp;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Duration;
 
@@ -6,9 +7,27 @@ pub trait RtoPolicy {
     /// Submit a new RTT measurement for the given remote address.
     fn submit_rtt(&mut self, remote_addr: SocketAddr, rtt: Duration);
 
+    /// Seed the RTT estimate for the given remote address with a value known in advance, e.g.
+    /// from a previous session, so that the first request to it uses a tuned RTO.
+    fn prime_rtt(&mut self, _remote_addr: SocketAddr, _rtt: Duration) {}
+
     /// Calculate RTO for the next retransmission that will happen immediately after this call.
     /// If `None` is returned, no retransmission will be made.
     fn calculate_rto(&mut self, remote_addr: SocketAddr, attempts_made: usize) -> Option<Duration>;
+
+    /// Whether the retransmission made after `attempts_made` attempts is the last one, i.e. the
+    /// RTO returned for it is the final wait before the transaction times out. By default
+    /// derived from `calculate_rto()`, which must then be free of side effects.
+    fn is_final_retransmission(&mut self, remote_addr: SocketAddr, attempts_made: usize) -> bool {
+        attempts_made > 0
+            && self.calculate_rto(remote_addr, attempts_made).is_some()
+            && self.calculate_rto(remote_addr, attempts_made + 1).is_none()
+    }
+
+    /// Whether this policy ever retransmits. If not, the first RTO is the transaction timeout.
+    fn retransmits(&self) -> bool {
+        true
+    }
 }
 
 pub struct NoRetransmissionsConstTimeout {
@@ -36,6 +55,10 @@ impl RtoPolicy for NoRetransmissionsConstTimeout {
             _ => None,
         }
     }
+
+    fn retransmits(&self) -> bool {
+        false
+    }
 }
 
 pub struct ExponentialBackoffFixedRtt<const RC: usize, const RM: u32> {
@@ -68,22 +91,178 @@ impl<const RC: usize, const RM: u32> RtoPolicy for ExponentialBackoffFixedRtt<RC
         _remote_addr: SocketAddr,
         attempts_made: usize,
     ) -> Option<Duration> {
-        macro_rules! exp_backoff {
-            () => {{
-                self.rto * (2 << (attempts_made - 1))
-            }};
+        exponential_backoff(self.rto, RC, RM, attempts_made)
+    }
+
+    fn is_final_retransmission(&mut self, _remote_addr: SocketAddr, attempts_made: usize) -> bool {
+        attempts_made > 0 && attempts_made == RC - 1
+    }
+}
+
+/// RTO doubling after every transmission, with at most `request_count` transmissions and the
+/// last wait capped at `last_rto_multiplier` times `rto` (Rc and Rm in RFC 8489).
+fn exponential_backoff(
+    rto: Duration,
+    request_count: usize,
+    last_rto_multiplier: u32,
+    attempts_made: usize,
+) -> Option<Duration> {
+    macro_rules! exp_backoff {
+        () => {{
+            rto * (2 << (attempts_made - 1))
+        }};
+    }
+
+    if attempts_made == 0 {
+        Some(rto)
+    } else if attempts_made == request_count {
+        None
+    } else if attempts_made == request_count - 1 {
+        Some(cmp::min(exp_backoff!(), rto * last_rto_multiplier))
+    } else {
+        Some(exp_backoff!())
+    }
+}
+
+/// The RFC 5389 retransmission schedule with parameters chosen at runtime: `initial_rto`
+/// doubling on every retransmission, `request_count` transmissions in total and a final wait of
+/// 16 times `initial_rto`. RTT measurements are ignored.
+pub struct Rfc5389RtoPolicy {
+    initial_rto: Duration,
+    request_count: usize,
+}
+
+impl Rfc5389RtoPolicy {
+    /// Multiplier of the initial RTO for the wait after the last transmission (Rm).
+    pub const LAST_RTO_MULTIPLIER: u32 = 16;
+
+    /// `request_count` includes the first transmission (Rc, 7 by default in RFC 5389).
+    pub fn new(initial_rto: Duration, request_count: usize) -> Self {
+        Self {
+            initial_rto,
+            request_count: cmp::max(request_count, 1),
         }
+    }
+}
+
+impl Default for Rfc5389RtoPolicy {
+    fn default() -> Self {
+        Self::new(INITIAL_RTO, 7)
+    }
+}
+
+impl RtoPolicy for Rfc5389RtoPolicy {
+    fn submit_rtt(&mut self, _remote_addr: SocketAddr, _rtt: Duration) {
+        /* noop */
+    }
+
+    fn calculate_rto(
+        &mut self,
+        _remote_addr: SocketAddr,
+        attempts_made: usize,
+    ) -> Option<Duration> {
+        exponential_backoff(
+            self.initial_rto,
+            self.request_count,
+            Self::LAST_RTO_MULTIPLIER,
+            attempts_made,
+        )
+    }
+
+    fn is_final_retransmission(&mut self, _remote_addr: SocketAddr, attempts_made: usize) -> bool {
+        attempts_made > 0 && attempts_made == self.request_count - 1
+    }
 
-        if attempts_made == 0 {
-            Some(self.rto)
-        } else if attempts_made == RC {
-            None
-        } else if attempts_made == RC - 1 {
-            Some(cmp::min(exp_backoff!(), self.rto * RM))
-        } else {
-            Some(exp_backoff!())
+    fn retransmits(&self) -> bool {
+        self.request_count > 1
+    }
+}
+
+/// Exponential backoff as in RFC 8489, starting from an RTO estimated per remote address from
+/// RTT measurements as in RFC 6298. Addresses without any measurements start at 500 ms. The
+/// manager only submits RTTs of requests that weren't retransmitted (Karn's algorithm).
+///
+/// Unlike RFC 8489 section 6.2.1, which has no lower bound, the estimated RTO never drops below
+/// `DEFAULT_MIN_RTO` unless overridden with `with_min_rto()`, to stay conservative on paths with
+/// very low RTTs.
+pub struct RfcRtoPolicy<const RC: usize, const RM: u32> {
+    estimates: HashMap<SocketAddr, RttEstimate>,
+    min_rto: Duration,
+}
+
+pub type DefaultRfcRtoPolicy = RfcRtoPolicy<7, 16>;
+
+struct RttEstimate {
+    srtt: Duration,
+    rttvar: Duration,
+}
+
+impl RttEstimate {
+    fn new(rtt: Duration) -> Self {
+        Self {
+            srtt: rtt,
+            rttvar: rtt / 2,
         }
     }
+
+    fn update(&mut self, rtt: Duration) {
+        self.rttvar = (self.rttvar * 3 + self.srtt.abs_diff(rtt)) / 4;
+        self.srtt = (self.srtt * 7 + rtt) / 8;
+    }
+
+    fn rto(&self) -> Duration {
+        self.srtt + cmp::max(CLOCK_GRANULARITY, self.rttvar * 4)
+    }
+}
+
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+
+const INITIAL_RTO: Duration = Duration::from_millis(500);
+
+pub const DEFAULT_MIN_RTO: Duration = Duration::from_millis(500);
+
+impl<const RC: usize, const RM: u32> Default for RfcRtoPolicy<RC, RM> {
+    fn default() -> Self {
+        Self {
+            estimates: HashMap::new(),
+            min_rto: DEFAULT_MIN_RTO,
+        }
+    }
+}
+
+impl<const RC: usize, const RM: u32> RfcRtoPolicy<RC, RM> {
+    /// Never let the estimated RTO drop below `min_rto` instead of `DEFAULT_MIN_RTO`. Zero
+    /// disables the floor.
+    pub fn with_min_rto(mut self, min_rto: Duration) -> Self {
+        self.min_rto = min_rto;
+        self
+    }
+}
+
+impl<const RC: usize, const RM: u32> RtoPolicy for RfcRtoPolicy<RC, RM> {
+    fn submit_rtt(&mut self, remote_addr: SocketAddr, rtt: Duration) {
+        self.estimates
+            .entry(remote_addr)
+            .and_modify(|estimate| estimate.update(rtt))
+            .or_insert_with(|| RttEstimate::new(rtt));
+    }
+
+    fn prime_rtt(&mut self, remote_addr: SocketAddr, rtt: Duration) {
+        self.estimates.insert(remote_addr, RttEstimate::new(rtt));
+    }
+
+    fn calculate_rto(&mut self, remote_addr: SocketAddr, attempts_made: usize) -> Option<Duration> {
+        let rto = self
+            .estimates
+            .get(&remote_addr)
+            .map_or(INITIAL_RTO, RttEstimate::rto)
+            .max(self.min_rto);
+        ExponentialBackoffFixedRtt::<RC, RM>::new(rto).calculate_rto(remote_addr, attempts_made)
+    }
+
+    fn is_final_retransmission(&mut self, _remote_addr: SocketAddr, attempts_made: usize) -> bool {
+        attempts_made > 0 && attempts_made == RC - 1
+    }
 }
 
 #[cfg(test)]
@@ -122,4 +301,135 @@ mod tests {
         let rto = policy.calculate_rto(IP, attempts);
         assert!(rto.is_none());
     }
+
+    #[test]
+    fn exponential_backoff_final_retransmission() {
+        let mut policy = DefaultExponentialBackoffFixedRtt::default();
+        let final_attempts: Vec<_> = (0..10)
+            .filter(|&attempts| policy.is_final_retransmission(IP, attempts))
+            .collect();
+        assert_eq!(final_attempts, vec![6]);
+
+        let mut policy = NoRetransmissionsConstTimeout::new(millisec!(500));
+        assert!((0..10).all(|attempts| !policy.is_final_retransmission(IP, attempts)));
+    }
+
+    #[test]
+    fn derive_final_retransmission_from_rto() {
+        struct ThreeTransmissions;
+
+        impl RtoPolicy for ThreeTransmissions {
+            fn submit_rtt(&mut self, _remote_addr: SocketAddr, _rtt: Duration) {}
+
+            fn calculate_rto(
+                &mut self,
+                _remote_addr: SocketAddr,
+                attempts: usize,
+            ) -> Option<Duration> {
+                (attempts < 3).then_some(millisec!(500))
+            }
+        }
+
+        let mut policy = ThreeTransmissions;
+        let final_attempts: Vec<_> = (0..10)
+            .filter(|&attempts| policy.is_final_retransmission(IP, attempts))
+            .collect();
+        assert_eq!(final_attempts, vec![2]);
+    }
+
+    #[test]
+    fn report_whether_policy_retransmits() {
+        assert!(DefaultExponentialBackoffFixedRtt::default().retransmits());
+        assert!(DefaultRfcRtoPolicy::default().retransmits());
+        assert!(!NoRetransmissionsConstTimeout::new(millisec!(500)).retransmits());
+    }
+
+    #[test]
+    fn rfc5389_policy_with_custom_parameters() {
+        let mut policy = Rfc5389RtoPolicy::new(millisec!(100), 4);
+
+        // when
+        policy.submit_rtt(IP, millisec!(10));
+
+        // then
+        assert_eq!(policy.calculate_rto(IP, 0), Some(millisec!(100)));
+        assert_eq!(policy.calculate_rto(IP, 1), Some(millisec!(200)));
+        assert_eq!(policy.calculate_rto(IP, 2), Some(millisec!(400)));
+        assert_eq!(policy.calculate_rto(IP, 3), Some(millisec!(800)));
+        assert_eq!(policy.calculate_rto(IP, 4), None);
+        assert!(policy.is_final_retransmission(IP, 3));
+        assert!(policy.retransmits());
+
+        // final wait is capped at 16 * RTO
+        let mut policy = Rfc5389RtoPolicy::new(millisec!(100), 7);
+        assert_eq!(policy.calculate_rto(IP, 5), Some(millisec!(3200)));
+        assert_eq!(policy.calculate_rto(IP, 6), Some(millisec!(1600)));
+        assert_eq!(policy.calculate_rto(IP, 7), None);
+
+        let mut policy = Rfc5389RtoPolicy::new(millisec!(100), 1);
+        assert_eq!(policy.calculate_rto(IP, 0), Some(millisec!(100)));
+        assert_eq!(policy.calculate_rto(IP, 1), None);
+        assert!(!policy.retransmits());
+    }
+
+    #[test]
+    fn rfc5389_policy_defaults_match_fixed_rtt() {
+        let mut policy = Rfc5389RtoPolicy::default();
+        let mut reference = DefaultExponentialBackoffFixedRtt::default();
+        for attempts in 0..10 {
+            assert_eq!(
+                policy.calculate_rto(IP, attempts),
+                reference.calculate_rto(IP, attempts)
+            );
+            assert_eq!(
+                policy.is_final_retransmission(IP, attempts),
+                reference.is_final_retransmission(IP, attempts)
+            );
+        }
+    }
+
+    #[test]
+    fn rfc_policy_uses_primed_rtt() {
+        const OTHER_IP: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::BROADCAST, 23456));
+        let mut policy = DefaultRfcRtoPolicy::default().with_min_rto(Duration::ZERO);
+
+        // when
+        policy.prime_rtt(IP, millisec!(100));
+
+        // then
+        assert_eq!(policy.calculate_rto(IP, 0), Some(millisec!(300)));
+        assert_eq!(policy.calculate_rto(IP, 1), Some(millisec!(600)));
+        assert_eq!(policy.calculate_rto(OTHER_IP, 0), Some(millisec!(500)));
+
+        // when
+        policy.submit_rtt(IP, millisec!(100));
+
+        // then: srtt=100ms, rttvar=37.5ms
+        assert_eq!(policy.calculate_rto(IP, 0), Some(millisec!(250)));
+    }
+
+    #[test]
+    fn rfc_policy_clamps_to_min_rto() {
+        let mut policy = DefaultRfcRtoPolicy::default();
+
+        // when: first sample initializes srtt=R, rttvar=R/2
+        policy.submit_rtt(IP, millisec!(40));
+
+        // then: 40ms + 4 * 20ms is below the minimum
+        assert_eq!(policy.calculate_rto(IP, 0), Some(millisec!(500)));
+        assert_eq!(policy.calculate_rto(IP, 1), Some(millisec!(1000)));
+
+        // when
+        policy.submit_rtt(IP, millisec!(600));
+
+        // then: srtt=110ms, rttvar=155ms
+        assert_eq!(policy.calculate_rto(IP, 0), Some(millisec!(730)));
+
+        // when: no floor
+        let mut policy = DefaultRfcRtoPolicy::default().with_min_rto(Duration::ZERO);
+        policy.submit_rtt(IP, millisec!(40));
+
+        // then
+        assert_eq!(policy.calculate_rto(IP, 0), Some(millisec!(120)));
+    }
 }
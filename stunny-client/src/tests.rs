@@ -1,7 +1,9 @@
 use super::*;
 use local_async_utils::{millisec, sec};
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::task::yield_now;
@@ -9,12 +11,6 @@ use tokio::{join, time};
 use tokio_test::task::spawn;
 use tokio_test::{assert_pending, assert_ready};
 
-thread_local! {
-    // To avoid having to add #[tokio::test] to every test, we need a way to disable calls to tokio::time::sleep(),
-    // which would panic without a tokio runtime
-    pub static SLEEP_ENABLED: Cell<bool> = const { Cell::new(false) };
-}
-
 fn attribute() -> Tlv {
     Tlv {
         attribute_type: 0x8022,
@@ -30,14 +26,16 @@ fn ip(port: u16) -> SocketAddr {
 fn single_outgoing_request() {
     let (egress_sink, mut egress_source) = mpsc::channel(10);
     let (ingress_sink, ingress_source) = mpsc::channel(10);
-    let (req_sender, _, _, processor) = setup_transactions(
+    let (req_sender, _, _, mut processor) = setup_transactions(
         MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         },
         1,
         NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
     );
+    processor.use_manual_clock();
     let mut runner_fut = spawn(processor.run());
     assert_pending!(runner_fut.poll());
 
@@ -76,14 +74,16 @@ fn single_outgoing_request() {
 fn concurrent_outgoing_requests() {
     let (egress_sink, mut egress_source) = mpsc::channel(10);
     let (ingress_sink, ingress_source) = mpsc::channel(10);
-    let (req_sender, _, _, processor) = setup_transactions(
+    let (req_sender, _, _, mut processor) = setup_transactions(
         MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         },
         2,
         NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
     );
+    processor.use_manual_clock();
     let mut runner_fut = spawn(processor.run());
     assert_pending!(runner_fut.poll());
 
@@ -154,13 +154,13 @@ async fn outgoing_request_retransmissions() {
     let _ = simple_logger::SimpleLogger::new()
         .with_level(log::LevelFilter::Trace)
         .init();
-    SLEEP_ENABLED.with(|sleep_enabled| sleep_enabled.set(true));
     let (egress_sink, mut egress_source) = mpsc::channel(10);
     let (_ingress_sink, ingress_source) = mpsc::channel(10);
     let (req_sender, _, _, processor) = setup_transactions(
         MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         },
         1,
         DefaultExponentialBackoffFixedRtt::default(),
@@ -226,14 +226,16 @@ async fn outgoing_request_retransmissions() {
 fn outgoing_indication() {
     let (egress_sink, mut egress_source) = mpsc::channel(10);
     let (_ingress_sink, ingress_source) = mpsc::channel(10);
-    let (_, ind_sender, _, processor) = setup_transactions(
+    let (_, ind_sender, _, mut processor) = setup_transactions(
         MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         },
         1,
         NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
     );
+    processor.use_manual_clock();
     let mut runner_fut = spawn(processor.run());
     assert_pending!(runner_fut.poll());
 
@@ -251,18 +253,87 @@ fn outgoing_indication() {
     assert_eq!(indication.attributes, vec![attribute()]);
 }
 
+#[tokio::test(start_paused = true)]
+async fn drop_indication_blocked_past_deadline() {
+    let (egress_sink, mut egress_source) = mpsc::channel(1);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (_, ind_sender, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+    let metrics = processor.metrics();
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        // given: the egress channel is full
+        ind_sender
+            .send_indication(ip(1111), 42u16, vec![attribute()])
+            .await
+            .unwrap();
+        time::sleep(millisec!(10)).await;
+
+        // when
+        let start_time = Instant::now();
+        ind_sender
+            .send_indication_until(
+                ip(2222),
+                42u16,
+                vec![attribute()],
+                start_time + millisec!(100),
+            )
+            .await
+            .unwrap();
+        time::sleep(millisec!(99)).await;
+        assert_eq!(metrics.dropped_indications(), 0);
+        time::sleep(millisec!(2)).await;
+
+        // then
+        assert_eq!(metrics.dropped_indications(), 1);
+        assert_eq!(egress_source.recv().await.unwrap().1, ip(1111));
+        time::sleep(millisec!(10)).await;
+        assert!(egress_source.try_recv().is_err());
+
+        // when: the egress channel has capacity
+        ind_sender
+            .send_indication_until(
+                ip(3333),
+                42u16,
+                vec![attribute()],
+                Instant::now() + millisec!(100),
+            )
+            .await
+            .unwrap();
+
+        // then
+        assert_eq!(egress_source.recv().await.unwrap().1, ip(3333));
+        assert_eq!(metrics.dropped_indications(), 1);
+    };
+
+    join!(processor_fut, test_fut);
+}
+
 #[test]
 fn incoming_indication() {
     let (egress_sink, _egress_source) = mpsc::channel(10);
     let (ingress_sink, ingress_source) = mpsc::channel(10);
-    let (_, _, mut ind_receiver, processor) = setup_transactions(
+    let (_, _, mut ind_receiver, mut processor) = setup_transactions(
         MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         },
         1,
         NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
     );
+    processor.use_manual_clock();
     let mut runner_fut = spawn(processor.run());
     assert_pending!(runner_fut.poll());
     let mut receive_fut = spawn(ind_receiver.receive_next());
@@ -281,3 +352,3643 @@ fn incoming_indication() {
     assert_eq!(indication.method, 42u16);
     assert_eq!(indication.attributes, vec![attribute()]);
 }
+
+#[test]
+fn answer_incoming_requests() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (_, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    let mut request_receiver = processor.receive_requests();
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let request = Message::request(BINDING_METHOD, [0xaf; 12], vec![attribute()]);
+    ingress_sink.try_send((request, ip(1234))).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let request = assert_ready!(spawn(request_receiver.receive_next()).poll()).unwrap();
+    assert_eq!(request.source_addr, ip(1234));
+    assert_eq!(request.method, BINDING_METHOD);
+    assert_eq!(request.transaction_id, [0xaf; 12]);
+    assert_eq!(request.attributes, vec![attribute()]);
+
+    // when
+    let mut attributes = Vec::new();
+    attributes.append_attribute(XorMappedAddress(request.source_addr));
+    assert_ready!(spawn(request.respond(attributes)).poll()).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (response, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(1234));
+    assert_eq!(response.header.class, Class::Response);
+    assert_eq!(response.header.method, BINDING_METHOD);
+    assert_eq!(response.header.transaction_id, [0xaf; 12]);
+    let mut attributes = response.xor_socket_addr(XorMappedAddress::ID).attributes;
+    assert_eq!(
+        attributes
+            .extract_attribute::<XorMappedAddress>()
+            .unwrap()
+            .0,
+        ip(1234)
+    );
+
+    // when
+    let request = Message::request(42u16, [0xbf; 12], vec![]);
+    ingress_sink.try_send((request, ip(5678))).unwrap();
+    assert_pending!(runner_fut.poll());
+    let request = assert_ready!(spawn(request_receiver.receive_next()).poll()).unwrap();
+    let mut attributes = Vec::new();
+    attributes.append_attribute(ErrorCode {
+        code: 400,
+        reason: "Bad Request".to_owned(),
+    });
+    assert_ready!(spawn(request.respond_error(attributes)).poll()).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (mut response, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(5678));
+    assert_eq!(response.header.class, Class::Error);
+    assert_eq!(response.header.method, 42u16);
+    assert_eq!(response.header.transaction_id, [0xbf; 12]);
+    assert_eq!(
+        response
+            .attributes
+            .extract_attribute::<ErrorCode>()
+            .unwrap()
+            .code,
+        400
+    );
+}
+
+#[test]
+fn send_and_receive_turn_indications() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (_, ind_sender, mut ind_receiver, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let send = Indication::send(ip(3478), ip(5555), b"hello".to_vec());
+    assert_eq!(send.method, 0x0006);
+    assert!(send.is_send());
+    assert!(!send.is_data());
+    assert_eq!(send.peer_address(), Some(ip(5555)));
+    assert_eq!(send.payload().as_deref(), Some(&b"hello"[..]));
+
+    // when
+    let mut send_fut = spawn(ind_sender.send(send));
+    assert_ready!(send_fut.poll()).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (message, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(3478));
+    assert_eq!(message.header.class, Class::Indication);
+    assert_eq!(message.header.method, SEND_METHOD);
+    let peer_tlv = &message.attributes[0];
+    assert_eq!(peer_tlv.attribute_type, XorPeerAddress::ID);
+    assert_ne!(
+        XorPeerAddress::decode_value(peer_tlv.value.clone())
+            .unwrap()
+            .0,
+        ip(5555)
+    );
+    let mut attributes = message.xor_socket_addr(XorPeerAddress::ID).attributes;
+    assert_eq!(
+        attributes.extract_attribute::<XorPeerAddress>().unwrap().0,
+        ip(5555)
+    );
+
+    // when
+    let mut receive_fut = spawn(ind_receiver.receive_next());
+    assert_pending!(receive_fut.poll());
+    let data = Indication::data(ip(1234), ip(5555), b"world".to_vec());
+    let message = Message::indication(DATA_METHOD, [0xaf; 12], data.attributes)
+        .xor_socket_addr(XorPeerAddress::ID);
+    ingress_sink.try_send((message, ip(3478))).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let indication = assert_ready!(receive_fut.poll()).unwrap();
+    assert_eq!(indication.farend_addr, ip(3478));
+    assert!(indication.is_data());
+    assert_eq!(indication.peer_address(), Some(ip(5555)));
+    assert_eq!(indication.payload().as_deref(), Some(&b"world"[..]));
+}
+
+#[tokio::test(start_paused = true)]
+async fn deliver_response_to_callback() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let (result_sink, result_source) = std::sync::mpsc::channel();
+
+        // when
+        req_sender.send_request_cb(ip(1111), 42u16, vec![attribute()], move |result| {
+            result_sink.send(result).unwrap();
+        });
+        let (request, addr) = egress_source.recv().await.unwrap();
+        assert_eq!(addr, ip(1111));
+        time::sleep(millisec!(100)).await;
+        assert!(result_source.try_recv().is_err());
+
+        let response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+        ingress_sink.send((response, ip(1111))).await.unwrap();
+        time::sleep(millisec!(100)).await;
+
+        // then
+        let response = result_source.try_recv().unwrap().unwrap();
+        assert!(response.success);
+        assert_eq!(response.attributes, vec![attribute()]);
+        assert!(result_source.try_recv().is_err());
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn submit_requests_and_collect_later() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        // when: two requests are submitted
+        let mut batch = req_sender.batch();
+        batch
+            .submit(ip(1111), 42u16, vec![attribute()])
+            .await
+            .unwrap();
+        batch
+            .submit(ip(2222), 43u16, vec![attribute()])
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 2);
+
+        // and: only the first one is responded to while we're doing something else
+        let (request1, addr1) = egress_source.recv().await.unwrap();
+        assert_eq!(addr1, ip(1111));
+        let (_request2, addr2) = egress_source.recv().await.unwrap();
+        assert_eq!(addr2, ip(2222));
+        let response1 = Message::response(
+            request1.header.method,
+            request1.header.transaction_id,
+            vec![attribute()],
+        );
+        ingress_sink.send((response1, ip(1111))).await.unwrap();
+        time::sleep(sec!(2)).await;
+
+        // then: the first result is a response, the second one timed out in the background
+        let mut results = batch.collect().await.into_iter();
+        let (addr, result) = results.next().unwrap();
+        assert_eq!(addr, ip(1111));
+        let response = result.unwrap();
+        assert!(response.success);
+        assert_eq!(response.attributes, vec![attribute()]);
+        let (addr, result) = results.next().unwrap();
+        assert_eq!(addr, ip(2222));
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        assert!(results.next().is_none());
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn final_retransmission_is_observed() {
+    struct FinalRetransmissionRecorder(Rc<RefCell<Vec<(SocketAddr, Instant)>>>);
+
+    impl TransactionObserver for FinalRetransmissionRecorder {
+        fn on_final_retransmission(&mut self, destination: SocketAddr, _tid: &[u8; 12]) {
+            self.0.borrow_mut().push((destination, Instant::now()));
+        }
+    }
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+    let events = Rc::new(RefCell::new(Vec::new()));
+    processor.set_observer(FinalRetransmissionRecorder(events.clone()));
+
+    let start_time = Instant::now();
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(40), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        let result = req_sender
+            .send_request(ip(1234), 42u16, vec![attribute()])
+            .await;
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+    };
+
+    let receiver_fut = async {
+        for transmission in 1..=7 {
+            egress_source.recv().await.unwrap();
+            // the event fires right before the 7th and last transmission
+            let expected_events = if transmission == 7 { 1 } else { 0 };
+            assert_eq!(
+                events.borrow().len(),
+                expected_events,
+                "transmission {transmission}"
+            );
+        }
+    };
+
+    join!(sender_fut, processor_fut, receiver_fut);
+    assert_eq!(
+        *events.borrow(),
+        vec![(ip(1234), start_time + millisec!(31500))]
+    );
+}
+
+#[test]
+fn binding_request_decodes_all_attributes() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut request_fut = spawn(req_sender.binding_request(ip(3478)));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (request, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(3478));
+    assert_eq!(request.header.method, BINDING_METHOD);
+    assert!(request.attributes.is_empty());
+
+    // when
+    let mut attributes = Vec::new();
+    attributes.append_attribute(XorMappedAddress(ip(5000)));
+    attributes.append_attribute(OtherAddress(ip(3479)));
+    attributes.append_attribute(ResponseOrigin(ip(3478)));
+    attributes.append_attribute(Software("stunny".to_owned()));
+    let response = Message::response(BINDING_METHOD, request.header.transaction_id, attributes)
+        .xor_socket_addr(XorMappedAddress::ID);
+    ingress_sink.try_send((response, ip(3478))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    let result = assert_ready!(request_fut.poll()).unwrap();
+    assert_eq!(
+        result,
+        BindingResult {
+            reflexive_address: ip(5000),
+            xor_mapped_address: Some(ip(5000)),
+            mapped_address: None,
+            other_address: Some(ip(3479)),
+            response_origin: Some(ip(3478)),
+            software: Some("stunny".to_owned()),
+        }
+    );
+}
+
+#[test]
+fn discover_mapped_address_falls_back_to_legacy_attribute() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut request_fut = spawn(req_sender.discover_mapped_address(ip(3478)));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (request, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(3478));
+    assert_eq!(request.header.method, BINDING_METHOD);
+    assert!(request.attributes.is_empty());
+
+    // when: an RFC 3489 server responds with MAPPED-ADDRESS only
+    let mut attributes = Vec::new();
+    attributes.append_attribute(MappedAddress(ip(5000)));
+    let response = Message::response(BINDING_METHOD, request.header.transaction_id, attributes);
+    ingress_sink.try_send((response, ip(3478))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert_eq!(assert_ready!(request_fut.poll()).unwrap(), ip(5000));
+}
+
+#[tokio::test(start_paused = true)]
+async fn discover_reflexive_addresses_of_responding_servers() {
+    use attributes::*;
+    use futures::StreamExt;
+    use std::collections::HashMap;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        4,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let start_time = Instant::now();
+        let stream = req_sender
+            .discover_reflexive([ip(1111), ip(2222), ip(3333), ip(4444)])
+            .await;
+
+        let mut requests = HashMap::new();
+        for _ in 0..4 {
+            let (request, server) = egress_source.recv().await.unwrap();
+            assert_eq!(request.header.method, BINDING_METHOD);
+            requests.insert(server, request.header.transaction_id);
+        }
+
+        // when: 3333 and 1111 succeed, 2222 fails and 4444 doesn't respond
+        for (server, reflexive) in [(ip(3333), ip(5003)), (ip(1111), ip(5001))] {
+            let mut attributes = Vec::new();
+            attributes.append_attribute(XorMappedAddress(reflexive));
+            let response = Message::response(BINDING_METHOD, requests[&server], attributes)
+                .xor_socket_addr(XorMappedAddress::ID);
+            ingress_sink.send((response, server)).await.unwrap();
+        }
+        let mut attributes = Vec::new();
+        attributes.append_attribute(ErrorCode {
+            code: 500,
+            reason: "Server Error".to_owned(),
+        });
+        let response = Message::error(BINDING_METHOD, requests[&ip(2222)], attributes);
+        ingress_sink.send((response, ip(2222))).await.unwrap();
+
+        // then
+        let results: Vec<_> = stream.collect().await;
+        assert_eq!(results.len(), 2);
+        assert!(results.contains(&(ip(1111), ip(5001))));
+        assert!(results.contains(&(ip(3333), ip(5003))));
+        assert_eq!(start_time.elapsed(), sec!(1));
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_request_after_timeout() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let mut request = req_sender.retryable_request(ip(1234), 42u16, vec![attribute()]);
+
+        // when: the first attempt is not responded to
+        let result = request.send().await;
+
+        // then
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        let (first, _) = egress_source.recv().await.unwrap();
+
+        // when: the retry is responded to
+        let (result, _) = join!(request.retry(), async {
+            let (second, addr) = egress_source.recv().await.unwrap();
+            assert_eq!(addr, ip(1234));
+            assert_eq!(second.header.method, 42u16);
+            assert_eq!(second.attributes, vec![attribute()]);
+            assert_ne!(second.header.transaction_id, first.header.transaction_id);
+            let response = Message::response(
+                second.header.method,
+                second.header.transaction_id,
+                vec![attribute()],
+            );
+            ingress_sink.send((response, ip(1234))).await.unwrap();
+        });
+
+        // then
+        let response = result.unwrap();
+        assert!(response.success);
+        assert_eq!(response.attributes, vec![attribute()]);
+        assert!(egress_source.try_recv().is_err());
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_request_while_first_attempt_is_outstanding() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(60), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let mut request = req_sender.retryable_request(ip(1234), 42u16, vec![attribute()]);
+
+        // when: the caller stops waiting for the first attempt before it's answered
+        let result = time::timeout(millisec!(100), request.send()).await;
+
+        // then
+        assert!(result.is_err());
+        let (first, _) = egress_source.recv().await.unwrap();
+
+        // when: the retry is responded to
+        let (result, _) = join!(request.retry(), async {
+            let (second, _) = egress_source.recv().await.unwrap();
+            assert_ne!(second.header.transaction_id, first.header.transaction_id);
+            let response = Message::response(42u16, second.header.transaction_id, vec![]);
+            ingress_sink.send((response, ip(1234))).await.unwrap();
+        });
+
+        // then: the first attempt is not retransmitted any further
+        assert!(result.unwrap().success);
+        assert!(req_sender
+            .outstanding_transactions()
+            .await
+            .unwrap()
+            .is_empty());
+        time::sleep(sec!(40)).await;
+        assert!(egress_source.try_recv().is_err());
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[test]
+fn binding_result_reports_xor_and_plain_addresses_separately() {
+    use attributes::*;
+
+    let mut attributes = Vec::new();
+    attributes.append_attribute(XorMappedAddress(ip(5000)));
+    attributes.append_attribute(MappedAddress(ip(6000)));
+    let response = Response {
+        success: true,
+        attributes,
+        time_elapsed: Duration::ZERO,
+        queue_wait: Duration::ZERO,
+        initial_rto: Duration::ZERO,
+    };
+
+    let result = BindingResult::from_response(response).unwrap();
+    assert_eq!(result.reflexive_address, ip(5000));
+    assert_eq!(result.xor_mapped_address, Some(ip(5000)));
+    assert_eq!(result.mapped_address, Some(ip(6000)));
+    assert_eq!(result.other_address, None);
+    assert_eq!(result.response_origin, None);
+    assert_eq!(result.software, None);
+}
+
+#[test]
+fn error_response_keeps_raw_attributes() {
+    use attributes::*;
+
+    let vendor_attribute = Tlv {
+        attribute_type: 0xc001,
+        value: b"quota exceeded".to_vec(),
+    };
+    let mut attributes = Vec::new();
+    attributes.append_attribute(ErrorCode {
+        code: 508,
+        reason: "Insufficient Capacity".to_owned(),
+    });
+    attributes.push(vendor_attribute.clone());
+    let response = Response {
+        success: false,
+        attributes,
+        time_elapsed: Duration::ZERO,
+        queue_wait: Duration::ZERO,
+        initial_rto: Duration::ZERO,
+    };
+
+    let error = BindingResult::from_response(response).unwrap_err();
+    let TransactionError::ErrorResponse { code, attributes } = error else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!(code.code, 508);
+    assert_eq!(code.reason, "Insufficient Capacity");
+    assert_eq!(attributes.len(), 2);
+    assert_eq!(attributes[0].attribute_type, ErrorCode::ID);
+    assert_eq!(attributes[1], vendor_attribute);
+}
+
+#[test]
+fn connection_limit_fails_requests_to_destination() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (event_sink, event_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request1_fut = spawn(req_sender.send_request(ip(1111), 42u16, vec![]));
+    assert_pending!(request1_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let mut request2_fut = spawn(req_sender.send_request(ip(2222), 42u16, vec![]));
+    assert_pending!(request2_fut.poll());
+    assert_pending!(runner_fut.poll());
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(1111));
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(2222));
+
+    // when
+    event_sink
+        .try_send(TransportEvent::ConnectionLimitReached(ip(2222)))
+        .unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(matches!(
+        assert_ready!(request2_fut.poll()),
+        Err(TransactionError::ConnectionLimitReached)
+    ));
+    assert_pending!(request1_fut.poll());
+}
+
+#[test]
+fn handle_burst_of_responses_in_one_batch() {
+    const BURST: usize = 8;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(BURST);
+    let (ingress_sink, ingress_source) = mpsc::channel(BURST);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: BURST,
+            ingress_batch_size: BURST,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let iterations = processor.iterations.clone();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request_futs: Vec<_> = (0..BURST)
+        .map(|_| spawn(req_sender.send_request(ip(1234), 42u16, vec![attribute()])))
+        .collect();
+    for request_fut in &mut request_futs {
+        assert_pending!(request_fut.poll());
+        assert_pending!(runner_fut.poll());
+    }
+
+    // when
+    for _ in 0..BURST {
+        let (request, _) = egress_source.try_recv().unwrap();
+        let response = Message::response(42u16, request.header.transaction_id, vec![]);
+        ingress_sink.try_send((response, ip(1234))).unwrap();
+    }
+    let iterations_before = iterations.get();
+    assert_pending!(runner_fut.poll());
+
+    // then: one iteration for the burst, and one that finds nothing left to do
+    assert_eq!(iterations.get() - iterations_before, 2);
+    for request_fut in &mut request_futs {
+        assert!(assert_ready!(request_fut.poll()).unwrap().success);
+    }
+}
+
+#[test]
+fn reject_requests_over_capacity() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 2,
+            backpressure: Backpressure::Reject,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut request_futs: Vec<_> = (0..3u16)
+        .map(|i| spawn(req_sender.send_request(ip(1000 + i), 42u16, vec![])))
+        .collect();
+    for request_fut in &mut request_futs[..2] {
+        assert_pending!(request_fut.poll());
+    }
+
+    // then
+    assert!(matches!(
+        assert_ready!(request_futs[2].poll()),
+        Err(TransactionError::TooManyOutstanding)
+    ));
+    assert_pending!(runner_fut.poll());
+    let (request, addr) = egress_source.try_recv().unwrap();
+    assert!(egress_source.try_recv().is_ok());
+
+    // when
+    let response = Message::response(42u16, request.header.transaction_id, vec![]);
+    ingress_sink.try_send((response, addr)).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let mut request_fut = spawn(req_sender.send_request(ip(2000), 42u16, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(2000));
+}
+
+#[test]
+fn reject_requests_when_overloaded() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 10,
+            max_pending_timeouts: 3,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut request_futs: Vec<_> = (0..5u16)
+        .map(|i| spawn(req_sender.send_request(ip(1000 + i), 42u16, vec![])))
+        .collect();
+    for request_fut in &mut request_futs {
+        assert_pending!(request_fut.poll());
+    }
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let mut requests = Vec::new();
+    while let Ok((request, addr)) = egress_source.try_recv() {
+        requests.push((request, addr));
+    }
+    assert_eq!(requests.len(), 3);
+    for request_fut in &mut request_futs[3..] {
+        assert!(matches!(
+            assert_ready!(request_fut.poll()),
+            Err(TransactionError::Overloaded)
+        ));
+    }
+    for request_fut in &mut request_futs[..3] {
+        assert_pending!(request_fut.poll());
+    }
+
+    // when
+    for (request, addr) in requests {
+        let response = Message::response(42u16, request.header.transaction_id, vec![]);
+        ingress_sink.try_send((response, addr)).unwrap();
+    }
+    assert_pending!(runner_fut.poll());
+
+    // then
+    for request_fut in &mut request_futs[..3] {
+        assert!(assert_ready!(request_fut.poll()).unwrap().success);
+    }
+
+    // when
+    let mut request_fut = spawn(req_sender.send_request(ip(2000), 42u16, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(2000));
+    assert_pending!(request_fut.poll());
+}
+
+#[test]
+fn drop_response_with_invalid_fingerprint() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 1,
+            reject_invalid_fingerprint: true,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+
+    // when: response with a corrupted fingerprint
+    let mut response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+    response.append_fingerprint();
+    response.attributes.last_mut().unwrap().value[3] ^= 0xff;
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert_pending!(request_fut.poll());
+
+    // when: response with a valid fingerprint
+    let mut response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+    response.append_fingerprint();
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    let response = assert_ready!(request_fut.poll()).unwrap();
+    assert!(response.success);
+    assert_eq!(response.attributes[0], attribute());
+}
+
+#[test]
+fn append_fingerprint_to_outgoing_messages() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, ind_sender, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 1,
+            append_fingerprint: true,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut indication_fut = spawn(ind_sender.send_indication(ip(1234), 42u16, vec![attribute()]));
+    assert_ready!(indication_fut.poll()).unwrap();
+    let mut request_fut = spawn(req_sender.send_request_with_integrity(
+        ip(1234),
+        42u16,
+        vec![attribute()],
+        b"key".to_vec(),
+    ));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then: requests are handled before indications
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(request.attributes.len(), 3);
+    assert_eq!(request.attributes[1].attribute_type, 0x0008);
+    assert_eq!(request.attributes[2].attribute_type, 0x8028);
+    assert_eq!(request.verify_fingerprint(), Some(true));
+    assert_eq!(request.verify_message_integrity(b"key"), Some(true));
+
+    let (indication, _) = egress_source.try_recv().unwrap();
+    assert_eq!(indication.attributes.len(), 2);
+    assert_eq!(indication.attributes[1].attribute_type, 0x8028);
+    assert_eq!(indication.verify_fingerprint(), Some(true));
+
+    // when: response with a corrupted fingerprint
+    let mut response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+    response.append_fingerprint();
+    response.attributes.last_mut().unwrap().value[0] ^= 0xff;
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert_pending!(request_fut.poll());
+
+    // when: response without fingerprint
+    let mut response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+    response.append_message_integrity(b"key");
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert!(assert_ready!(request_fut.poll()).unwrap().success);
+
+    // when
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(request.attributes.len(), 1);
+    assert_eq!(request.verify_fingerprint(), Some(true));
+}
+
+#[test]
+fn append_configured_software_to_requests() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source: mpsc::channel(1).1,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 2,
+            software: Some("stunny test".to_owned()),
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut request1_fut = spawn(req_sender.send_request_with_integrity(
+        ip(1234),
+        42u16,
+        vec![attribute()],
+        b"key".to_vec(),
+    ));
+    assert_pending!(request1_fut.poll());
+    let mut request2_fut =
+        spawn(req_sender.send_request(ip(1234), 42u16, vec![Software::tlv("caller's own")]));
+    assert_pending!(request2_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then: signed along with the other attributes
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(request.attributes.len(), 4);
+    assert_eq!(request.attributes[0], attribute());
+    assert_eq!(
+        request.attributes[1].as_software().as_deref(),
+        Some("stunny test")
+    );
+    assert_eq!(request.verify_message_integrity(b"key"), Some(true));
+
+    // then: not duplicated
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(request.attributes.len(), 1);
+    assert_eq!(
+        request.attributes[0].as_software().as_deref(),
+        Some("caller's own")
+    );
+}
+
+#[test]
+fn reject_caller_supplied_fingerprint_when_appended_automatically() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source: mpsc::channel(1).1,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 1,
+            append_fingerprint: true,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut attributes = vec![attribute()];
+    attributes.append_attribute(Fingerprint(0xdeadbeef));
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, attributes));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(matches!(
+        assert_ready!(request_fut.poll()),
+        Err(TransactionError::ReservedAttribute(0x8028))
+    ));
+    assert!(egress_source.try_recv().is_err());
+}
+
+#[test]
+fn reject_request_with_attributes_exceeding_cap() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source: mpsc::channel(1).1,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 2,
+            max_request_attributes_size: Some(100),
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let padding = |len: usize| Tlv {
+        attribute_type: 0x0026,
+        value: vec![0; len],
+    };
+
+    // when: PADDING that takes 104 bytes on the wire
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![padding(97)]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(matches!(
+        assert_ready!(request_fut.poll()),
+        Err(TransactionError::AttributesTooLarge {
+            size: 104,
+            limit: 100
+        })
+    ));
+    assert!(egress_source.try_recv().is_err());
+
+    // when: PADDING that fits exactly
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![padding(96)]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert_pending!(request_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(request.attributes, vec![padding(96)]);
+}
+
+#[test]
+fn omit_fingerprint_per_request() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 2,
+            append_fingerprint: true,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut unsigned_fut = spawn(req_sender.send_request_signed(
+        ip(1234),
+        42u16,
+        vec![attribute()],
+        Signing {
+            integrity_key: None,
+            fingerprint: Some(false),
+        },
+    ));
+    assert_pending!(unsigned_fut.poll());
+    let mut signed_fut = spawn(req_sender.send_request_signed(
+        ip(1234),
+        42u16,
+        vec![attribute()],
+        Signing {
+            integrity_key: Some(b"key".to_vec()),
+            fingerprint: Some(false),
+        },
+    ));
+    assert_pending!(signed_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (unsigned, _) = egress_source.try_recv().unwrap();
+    assert_eq!(unsigned.attributes.len(), 1);
+    assert_eq!(unsigned.verify_fingerprint(), None);
+
+    let (signed, _) = egress_source.try_recv().unwrap();
+    assert_eq!(signed.attributes.len(), 2);
+    assert_eq!(signed.attributes[1].attribute_type, 0x0008);
+    assert_eq!(signed.verify_fingerprint(), None);
+    assert_eq!(signed.verify_message_integrity(b"key"), Some(true));
+
+    // when
+    for request in [unsigned, signed] {
+        let response = Message::response(42u16, request.header.transaction_id, vec![]);
+        ingress_sink.try_send((response, ip(1234))).unwrap();
+    }
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(assert_ready!(unsigned_fut.poll()).unwrap().success);
+    assert!(assert_ready!(signed_fut.poll()).unwrap().success);
+}
+
+#[test]
+fn custom_validator_rejects_response_without_attribute() {
+    use attributes::LookupError;
+
+    struct RequireSoftware;
+
+    impl ResponseValidator for RequireSoftware {
+        fn validate(
+            &mut self,
+            request: &RequestDetails,
+            response: &Message,
+            source: SocketAddr,
+        ) -> Validation {
+            if !response
+                .attributes
+                .iter()
+                .any(|tlv| tlv.attribute_type == 0x8022)
+            {
+                return Validation::Reject(TransactionError::MalformedResponse(
+                    LookupError::NotFound(0x8022),
+                ));
+            }
+            DefaultResponseValidator.validate(request, response, source)
+        }
+    }
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.set_response_validator(RequireSoftware);
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request1_fut = spawn(req_sender.send_request(ip(1111), 42u16, vec![]));
+    let mut request2_fut = spawn(req_sender.send_request(ip(2222), 42u16, vec![]));
+    assert_pending!(request1_fut.poll());
+    assert_pending!(request2_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request1, _) = egress_source.try_recv().unwrap();
+    let (request2, _) = egress_source.try_recv().unwrap();
+
+    // when
+    let response = Message::response(42u16, request1.header.transaction_id, vec![]);
+    ingress_sink.try_send((response, ip(1111))).unwrap();
+    let response = Message::response(42u16, request2.header.transaction_id, vec![attribute()]);
+    ingress_sink.try_send((response, ip(2222))).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(matches!(
+        assert_ready!(request1_fut.poll()),
+        Err(TransactionError::MalformedResponse(LookupError::NotFound(
+            0x8022
+        )))
+    ));
+    let response = assert_ready!(request2_fut.poll()).unwrap();
+    assert_eq!(response.attributes, vec![attribute()]);
+}
+
+#[test]
+fn drop_response_with_invalid_message_integrity() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request_fut =
+        spawn(req_sender.send_request_with_integrity(ip(1234), 42u16, vec![], b"key".to_vec()));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(request.verify_message_integrity(b"key"), Some(true));
+
+    // when: response signed with the wrong key
+    let mut response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+    response.append_message_integrity(b"other key");
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert_pending!(request_fut.poll());
+
+    // when: response signed with the right key
+    let mut response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+    response.append_message_integrity(b"key");
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    let response = assert_ready!(request_fut.poll()).unwrap();
+    assert!(response.success);
+    assert_eq!(response.attributes[0], attribute());
+}
+
+#[test]
+fn drop_unsigned_response_to_signed_request_unless_allowed_error() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request_fut =
+        spawn(req_sender.send_request_with_integrity(ip(1234), 42u16, vec![], b"key".to_vec()));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+    let error_response = |code: u16| {
+        let mut attributes = Vec::new();
+        attributes.append_attribute(ErrorCode {
+            code,
+            reason: String::new(),
+        });
+        Message::error(42u16, request.header.transaction_id, attributes)
+    };
+
+    // when: unsigned success response
+    let response = Message::response(42u16, request.header.transaction_id, vec![attribute()]);
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert_pending!(request_fut.poll());
+
+    // when: unsigned error that requires MESSAGE-INTEGRITY
+    ingress_sink
+        .try_send((error_response(500), ip(1234)))
+        .unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert_pending!(request_fut.poll());
+
+    // when: unsigned 401
+    ingress_sink
+        .try_send((error_response(401), ip(1234)))
+        .unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    let response = assert_ready!(request_fut.poll()).unwrap();
+    assert!(!response.success);
+    assert_eq!(response.error_code().unwrap().code, 401);
+}
+
+#[tokio::test(start_paused = true)]
+async fn estimate_loss_rate_per_destination() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+    let metrics = processor.metrics();
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(100), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        // responds to the transmission number `answered`, ignoring the preceding ones
+        macro_rules! transaction {
+            ($destination:expr, $answered:expr) => {{
+                let respond = async {
+                    for _ in 1..$answered {
+                        egress_source.recv().await.unwrap();
+                    }
+                    let (request, addr) = egress_source.recv().await.unwrap();
+                    let response = Message::response(42u16, request.header.transaction_id, vec![]);
+                    ingress_sink.send((response, addr)).await.unwrap();
+                };
+                let (result, _) = join!(
+                    req_sender.send_request($destination, 42u16, vec![]),
+                    respond
+                );
+                assert!(result.unwrap().success);
+            }};
+        }
+
+        // when
+        transaction!(ip(1111), 3);
+        transaction!(ip(2222), 1);
+
+        // then
+        let loss_rate = metrics.loss_rate(ip(1111)).unwrap();
+        assert!((0.66..0.67).contains(&loss_rate), "{loss_rate}");
+        assert_eq!(metrics.loss_rate(ip(2222)), Some(0.0));
+        assert_eq!(metrics.loss_rate(ip(3333)), None);
+
+        // when: older samples have decayed by half
+        time::sleep(LOSS_HALF_LIFE).await;
+        transaction!(ip(1111), 1);
+
+        // then: 1 / (1.5 + 1)
+        let loss_rate = metrics.loss_rate(ip(1111)).unwrap();
+        assert!((0.39..0.41).contains(&loss_rate), "{loss_rate}");
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn pace_out_requests_at_configured_rate() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_outstanding_requests: 10,
+            rate_limit: Some(RateLimit {
+                requests_per_second: 10,
+                max_queued: 2,
+            }),
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(sec!(5)),
+    );
+    assert_eq!(req_sender.rate_limit().unwrap().interval(), millisec!(100));
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(10), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let start_time = Instant::now();
+        let mut pending_responses = Vec::new();
+        for port in 1..=4 {
+            pending_responses.push(
+                req_sender
+                    .submit_request(ip(port), 42u16, vec![])
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // then: the burst exceeding the queue is rejected
+        let rejected = pending_responses.pop().unwrap();
+        assert!(matches!(rejected.await, Err(TransactionError::Overloaded)));
+
+        // and: the rest is sent one by one
+        for (i, port) in (1..=3).enumerate() {
+            let (request, addr) = egress_source.recv().await.unwrap();
+            assert_eq!(addr, ip(port));
+            assert_eq!(request.header.class, Class::Request);
+            assert_eq!(start_time.elapsed(), millisec!(100) * i as u32);
+        }
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[test]
+fn reject_non_cryptographic_rng_in_strict_mode() {
+    let channels = || MessageChannels {
+        egress_sink: mpsc::channel(1).0,
+        ingress_source: mpsc::channel(1).1,
+        event_source: mpsc::channel(1).1,
+    };
+    let strict_config = Config {
+        require_cryptographic_rng: true,
+        ..Default::default()
+    };
+
+    let result = try_setup_transactions_with_rng(
+        channels(),
+        strict_config.clone(),
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+        TransactionIdRng::non_cryptographic(rand::rngs::mock::StepRng::new(0, 1)),
+    );
+    assert!(matches!(result, Err(SetupError::InsecureRng)));
+
+    let result = try_setup_transactions_with_rng(
+        channels(),
+        strict_config,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+        TransactionIdRng::cryptographic(rand::rngs::OsRng),
+    );
+    assert!(result.is_ok());
+
+    let result = try_setup_transactions_with_rng(
+        channels(),
+        Config::default(),
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+        TransactionIdRng::non_cryptographic(rand::rngs::mock::StepRng::new(0, 1)),
+    );
+    assert!(result.is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn count_transaction_outcomes() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        3,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+    let metrics = processor.metrics();
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let mut batch = req_sender.batch();
+        for port in [1111, 2222, 3333] {
+            batch.submit(ip(port), 42u16, vec![]).await.unwrap();
+        }
+        let (request1, _) = egress_source.recv().await.unwrap();
+        let (request2, _) = egress_source.recv().await.unwrap();
+        let _ = egress_source.recv().await.unwrap();
+
+        // when
+        let response = Message::response(42u16, request1.header.transaction_id, vec![]);
+        ingress_sink.send((response, ip(1111))).await.unwrap();
+        let response = Message::error(42u16, request2.header.transaction_id, vec![]);
+        ingress_sink.send((response, ip(2222))).await.unwrap();
+        let response = Message::response(42u16, [0xab; 12], vec![]);
+        ingress_sink.send((response, ip(4444))).await.unwrap();
+        let _ = batch.collect().await;
+
+        // then
+        assert_eq!(metrics.requests_sent(), 3);
+        assert_eq!(metrics.retransmissions(), 0);
+        assert_eq!(metrics.success_responses(), 1);
+        assert_eq!(metrics.error_responses(), 1);
+        assert_eq!(metrics.timeouts(), 1);
+        assert_eq!(metrics.orphaned_responses(), 1);
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retransmissions_are_counted() {
+    let (egress_sink, mut egress_source) = mpsc::channel(20);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        2,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+    let metrics = processor.metrics();
+    assert_eq!(metrics.retransmissions(), 0);
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(45), processor.run()).await;
+    };
+
+    let sender_fut = async {
+        let (result1, result2) = join!(
+            req_sender.send_request(ip(1111), 42u16, vec![attribute()]),
+            req_sender.send_request(ip(2222), 42u16, vec![attribute()]),
+        );
+        assert!(matches!(result1, Err(TransactionError::Timeout)));
+        assert!(matches!(result2, Err(TransactionError::Timeout)));
+    };
+
+    join!(sender_fut, processor_fut);
+
+    // 7 transmissions per request, of which 6 are retransmissions
+    assert_eq!(metrics.retransmissions(), 12);
+    let mut transmissions = 0;
+    while egress_source.try_recv().is_ok() {
+        transmissions += 1;
+    }
+    assert_eq!(transmissions, 14);
+}
+
+#[tokio::test(start_paused = true)]
+async fn initial_rto_reflects_rtt_samples() {
+    struct DoubleRtt(Option<Duration>);
+
+    impl RtoPolicy for DoubleRtt {
+        fn submit_rtt(&mut self, _remote_addr: SocketAddr, rtt: Duration) {
+            self.0 = Some(rtt);
+        }
+
+        fn calculate_rto(&mut self, _remote_addr: SocketAddr, attempts: usize) -> Option<Duration> {
+            match attempts {
+                0 => Some(self.0.map_or(sec!(1), |rtt| rtt * 2)),
+                _ => None,
+            }
+        }
+    }
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DoubleRtt(None),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        for expected_initial_rto in [sec!(1), millisec!(200)] {
+            let (result, _) = join!(req_sender.send_request(ip(1234), 42u16, vec![]), async {
+                let (request, _) = egress_source.recv().await.unwrap();
+                time::sleep(millisec!(100)).await;
+                let response = Message::response(42u16, request.header.transaction_id, vec![]);
+                ingress_sink.send((response, ip(1234))).await.unwrap();
+            });
+            assert_eq!(result.unwrap().initial_rto, expected_initial_rto);
+        }
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[test]
+fn send_request_after_shutdown() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+
+    let mut list_fut = spawn(req_sender.outstanding_transactions());
+    assert_pending!(list_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let outstanding = assert_ready!(list_fut.poll()).unwrap();
+    assert_eq!(outstanding.len(), 1);
+    assert_eq!(outstanding[0].destination, ip(1234));
+    assert_eq!(outstanding[0].method, 42u16);
+    assert_eq!(outstanding[0].transaction_id, request.header.transaction_id);
+    assert_eq!(outstanding[0].attempts_made, 1);
+
+    // when
+    let mut shutdown_fut = spawn(req_sender.shutdown());
+    assert_ready!(shutdown_fut.poll());
+
+    // then: new requests fail immediately, even before the processor has handled the shutdown
+    let mut late_request_fut = spawn(req_sender.send_request(ip(5678), 42u16, vec![]));
+    assert!(matches!(
+        assert_ready!(late_request_fut.poll()),
+        Err(TransactionError::Shutdown)
+    ));
+    let mut list_fut = spawn(req_sender.outstanding_transactions());
+    assert!(assert_ready!(list_fut.poll()).unwrap().is_empty());
+
+    // and: outstanding requests are failed and the processor exits
+    assert_ready!(runner_fut.poll()).unwrap();
+    assert!(matches!(
+        assert_ready!(request_fut.poll()),
+        Err(TransactionError::Shutdown)
+    ));
+    assert!(egress_source.try_recv().is_err());
+}
+
+#[test]
+fn reject_oversized_indication() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (_, ind_sender, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_indication_size: Some(1000),
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let data = |len: usize| Tlv {
+        attribute_type: 0x0013,
+        value: vec![0xab; len],
+    };
+
+    // when: Send indication with DATA that doesn't fit
+    let mut indication_fut = spawn(ind_sender.send_indication(ip(3478), 0x0006, vec![data(977)]));
+
+    // then
+    assert!(matches!(
+        assert_ready!(indication_fut.poll()),
+        Err(TransactionError::PayloadTooLarge {
+            size: 1004,
+            limit: 1000
+        })
+    ));
+    assert_pending!(runner_fut.poll());
+    assert!(egress_source.try_recv().is_err());
+
+    // when: Send indication that fits exactly
+    let mut indication_fut = spawn(ind_sender.send_indication(ip(3478), 0x0006, vec![data(976)]));
+
+    // then
+    assert_ready!(indication_fut.poll()).unwrap();
+    assert_pending!(runner_fut.poll());
+    assert_eq!(
+        egress_source.try_recv().unwrap().0.attributes,
+        vec![data(976)]
+    );
+}
+
+#[test]
+fn count_fingerprint_towards_indication_size() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (_, ind_sender, _, mut processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            max_indication_size: Some(1000),
+            append_fingerprint: true,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let data = |len: usize| Tlv {
+        attribute_type: 0x0013,
+        value: vec![0xab; len],
+    };
+
+    // when: Send indication that fits only without FINGERPRINT
+    let mut indication_fut = spawn(ind_sender.send_indication(ip(3478), 0x0006, vec![data(976)]));
+
+    // then
+    assert!(matches!(
+        assert_ready!(indication_fut.poll()),
+        Err(TransactionError::PayloadTooLarge {
+            size: 1008,
+            limit: 1000
+        })
+    ));
+    assert_pending!(runner_fut.poll());
+    assert!(egress_source.try_recv().is_err());
+
+    // when: Send indication that fits exactly with FINGERPRINT
+    let mut indication_fut = spawn(ind_sender.send_indication(ip(3478), 0x0006, vec![data(968)]));
+
+    // then
+    assert_ready!(indication_fut.poll()).unwrap();
+    assert_pending!(runner_fut.poll());
+    let (indication, _) = egress_source.try_recv().unwrap();
+    assert_eq!(indication.attributes.len(), 2);
+    assert_eq!(indication.verify_fingerprint(), Some(true));
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_over_tcp_after_truncated_udp_response() {
+    let (udp_egress_sink, mut udp_egress_source) = mpsc::channel(10);
+    let (_udp_ingress_sink, udp_ingress_source) = mpsc::channel(10);
+    let (udp_event_sink, udp_event_source) = mpsc::channel(10);
+    let (udp_sender, _, _, udp_processor) = setup_transactions(
+        MessageChannels {
+            egress_sink: udp_egress_sink,
+            ingress_source: udp_ingress_source,
+            event_source: udp_event_source,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(sec!(5)),
+    );
+    let (tcp_egress_sink, mut tcp_egress_source) = mpsc::channel(10);
+    let (tcp_ingress_sink, tcp_ingress_source) = mpsc::channel(10);
+    let (tcp_sender, _, _, tcp_processor) = setup_transactions(
+        MessageChannels {
+            egress_sink: tcp_egress_sink,
+            ingress_source: tcp_ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(sec!(5)),
+    );
+    let composite = CompositeRequestSender {
+        udp: udp_sender,
+        tcp: tcp_sender,
+        tls: None,
+    };
+    let server = SocketAddr::from((Ipv4Addr::LOCALHOST, 3478));
+
+    let processors_fut = async move {
+        let _ = join!(
+            time::timeout(sec!(10), udp_processor.run()),
+            time::timeout(sec!(10), tcp_processor.run())
+        );
+    };
+
+    let test_fut = async move {
+        let request_fut =
+            composite.udp_request("stun:127.0.0.1:3478", BINDING_METHOD, vec![attribute()]);
+        let server_fut = async {
+            let (request, addr) = udp_egress_source.recv().await.unwrap();
+            assert_eq!(addr, server);
+            udp_event_sink
+                .send(TransportEvent::Truncated {
+                    remote_addr: server,
+                    transaction_id: request.header.transaction_id,
+                })
+                .await
+                .unwrap();
+
+            let (request, addr) = tcp_egress_source.recv().await.unwrap();
+            assert_eq!(addr, server);
+            assert_eq!(request.header.method, BINDING_METHOD);
+            assert_eq!(request.attributes, vec![attribute()]);
+            let response = Message::response(BINDING_METHOD, request.header.transaction_id, vec![]);
+            tcp_ingress_sink.send((response, server)).await.unwrap();
+        };
+        let (result, _) = join!(request_fut, server_fut);
+        assert!(result.unwrap().success);
+    };
+
+    join!(processors_fut, test_fut);
+}
+
+#[test]
+fn fail_requests_when_transport_closes() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (event_sink, event_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request1_fut = spawn(req_sender.send_request(ip(1111), 42u16, vec![]));
+    assert_pending!(request1_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let mut request2_fut = spawn(req_sender.send_request(ip(2222), 42u16, vec![]));
+    assert_pending!(request2_fut.poll());
+    assert_pending!(runner_fut.poll());
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(1111));
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(2222));
+
+    // when: the transport closes a connection and exits
+    event_sink
+        .try_send(TransportEvent::ConnectionClosed(ip(1111)))
+        .unwrap();
+    drop(event_sink);
+    drop(ingress_sink);
+
+    // then
+    assert!(matches!(
+        assert_ready!(runner_fut.poll()),
+        Err(TransactionError::ChannelClosed)
+    ));
+    assert!(matches!(
+        assert_ready!(request1_fut.poll()),
+        Err(TransactionError::ConnectionClosed)
+    ));
+    assert!(matches!(
+        assert_ready!(request2_fut.poll()),
+        Err(TransactionError::ChannelClosed)
+    ));
+}
+
+#[test]
+fn fail_requests_when_connection_is_lost() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (event_sink, event_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request1_fut = spawn(req_sender.send_request(ip(1111), 42u16, vec![]));
+    assert_pending!(request1_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let mut request2_fut = spawn(req_sender.send_request(ip(2222), 42u16, vec![]));
+    assert_pending!(request2_fut.poll());
+    assert_pending!(runner_fut.poll());
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(1111));
+    assert_eq!(egress_source.try_recv().unwrap().1, ip(2222));
+
+    // when
+    event_sink
+        .try_send(TransportEvent::ConnectionLost(ip(1111)))
+        .unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(matches!(
+        assert_ready!(request1_fut.poll()),
+        Err(TransactionError::ConnectionLost)
+    ));
+    assert_pending!(request2_fut.poll());
+}
+
+#[test]
+fn decode_unauthenticated_challenge() {
+    use attributes::*;
+
+    let mut attributes = Vec::new();
+    attributes.append_attribute(ErrorCode {
+        code: 401,
+        reason: "Unauthenticated".to_owned(),
+    });
+    attributes.append_attribute(Realm("example.org".to_owned()));
+    attributes.append_attribute(Nonce(
+        "obMatJos2AAACf//499k954d6OL34oL9FSTvy64sA".to_owned(),
+    ));
+    attributes.append_attribute(PasswordAlgorithms(vec![PasswordAlgorithm {
+        algorithm: PasswordAlgorithm::SHA256,
+        parameters: Vec::new(),
+    }]));
+    let response = Response {
+        success: false,
+        attributes,
+        time_elapsed: Duration::ZERO,
+        queue_wait: Duration::ZERO,
+        initial_rto: Duration::ZERO,
+    };
+
+    let challenge = response.challenge().unwrap();
+    assert_eq!(challenge.realm, "example.org");
+    assert_eq!(challenge.nonce, "obMatJos2AAACf//499k954d6OL34oL9FSTvy64sA");
+    assert_eq!(
+        challenge.password_algorithms,
+        vec![PasswordAlgorithm {
+            algorithm: PasswordAlgorithm::SHA256,
+            parameters: Vec::new(),
+        }]
+    );
+    // the response itself is left intact
+    assert_eq!(response.attributes.len(), 4);
+
+    let mut attributes = Vec::new();
+    attributes.append_attribute(ErrorCode {
+        code: 400,
+        reason: "Bad Request".to_owned(),
+    });
+    attributes.append_attribute(Realm("example.org".to_owned()));
+    attributes.append_attribute(Nonce("abc".to_owned()));
+    let response = Response {
+        success: false,
+        attributes,
+        time_elapsed: Duration::ZERO,
+        queue_wait: Duration::ZERO,
+        initial_rto: Duration::ZERO,
+    };
+    assert!(response.challenge().is_none());
+}
+
+#[test]
+fn decode_software_and_error_code_of_any_response() {
+    use attributes::*;
+
+    let mut attributes = Vec::new();
+    attributes.append_attribute(ErrorCode {
+        code: 420,
+        reason: "Unknown Attribute".to_owned(),
+    });
+    attributes.append_attribute(Software("stunny-server".to_owned()));
+    let response = Response {
+        success: false,
+        attributes,
+        time_elapsed: Duration::ZERO,
+        queue_wait: Duration::ZERO,
+        initial_rto: Duration::ZERO,
+    };
+    assert_eq!(response.software().as_deref(), Some("stunny-server"));
+    let error_code = response.error_code().unwrap();
+    assert_eq!(error_code.code, 420);
+    assert_eq!(error_code.reason, "Unknown Attribute");
+    // the response itself is left intact
+    assert_eq!(response.attributes.len(), 2);
+
+    let mut attributes = Vec::new();
+    attributes.append_attribute(XorMappedAddress(ip(3478)));
+    attributes.append_attribute(Software("stunny-server".to_owned()));
+    let response = Response {
+        success: true,
+        attributes,
+        time_elapsed: Duration::ZERO,
+        queue_wait: Duration::ZERO,
+        initial_rto: Duration::ZERO,
+    };
+    assert_eq!(response.software().as_deref(), Some("stunny-server"));
+    assert!(response.error_code().is_none());
+}
+
+#[test]
+fn flag_unknown_comprehension_required_attributes() {
+    use attributes::*;
+
+    let mut attributes = Vec::new();
+    attributes.append_attribute(XorMappedAddress(ip(3478)));
+    attributes.push(Tlv {
+        attribute_type: 0x7777,
+        value: vec![0; 4],
+    });
+    attributes.append_attribute(Software("stunny-server".to_owned()));
+    attributes.push(Tlv {
+        attribute_type: 0xc057,
+        value: vec![0; 4],
+    });
+    let response = Response {
+        success: true,
+        attributes,
+        time_elapsed: Duration::ZERO,
+        queue_wait: Duration::ZERO,
+        initial_rto: Duration::ZERO,
+    };
+
+    let known = [XorMappedAddress::ID];
+    assert_eq!(
+        response.comprehension_required_unknown(&known),
+        vec![0x7777]
+    );
+    let optional: Vec<u16> = response
+        .attributes
+        .iter()
+        .map(|tlv| tlv.attribute_type)
+        .filter(|&t| AttributeClass::of(t, &known) == AttributeClass::UnknownOptional)
+        .collect();
+    assert_eq!(optional, vec![Software::ID, 0xc057]);
+
+    assert!(response
+        .comprehension_required_unknown(&[XorMappedAddress::ID, 0x7777])
+        .is_empty());
+}
+
+#[tokio::test]
+async fn confirm_indication_delivery() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (event_sink, event_source) = mpsc::channel(10);
+    let (_, ind_sender, _, processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source,
+        },
+        Config {
+            max_outstanding_requests: 1,
+            transport_confirms_delivery: true,
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut indication_fut =
+        spawn(ind_sender.send_indication_confirmed(ip(1234), 0x0006, vec![attribute()]));
+    assert_pending!(indication_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then: not confirmed until the transport reports delivery
+    let (indication, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(1234));
+    assert_eq!(indication.header.class, Class::Indication);
+    assert_pending!(indication_fut.poll());
+
+    // when
+    event_sink
+        .try_send(TransportEvent::Delivered {
+            remote_addr: ip(1234),
+            transaction_id: indication.header.transaction_id,
+        })
+        .unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert_ready!(indication_fut.poll()).unwrap();
+}
+
+#[test]
+fn fail_confirmed_indication_if_transport_doesnt_confirm_delivery() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_, ind_sender, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source: mpsc::channel(1).1,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut indication_fut =
+        spawn(ind_sender.send_indication_confirmed(ip(1234), 0x0006, vec![attribute()]));
+
+    // then
+    assert!(matches!(
+        assert_ready!(indication_fut.poll()),
+        Err(TransactionError::DeliveryConfirmationUnsupported)
+    ));
+    assert_pending!(runner_fut.poll());
+    assert!(egress_source.try_recv().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_all_outstanding_requests() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        4,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(60), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let mut batch = req_sender.batch();
+        for port in [1111, 2222, 3333] {
+            batch.submit(ip(port), 42u16, vec![]).await.unwrap();
+        }
+        for _ in 0..3 {
+            egress_source.recv().await.unwrap();
+        }
+        assert_eq!(
+            req_sender.outstanding_transactions().await.unwrap().len(),
+            3
+        );
+
+        // when
+        req_sender.cancel_all().await.unwrap();
+
+        // then: requests submitted afterwards are unaffected
+        let mut late_batch = req_sender.batch();
+        late_batch.submit(ip(4444), 42u16, vec![]).await.unwrap();
+        assert_eq!(egress_source.recv().await.unwrap().1, ip(4444));
+        let outstanding = req_sender.outstanding_transactions().await.unwrap();
+        assert_eq!(outstanding.len(), 1);
+        assert_eq!(outstanding[0].destination, ip(4444));
+
+        // and: all earlier requests are cancelled
+        for (_, result) in batch.collect().await {
+            assert!(matches!(result, Err(TransactionError::Cancelled)));
+        }
+
+        // and: only the remaining request is retransmitted
+        let (_, result) = late_batch.collect().await.into_iter().next().unwrap();
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        while let Ok((_, addr)) = egress_source.try_recv() {
+            assert_eq!(addr, ip(4444));
+        }
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_single_request() {
+    let (egress_sink, mut egress_source) = mpsc::channel(20);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        4,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(60), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let cancelled = req_sender
+            .submit_request(ip(1111), 42u16, vec![])
+            .await
+            .unwrap();
+        let answered = req_sender
+            .submit_request(ip(2222), 42u16, vec![])
+            .await
+            .unwrap();
+        let (cancelled_request, _) = egress_source.recv().await.unwrap();
+        let (answered_request, _) = egress_source.recv().await.unwrap();
+
+        // when
+        cancelled.cancel_handle().cancel().await.unwrap();
+
+        // then
+        assert!(matches!(cancelled.await, Err(TransactionError::Cancelled)));
+        let outstanding = req_sender.outstanding_transactions().await.unwrap();
+        assert_eq!(outstanding.len(), 1);
+        assert_eq!(outstanding[0].destination, ip(2222));
+
+        // when: a late response to the cancelled request arrives
+        let response = Message::response(42u16, cancelled_request.header.transaction_id, vec![]);
+        ingress_sink.send((response, ip(1111))).await.unwrap();
+
+        // and: the other request is answered before it's cancelled
+        let cancel_handle = answered.cancel_handle();
+        let response = Message::response(42u16, answered_request.header.transaction_id, vec![]);
+        ingress_sink.send((response, ip(2222))).await.unwrap();
+        time::sleep(millisec!(100)).await;
+        cancel_handle.cancel().await.unwrap();
+
+        // then: the response wins and cancelling is a no-op
+        assert!(answered.await.unwrap().success);
+
+        // and: the cancelled request is never retransmitted
+        time::sleep(sec!(40)).await;
+        assert!(egress_source.try_recv().is_err());
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[test]
+fn retransmissions_follow_manual_clock() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+    let clock = processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![attribute()]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+
+    // when: the clock stops just short of the RTO
+    clock.advance(millisec!(499));
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(egress_source.try_recv().is_err());
+
+    // when
+    clock.advance(millisec!(1));
+    assert!(runner_fut.is_woken());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (retransmission, _) = egress_source.try_recv().unwrap();
+    assert_eq!(
+        retransmission.header.transaction_id,
+        request.header.transaction_id
+    );
+    assert!(egress_source.try_recv().is_err());
+
+    // when: the clock jumps past several retransmissions at once
+    clock.advance(millisec!(3000));
+    assert_pending!(runner_fut.poll());
+
+    // then: only the one that was due is sent, the next is scheduled relative to the new time
+    assert!(egress_source.try_recv().is_ok());
+    assert!(egress_source.try_recv().is_err());
+
+    // when: the remaining timeouts expire
+    for delay in [2000, 4000, 8000, 16000, 32000] {
+        clock.advance(millisec!(delay));
+        assert_pending!(runner_fut.poll());
+    }
+
+    // then
+    for _ in 0..4 {
+        assert!(egress_source.try_recv().is_ok());
+    }
+    assert!(egress_source.try_recv().is_err());
+    assert!(matches!(
+        assert_ready!(request_fut.poll()),
+        Err(TransactionError::Timeout)
+    ));
+}
+
+#[test]
+fn per_request_timeout_follows_manual_clock() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        2,
+        NoRetransmissionsConstTimeout::new(sec!(60)),
+    );
+    let clock = processor.use_manual_clock();
+    // far from the system time
+    clock.advance(sec!(3600));
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut answered_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![attribute()]));
+    let mut timed_out_fut =
+        spawn(req_sender.send_request_with_timeout(ip(1234), 42u16, vec![attribute()], sec!(2)));
+    assert_pending!(answered_fut.poll());
+    assert_pending!(timed_out_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, source) = egress_source.try_recv().unwrap();
+    assert!(egress_source.try_recv().is_ok());
+
+    // when
+    clock.advance(millisec!(1999));
+    ingress_sink
+        .try_send((
+            Message::response(42u16, request.header.transaction_id, vec![]),
+            source,
+        ))
+        .unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then: the queue wait is measured with the manual clock too
+    let response = assert_ready!(answered_fut.poll()).unwrap();
+    assert!(
+        response.queue_wait < millisec!(1),
+        "{:?}",
+        response.queue_wait
+    );
+    assert_pending!(timed_out_fut.poll());
+
+    // when
+    clock.advance(millisec!(1));
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(matches!(
+        assert_ready!(timed_out_fut.poll()),
+        Err(TransactionError::Timeout)
+    ));
+}
+
+#[cfg(feature = "ice")]
+#[test]
+fn connectivity_check_sends_signed_binding_request() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let check = ConnectivityCheck {
+        local: Candidate {
+            address: ip(5000),
+            priority: (126 << 24) | (65535 << 8) | 255,
+        },
+        remote: Candidate {
+            address: ip(6000),
+            priority: (100 << 24) | (65535 << 8) | 255,
+        },
+        credentials: IceCredentials {
+            local_ufrag: "LFRG".to_owned(),
+            remote_ufrag: "RFRG".to_owned(),
+            remote_password: "remote-password".to_owned(),
+        },
+        role: Role::Controlling {
+            tie_breaker: 0x0123456789abcdef,
+        },
+        nominate: true,
+    };
+
+    // when
+    let mut check_fut = spawn(req_sender.connectivity_check(&check));
+    assert_pending!(check_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let (request, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(6000));
+    assert_eq!(request.header.class, Class::Request);
+    assert_eq!(request.header.method, BINDING_METHOD);
+    let attribute_types: Vec<u16> = request
+        .attributes
+        .iter()
+        .map(|tlv| tlv.attribute_type)
+        .collect();
+    assert_eq!(
+        attribute_types,
+        vec![
+            Username::ID,
+            Priority::ID,
+            IceControlling::ID,
+            UseCandidate::ID,
+            MessageIntegrity::ID,
+            Fingerprint::ID,
+        ]
+    );
+    let mut attributes = request.attributes.clone();
+    assert_eq!(
+        attributes.extract_attribute::<Username>().unwrap().0,
+        "RFRG:LFRG"
+    );
+    assert_eq!(
+        attributes.extract_attribute::<Priority>().unwrap().0,
+        (110 << 24) | (65535 << 8) | 255
+    );
+    assert_eq!(
+        attributes.extract_attribute::<IceControlling>().unwrap().0,
+        0x0123456789abcdef
+    );
+    assert_eq!(request.verify_fingerprint(), Some(true));
+
+    // when
+    let mut attributes = Vec::new();
+    attributes.append_attribute(XorMappedAddress(ip(5001)));
+    let response = Message::response(BINDING_METHOD, request.header.transaction_id, attributes)
+        .xor_socket_addr(XorMappedAddress::ID);
+    ingress_sink.try_send((response, ip(6000))).unwrap();
+
+    // then
+    assert_pending!(runner_fut.poll());
+    assert_eq!(assert_ready!(check_fut.poll()).unwrap(), ip(5001));
+}
+
+#[test]
+fn indication_with_colliding_id_does_not_complete_request() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, mut ind_receiver, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+    let mut receive_fut = spawn(ind_receiver.receive_next());
+    assert_pending!(receive_fut.poll());
+
+    // when
+    let indication = Message::indication(42u16, request.header.transaction_id, vec![attribute()]);
+    ingress_sink.try_send((indication, ip(1234))).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then: the indication is delivered as usual
+    let indication = assert_ready!(receive_fut.poll()).unwrap();
+    assert_eq!(indication.farend_addr, ip(1234));
+    assert_eq!(indication.attributes, vec![attribute()]);
+
+    // and: the request is still outstanding
+    assert_pending!(request_fut.poll());
+    let mut list_fut = spawn(req_sender.outstanding_transactions());
+    assert_pending!(list_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let outstanding = assert_ready!(list_fut.poll()).unwrap();
+    assert_eq!(outstanding.len(), 1);
+    assert_eq!(outstanding[0].transaction_id, request.header.transaction_id);
+
+    // when
+    let response = Message::response(42u16, request.header.transaction_id, vec![]);
+    ingress_sink.try_send((response, ip(1234))).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(assert_ready!(request_fut.poll()).unwrap().success);
+}
+
+#[test]
+fn authenticated_requests_reuse_cached_nonce() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+    let auth_sender = AuthenticatingSender::new(req_sender, "user".to_owned(), "pass".to_owned());
+
+    let nonce_of = |request: &Message| {
+        let mut attributes = request.attributes.clone();
+        attributes
+            .extract_attribute::<Nonce>()
+            .ok()
+            .map(|attr| attr.0)
+    };
+
+    // when
+    let mut request_fut = spawn(auth_sender.send_request(ip(3478), BINDING_METHOD, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then: the first request carries no credentials
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(nonce_of(&request), None);
+
+    // when
+    let mut attributes = Vec::new();
+    attributes.append_attribute(ErrorCode {
+        code: 401,
+        reason: "Unauthenticated".to_owned(),
+    });
+    attributes.append_attribute(Realm("example.org".to_owned()));
+    attributes.append_attribute(Nonce("f//499k954d6OL34".to_owned()));
+    let challenge = Message::error(BINDING_METHOD, request.header.transaction_id, attributes);
+    ingress_sink.try_send((challenge, ip(3478))).unwrap();
+    assert_pending!(runner_fut.poll());
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then: it's retried with credentials
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(nonce_of(&request).as_deref(), Some("f//499k954d6OL34"));
+    let mut attributes = request.attributes.clone();
+    assert_eq!(
+        attributes.extract_attribute::<Username>().unwrap().0,
+        "user"
+    );
+    assert_eq!(
+        attributes.extract_attribute::<Realm>().unwrap().0,
+        "example.org"
+    );
+    assert!(attributes.extract_attribute::<MessageIntegrity>().is_ok());
+    assert_eq!(request.verify_fingerprint(), Some(true));
+
+    // when
+    let response = Message::response(BINDING_METHOD, request.header.transaction_id, vec![]);
+    ingress_sink.try_send((response, ip(3478))).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(assert_ready!(request_fut.poll()).unwrap().success);
+
+    // when
+    let mut request_fut = spawn(auth_sender.send_request(ip(3478), BINDING_METHOD, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then: the cached nonce is used right away
+    let (request, _) = egress_source.try_recv().unwrap();
+    assert_eq!(nonce_of(&request).as_deref(), Some("f//499k954d6OL34"));
+    let response = Message::response(BINDING_METHOD, request.header.transaction_id, vec![]);
+    ingress_sink.try_send((response, ip(3478))).unwrap();
+    assert_pending!(runner_fut.poll());
+    assert!(assert_ready!(request_fut.poll()).unwrap().success);
+    assert!(egress_source.try_recv().is_err());
+}
+
+#[test]
+fn decode_nonce_security_features() {
+    let challenge = |nonce: &str| Challenge {
+        realm: "example.org".to_owned(),
+        nonce: nonce.to_owned(),
+        password_algorithms: Vec::new(),
+    };
+
+    assert_eq!(
+        challenge("obMatJos2AAACf//499k954d6OL34oL9FSTvy64sA").security_features(),
+        Some(SecurityFeatures {
+            password_algorithms: false,
+            username_anonymity: true,
+        })
+    );
+    assert_eq!(
+        challenge("obMatJos2AAABf//499k954d6OL34").security_features(),
+        Some(SecurityFeatures {
+            password_algorithms: true,
+            username_anonymity: false,
+        })
+    );
+    assert_eq!(challenge("f//499k954d6OL34").security_features(), None);
+}
+
+#[test]
+fn request_copies_reuse_pooled_buffers() {
+    struct CountingPool {
+        free: Vec<Vec<u8>>,
+        acquired: Rc<Cell<usize>>,
+        allocated: Rc<Cell<usize>>,
+    }
+    impl BufferPool for CountingPool {
+        fn acquire(&mut self, capacity: usize) -> Vec<u8> {
+            self.acquired.set(self.acquired.get() + 1);
+            self.free.pop().unwrap_or_else(|| {
+                self.allocated.set(self.allocated.get() + 1);
+                Vec::with_capacity(capacity)
+            })
+        }
+        fn release(&mut self, buffer: Vec<u8>) {
+            self.free.push(buffer);
+        }
+    }
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    let acquired = Rc::new(Cell::new(0));
+    let allocated = Rc::new(Cell::new(0));
+    processor.set_buffer_pool(CountingPool {
+        free: Vec::new(),
+        acquired: acquired.clone(),
+        allocated: allocated.clone(),
+    });
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    for _ in 0..3 {
+        // when
+        let mut request_fut = spawn(req_sender.send_request(ip(1234), 42u16, vec![attribute()]));
+        assert_pending!(request_fut.poll());
+        assert_pending!(runner_fut.poll());
+        let (request, _) = egress_source.try_recv().unwrap();
+        assert_eq!(request.attributes, vec![attribute()]);
+
+        let response = Message::response(42u16, request.header.transaction_id, vec![]);
+        ingress_sink.try_send((response, ip(1234))).unwrap();
+        assert_pending!(runner_fut.poll());
+        assert!(assert_ready!(request_fut.poll()).unwrap().success);
+    }
+
+    // then
+    assert_eq!(acquired.get(), 3);
+    assert_eq!(allocated.get(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retransmissions_dont_leak_pooled_buffers() {
+    struct CountingPool {
+        acquired: Rc<Cell<usize>>,
+        released: Rc<Cell<usize>>,
+    }
+    impl BufferPool for CountingPool {
+        fn acquire(&mut self, capacity: usize) -> Vec<u8> {
+            self.acquired.set(self.acquired.get() + 1);
+            Vec::with_capacity(capacity)
+        }
+        fn release(&mut self, _buffer: Vec<u8>) {
+            self.released.set(self.released.get() + 1);
+        }
+    }
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+    let acquired = Rc::new(Cell::new(0));
+    let released = Rc::new(Cell::new(0));
+    processor.set_buffer_pool(CountingPool {
+        acquired: acquired.clone(),
+        released: released.clone(),
+    });
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(60), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        // when: the request is answered after 3 retransmissions
+        let respond = async {
+            for _ in 0..3 {
+                egress_source.recv().await.unwrap();
+            }
+            let (request, addr) = egress_source.recv().await.unwrap();
+            let response = Message::response(42u16, request.header.transaction_id, vec![]);
+            ingress_sink.send((response, addr)).await.unwrap();
+        };
+        let (result, _) = join!(
+            req_sender.send_request(ip(1234), 42u16, vec![attribute(), attribute()]),
+            respond
+        );
+        assert!(result.unwrap().success);
+
+        // then
+        assert_eq!(acquired.get(), 2);
+        assert_eq!(released.get(), 2);
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[cfg(feature = "ice")]
+#[test]
+fn construct_ice_username() {
+    use attributes::*;
+
+    let username = ice_username("RFRG", "LFRAG").unwrap();
+    assert_eq!(username.0, "RFRG:LFRAG");
+
+    let mut tlvs = Vec::new();
+    tlvs.append_attribute(username);
+    let mut buffer = Vec::new();
+    tlvs.encode_into(&mut buffer).unwrap();
+    assert_eq!(
+        buffer,
+        [
+            0x00, 0x06, 0x00, 0x0a, b'R', b'F', b'R', b'G', b':', b'L', b'F', b'R', b'A', b'G',
+            0x00, 0x00,
+        ]
+    );
+
+    assert_eq!(
+        ice_username("RFR", "LFRAG").unwrap_err(),
+        InvalidUfrag("RFR".to_owned())
+    );
+    assert_eq!(
+        ice_username("RFRG", &"L".repeat(257)).unwrap_err(),
+        InvalidUfrag("L".repeat(257))
+    );
+    assert!(ice_username("RF:G", "LFRAG").is_err());
+    assert!(ice_username(&"R".repeat(256), "LFRAG").is_ok());
+}
+
+#[test]
+fn send_request_without_waiting_for_response() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut send_fut =
+        spawn(req_sender.send_request_without_response(ip(1234), 0x0123, vec![attribute()]));
+    assert_pending!(send_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert_ready!(send_fut.poll()).unwrap();
+    let (request, addr) = egress_source.try_recv().unwrap();
+    assert_eq!(addr, ip(1234));
+    assert_eq!(request.header.class, Class::Request);
+    assert_eq!(request.header.method, 0x0123);
+    assert_eq!(request.attributes, vec![attribute()]);
+
+    // and: nothing is outstanding
+    let mut list_fut = spawn(req_sender.outstanding_transactions());
+    assert_pending!(list_fut.poll());
+    assert_pending!(runner_fut.poll());
+    assert!(assert_ready!(list_fut.poll()).unwrap().is_empty());
+}
+
+#[test]
+fn serialize_binding_request_without_sending() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(Duration::from_secs(1)),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    // when
+    let mut serialize_fut = spawn(req_sender.serialize_request(BINDING_METHOD, vec![attribute()]));
+    assert_pending!(serialize_fut.poll());
+    assert_pending!(runner_fut.poll());
+
+    // then
+    let mut bytes = assert_ready!(serialize_fut.poll()).unwrap();
+    assert_eq!(bytes.len(), 28);
+    // transaction id is random
+    bytes[8..20].fill(0);
+    assert_eq!(
+        bytes,
+        [
+            0x00, 0x01, 0x00, 0x08, 0x21, 0x12, 0xa4, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x22, 0x00, 0x04, b'U', b'g', b'h', b'!',
+        ]
+    );
+
+    // and: nothing is sent or outstanding
+    assert!(egress_source.try_recv().is_err());
+    let mut list_fut = spawn(req_sender.outstanding_transactions());
+    assert_pending!(list_fut.poll());
+    assert_pending!(runner_fut.poll());
+    assert!(assert_ready!(list_fut.poll()).unwrap().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_after_server_error() {
+    use attributes::*;
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions_with_config(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        Config {
+            server_error_retry: Some(ServerErrorRetry {
+                max_retries: 2,
+                initial_backoff: millisec!(100),
+            }),
+            ..Default::default()
+        },
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        let start_time = Instant::now();
+        let response = req_sender
+            .send_request(ip(3478), BINDING_METHOD, vec![attribute()])
+            .await
+            .unwrap();
+        assert!(response.success);
+        assert_eq!(start_time.elapsed(), millisec!(100));
+    };
+
+    let server_fut = async move {
+        // when: the first attempt fails with 500
+        let (request, _) = egress_source.recv().await.unwrap();
+        let mut attributes = Vec::new();
+        attributes.append_attribute(ErrorCode {
+            code: 500,
+            reason: "Server Error".to_owned(),
+        });
+        let error = Message::error(BINDING_METHOD, request.header.transaction_id, attributes);
+        ingress_sink.send((error, ip(3478))).await.unwrap();
+
+        // then: it's retried in a new transaction after the backoff
+        let (retry, _) = egress_source.recv().await.unwrap();
+        assert_ne!(retry.header.transaction_id, request.header.transaction_id);
+        assert_eq!(retry.attributes, vec![attribute()]);
+
+        // when
+        let response = Message::response(BINDING_METHOD, retry.header.transaction_id, vec![]);
+        ingress_sink.send((response, ip(3478))).await.unwrap();
+    };
+
+    join!(sender_fut, processor_fut, server_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn report_time_spent_in_queue() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        // when: the second request has to wait for the first one to complete
+        let (response1, response2) = join!(
+            req_sender.send_request(ip(1111), 42u16, vec![]),
+            req_sender.send_request(ip(2222), 42u16, vec![]),
+        );
+
+        // then
+        let response1 = response1.unwrap();
+        assert_eq!(response1.queue_wait, Duration::ZERO);
+        assert_eq!(response1.time_elapsed, millisec!(300));
+        let response2 = response2.unwrap();
+        assert_eq!(response2.queue_wait, millisec!(300));
+        assert_eq!(response2.time_elapsed, millisec!(100));
+    };
+
+    let server_fut = async move {
+        for delay in [300, 100] {
+            let (request, addr) = egress_source.recv().await.unwrap();
+            time::sleep(millisec!(delay)).await;
+            let response = Message::response(42u16, request.header.transaction_id, vec![]);
+            ingress_sink.send((response, addr)).await.unwrap();
+        }
+    };
+
+    join!(sender_fut, processor_fut, server_fut);
+}
+
+#[test]
+fn correlate_response_from_ipv4_mapped_address() {
+    struct RecordRtt(Rc<RefCell<Vec<SocketAddr>>>);
+
+    impl RtoPolicy for RecordRtt {
+        fn submit_rtt(&mut self, remote_addr: SocketAddr, _rtt: Duration) {
+            self.0.borrow_mut().push(remote_addr);
+        }
+
+        fn calculate_rto(&mut self, _remote_addr: SocketAddr, attempts: usize) -> Option<Duration> {
+            (attempts == 0).then_some(sec!(1))
+        }
+    }
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let rtt_sources = Rc::new(RefCell::new(Vec::new()));
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        RecordRtt(rtt_sources.clone()),
+    );
+    processor.use_manual_clock();
+    let mut runner_fut = spawn(processor.run());
+    assert_pending!(runner_fut.poll());
+
+    let ipv4_addr = SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 3478);
+    let mapped_addr = SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).to_ipv6_mapped().into(), 3478);
+
+    // when
+    let mut request_fut = spawn(req_sender.send_request(ipv4_addr, 42u16, vec![]));
+    assert_pending!(request_fut.poll());
+    assert_pending!(runner_fut.poll());
+    let (request, _) = egress_source.try_recv().unwrap();
+    let response = Message::response(42u16, request.header.transaction_id, vec![]);
+    ingress_sink.try_send((response, mapped_addr)).unwrap();
+    assert_pending!(runner_fut.poll());
+
+    // then
+    assert!(assert_ready!(request_fut.poll()).unwrap().success);
+    assert_eq!(*rtt_sources.borrow(), vec![ipv4_addr]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn pause_suspends_retransmissions() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        2,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(60), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let start_time = Instant::now();
+        let pending = req_sender
+            .submit_request(ip(1111), 42u16, vec![])
+            .await
+            .unwrap();
+        egress_source.recv().await.unwrap();
+
+        // when: paused within the initial RTO of 500ms
+        time::sleep(millisec!(200)).await;
+        req_sender.pause().await.unwrap();
+        let queued = req_sender
+            .submit_request(ip(2222), 42u16, vec![])
+            .await
+            .unwrap();
+
+        // then: nothing is sent while paused
+        time::sleep(sec!(5)).await;
+        egress_source.try_recv().unwrap_err();
+
+        // when
+        req_sender.resume().await.unwrap();
+
+        // then: the queued request is sent right away
+        let (_, addr) = egress_source.recv().await.unwrap();
+        assert_eq!(addr, ip(2222));
+
+        // and: the remaining 300ms of the RTO are honoured
+        let (_, addr) = egress_source.recv().await.unwrap();
+        assert_eq!(addr, ip(1111));
+        assert_eq!(start_time.elapsed(), millisec!(5500));
+
+        // and: the request times out 5s later than it would have without the pause
+        assert!(matches!(pending.await, Err(TransactionError::Timeout)));
+        assert_eq!(start_time.elapsed(), millisec!(44500));
+        assert!(matches!(queued.await, Err(TransactionError::Timeout)));
+    };
+
+    join!(processor_fut, test_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_fails_at_external_deadline() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(60), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        // between the retransmissions at 1500ms and 3500ms
+        let deadline = Instant::now() + millisec!(2200);
+        let result = req_sender
+            .send_request_until(ip(1234), 42u16, vec![], deadline)
+            .await;
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        assert_eq!(Instant::now(), deadline);
+    };
+
+    join!(processor_fut, sender_fut);
+
+    let mut transmissions = 0;
+    while egress_source.try_recv().is_ok() {
+        transmissions += 1;
+    }
+    assert_eq!(transmissions, 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_fails_at_per_request_timeout_or_rto_policy_timeout() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(60), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        // when: the per-request timeout is shorter than the RTO schedule
+        let start_time = Instant::now();
+        let result = req_sender
+            .send_request_with_timeout(ip(1234), 42u16, vec![], millisec!(2200))
+            .await;
+
+        // then
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        assert_eq!(start_time.elapsed(), millisec!(2200));
+        assert!(req_sender
+            .outstanding_transactions()
+            .await
+            .unwrap()
+            .is_empty());
+        let mut transmissions = 0;
+        while egress_source.try_recv().is_ok() {
+            transmissions += 1;
+        }
+        assert_eq!(transmissions, 3);
+
+        // when: the per-request timeout is longer than the RTO schedule
+        let start_time = Instant::now();
+        let result = req_sender
+            .send_request_with_timeout(ip(1234), 42u16, vec![], sec!(50))
+            .await;
+
+        // then
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        assert_eq!(start_time.elapsed(), millisec!(39500));
+    };
+
+    join!(processor_fut, sender_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn report_retransmission_history_on_timeout() {
+    struct TimeoutRecorder(Rc<RefCell<Vec<Attempt>>>);
+
+    impl TransactionObserver for TimeoutRecorder {
+        fn on_timeout(&mut self, _destination: SocketAddr, _tid: &[u8; 12], attempts: &[Attempt]) {
+            self.0.borrow_mut().extend_from_slice(attempts);
+        }
+    }
+
+    let (egress_sink, _egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        ExponentialBackoffFixedRtt::<4, 2>::new(millisec!(500)),
+    );
+    let history = Rc::new(RefCell::new(Vec::new()));
+    processor.set_observer(TimeoutRecorder(history.clone()));
+
+    let start_time = Instant::now();
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(40), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        let result = req_sender
+            .send_request(ip(1234), 42u16, vec![attribute()])
+            .await;
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+    };
+
+    join!(sender_fut, processor_fut);
+    let history = history.borrow();
+    assert_eq!(history.len(), 4);
+    let timeline: Vec<_> = history
+        .iter()
+        .map(|attempt| (attempt.sent_at - start_time, attempt.rto))
+        .collect();
+    assert_eq!(
+        timeline,
+        vec![
+            (millisec!(0), millisec!(500)),
+            (millisec!(500), millisec!(1000)),
+            (millisec!(1500), millisec!(2000)),
+            (millisec!(3500), millisec!(1000)),
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn rtt_is_measured_at_transport_receive_time() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        let response = req_sender
+            .send_request(ip(1234), 42u16, vec![])
+            .await
+            .unwrap();
+        assert_eq!(response.time_elapsed, millisec!(50));
+    };
+
+    let transport_fut = async move {
+        let (request, _) = egress_source.recv().await.unwrap();
+        time::sleep(millisec!(50)).await;
+        let mut response = Message::response(42u16, request.header.transaction_id, vec![]);
+        response.received_at = Some(Instant::now());
+
+        // when: the response reaches the processor only after some processing delay
+        time::sleep(millisec!(300)).await;
+        ingress_sink.send((response, ip(1234))).await.unwrap();
+    };
+
+    join!(sender_fut, processor_fut, transport_fut);
+}
+
+#[tokio::test(start_paused = true)]
+async fn report_progress_on_retransmissions() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        ExponentialBackoffFixedRtt::<3, 16>::new(millisec!(500)),
+    );
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(10), processor.run()).await;
+    };
+
+    let sender_fut = {
+        let attempts = attempts.clone();
+        async move {
+            let result = req_sender
+                .send_request_with_progress(ip(1234), 42u16, vec![], move |attempt| {
+                    attempts.lock().unwrap().push(attempt)
+                })
+                .await;
+            assert!(matches!(result, Err(TransactionError::Timeout)));
+        }
+    };
+
+    let receiver_fut = async {
+        egress_source.recv().await.unwrap();
+        assert!(attempts.lock().unwrap().is_empty());
+        egress_source.recv().await.unwrap();
+        assert_eq!(*attempts.lock().unwrap(), vec![2]);
+        egress_source.recv().await.unwrap();
+        assert_eq!(*attempts.lock().unwrap(), vec![2, 3]);
+    };
+
+    join!(sender_fut, processor_fut, receiver_fut);
+    assert_eq!(*attempts.lock().unwrap(), vec![2, 3]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn send_periodic_keepalives() {
+    use attributes::*;
+
+    struct MappingRecorder(Rc<RefCell<Vec<(SocketAddr, SocketAddr, Instant)>>>);
+
+    impl TransactionObserver for MappingRecorder {
+        fn on_mapping_changed(&mut self, server: SocketAddr, mapped_address: SocketAddr) {
+            self.0
+                .borrow_mut()
+                .push((server, mapped_address, Instant::now()));
+        }
+    }
+
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (_req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        DefaultExponentialBackoffFixedRtt::default(),
+    );
+    let changes = Rc::new(RefCell::new(Vec::new()));
+    processor.set_observer(MappingRecorder(changes.clone()));
+    processor.add_keepalive(ip(3478), sec!(5));
+
+    let start_time = Instant::now();
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(12), processor.run()).await;
+    };
+
+    let server_fut = async move {
+        for (expected_time, mapped_address) in [
+            (sec!(0), ip(5000)),
+            (sec!(5), ip(5000)),
+            (sec!(10), ip(6000)),
+        ] {
+            let (request, addr) = egress_source.recv().await.unwrap();
+            assert_eq!(addr, ip(3478));
+            assert_eq!(request.header.method, BINDING_METHOD);
+            assert_eq!(start_time.elapsed(), expected_time);
+
+            let mut attributes = Vec::new();
+            attributes.append_attribute(XorMappedAddress(mapped_address));
+            let response =
+                Message::response(BINDING_METHOD, request.header.transaction_id, attributes)
+                    .xor_socket_addr(XorMappedAddress::ID);
+            ingress_sink.send((response, ip(3478))).await.unwrap();
+        }
+        // no retransmissions of answered keepalives
+        time::sleep(sec!(1)).await;
+        assert!(egress_source.try_recv().is_err());
+    };
+
+    join!(processor_fut, server_fut);
+    assert_eq!(
+        *changes.borrow(),
+        vec![
+            (ip(3478), ip(5000), start_time),
+            (ip(3478), ip(6000), start_time + sec!(10)),
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn no_retransmissions_policy_schedules_single_timeout() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let sender_fut = async move {
+        let start_time = Instant::now();
+        let result = req_sender.send_request(ip(1234), 42u16, vec![]).await;
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        assert_eq!(start_time.elapsed(), sec!(1));
+    };
+
+    join!(processor_fut, sender_fut);
+    assert!(egress_source.try_recv().is_ok());
+    assert!(egress_source.try_recv().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn send_indications_from_cloned_senders() {
+    let (egress_sink, mut egress_source) = mpsc::channel(100);
+    let (_ingress_sink, ingress_source) = mpsc::channel(10);
+    let (_, ind_sender, _, processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        NoRetransmissionsConstTimeout::new(sec!(1)),
+    );
+
+    let producers: Vec<_> = (0..4u16)
+        .map(|producer| {
+            let ind_sender = ind_sender.clone();
+            tokio::spawn(async move {
+                for i in 0..10u16 {
+                    ind_sender
+                        .send_indication(ip(1000 * (producer + 1) + i), 42u16, vec![attribute()])
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(ind_sender);
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+    let producers_fut = async move {
+        for producer in producers {
+            producer.await.unwrap();
+        }
+    };
+    join!(processor_fut, producers_fut);
+
+    let mut received = Vec::new();
+    while let Ok((indication, addr)) = egress_source.try_recv() {
+        assert_eq!(indication.header.class, Class::Indication);
+        assert_eq!(indication.attributes, vec![attribute()]);
+        received.push(addr.port());
+    }
+    received.sort();
+    let expected: Vec<u16> = (0..4u16)
+        .flat_map(|producer| (0..10u16).map(move |i| 1000 * (producer + 1) + i))
+        .collect();
+    assert_eq!(received, expected);
+}
+
+#[cfg(feature = "binary-log")]
+#[tokio::test(start_paused = true)]
+async fn write_transaction_events_to_binary_log() {
+    let (egress_sink, mut egress_source) = mpsc::channel(10);
+    let (ingress_sink, ingress_source) = mpsc::channel(10);
+    let (req_sender, _, _, mut processor) = setup_transactions(
+        MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        },
+        1,
+        ExponentialBackoffFixedRtt::<2, 16>::new(millisec!(500)),
+    );
+    let (binary_log, log_writer) = binary_log(Vec::new(), 16);
+    processor.set_observer(binary_log);
+
+    let processor_fut = async move {
+        let _ = time::timeout(sec!(5), processor.run()).await;
+    };
+
+    let test_fut = async move {
+        let answered = req_sender
+            .submit_request(ip(1111), 42u16, vec![])
+            .await
+            .unwrap();
+        egress_source.recv().await.unwrap();
+        let (request, _) = egress_source.recv().await.unwrap();
+        time::sleep(millisec!(200)).await;
+        let response = Message::response(42u16, request.header.transaction_id, vec![]);
+        ingress_sink.send((response, ip(1111))).await.unwrap();
+        assert!(answered.await.unwrap().success);
+
+        let result = req_sender.send_request(ip(2222), 42u16, vec![]).await;
+        assert!(matches!(result, Err(TransactionError::Timeout)));
+        request.header.transaction_id
+    };
+
+    let (_, first_tid, written) = join!(processor_fut, test_fut, log_writer.run());
+    let written = written.unwrap();
+
+    let mut buffer = written.as_slice();
+    let mut records = Vec::new();
+    while !buffer.is_empty() {
+        records.push(LogRecord::decode_from(&mut buffer).unwrap());
+    }
+    let events: Vec<_> = records
+        .iter()
+        .map(|record| (record.kind, record.address, record.timestamp))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (EventKind::Sent, ip(1111), millisec!(0)),
+            (EventKind::Retransmitted, ip(1111), millisec!(500)),
+            (EventKind::Response, ip(1111), millisec!(700)),
+            (EventKind::Sent, ip(2222), millisec!(700)),
+            (EventKind::Retransmitted, ip(2222), millisec!(1200)),
+            (EventKind::Timeout, ip(2222), millisec!(2200)),
+        ]
+    );
+    assert!(records[..3]
+        .iter()
+        .all(|record| record.transaction_id == first_tid));
+    assert!(records[3..]
+        .iter()
+        .all(|record| record.transaction_id == records[3].transaction_id));
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn report_transaction_lifecycle_to_metrics_recorder() {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use std::collections::HashMap;
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    ::metrics::with_local_recorder(&recorder, || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap()
+            .block_on(async {
+                let (egress_sink, mut egress_source) = mpsc::channel(20);
+                let (ingress_sink, ingress_source) = mpsc::channel(10);
+                let (req_sender, _, _, processor) = setup_transactions(
+                    MessageChannels {
+                        egress_sink,
+                        ingress_source,
+                        event_source: mpsc::channel(1).1,
+                    },
+                    3,
+                    DefaultExponentialBackoffFixedRtt::default(),
+                );
+
+                let processor_fut = async move {
+                    let _ = time::timeout(sec!(45), processor.run()).await;
+                };
+
+                let test_fut = async move {
+                    let mut batch = req_sender.batch();
+                    for port in [1111, 2222, 3333] {
+                        batch.submit(ip(port), 42u16, vec![]).await.unwrap();
+                    }
+                    let (request1, _) = egress_source.recv().await.unwrap();
+                    let (request2, _) = egress_source.recv().await.unwrap();
+                    let _ = egress_source.recv().await.unwrap();
+
+                    time::sleep(millisec!(100)).await;
+                    let response = Message::response(42u16, request1.header.transaction_id, vec![]);
+                    ingress_sink.send((response, ip(1111))).await.unwrap();
+                    let response = Message::error(42u16, request2.header.transaction_id, vec![]);
+                    ingress_sink.send((response, ip(2222))).await.unwrap();
+                    let _ = batch.collect().await;
+                };
+
+                join!(processor_fut, test_fut);
+            });
+    });
+
+    let metrics: HashMap<_, _> = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| {
+            let (_, key) = key.into_parts();
+            let labels: Vec<_> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            (format!("{}{labels:?}", key.name()), value)
+        })
+        .collect();
+
+    let counter = |name: &str| match metrics.get(name) {
+        Some(DebugValue::Counter(value)) => *value,
+        other => panic!("{name}: {other:?}"),
+    };
+    assert_eq!(counter("stunny_client_requests_sent_total[]"), 3);
+    // 7 transmissions of the unanswered request
+    assert_eq!(counter("stunny_client_retransmissions_total[]"), 6);
+    assert_eq!(
+        counter("stunny_client_responses_total[\"class=success\"]"),
+        1
+    );
+    assert_eq!(counter("stunny_client_responses_total[\"class=error\"]"), 1);
+    assert_eq!(counter("stunny_client_timeouts_total[]"), 1);
+    match metrics.get("stunny_client_rtt_seconds[]") {
+        Some(DebugValue::Histogram(samples)) => assert_eq!(samples.len(), 2),
+        other => panic!("stunny_client_rtt_seconds: {other:?}"),
+    }
+}
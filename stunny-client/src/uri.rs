@@ -0,0 +1,298 @@
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Stun,
+    Stuns,
+    Turn,
+    Turns,
+}
+
+impl Scheme {
+    /// 3478 for stun: and turn:, 5349 for stuns: and turns:.
+    pub fn default_port(self) -> u16 {
+        if self.is_secure() {
+            5349
+        } else {
+            3478
+        }
+    }
+
+    pub fn is_secure(self) -> bool {
+        matches!(self, Scheme::Stuns | Scheme::Turns)
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scheme::Stun => "stun",
+            Scheme::Stuns => "stuns",
+            Scheme::Turn => "turn",
+            Scheme::Turns => "turns",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportHint {
+    Udp,
+    Tcp,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum UriError {
+    #[error("unknown URI scheme '{0}'")]
+    UnknownScheme(String),
+
+    #[error("URI has no host")]
+    MissingHost,
+
+    #[error("invalid port '{0}'")]
+    InvalidPort(String),
+
+    #[error("invalid IPv6 literal '{0}'")]
+    InvalidIpv6(String),
+
+    #[error("unsupported URI query parameter '{0}'")]
+    UnknownParameter(String),
+
+    #[error("invalid transport '{0}'")]
+    InvalidTransport(String),
+
+    #[error("invalid percent-encoding in '{0}'")]
+    InvalidPercentEncoding(String),
+}
+
+/// stun:, stuns: (RFC 7064), turn: or turns: (RFC 7065) URI, e.g.
+/// "turn:example.org:3478?transport=tcp". The `?transport=` parameter is accepted for all schemes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerUri {
+    pub scheme: Scheme,
+    /// Percent-decoded user info preceding '@', if any.
+    pub userinfo: Option<String>,
+    /// Hostname or IP address, IPv6 literals without brackets.
+    pub host: String,
+    /// Explicit port or the default port of the scheme.
+    pub port: u16,
+    pub transport_hint: Option<TransportHint>,
+}
+
+impl ServerUri {
+    /// The transport to reach the server over: the `?transport=` parameter if present, otherwise
+    /// TCP for the secure schemes and UDP for the others.
+    pub fn transport(&self) -> TransportHint {
+        self.transport_hint.unwrap_or(if self.scheme.is_secure() {
+            TransportHint::Tcp
+        } else {
+            TransportHint::Udp
+        })
+    }
+}
+
+impl FromStr for ServerUri {
+    type Err = UriError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = uri
+            .split_once(':')
+            .ok_or_else(|| UriError::UnknownScheme(uri.to_owned()))?;
+        let scheme = match scheme.to_ascii_lowercase().as_str() {
+            "stun" => Scheme::Stun,
+            "stuns" => Scheme::Stuns,
+            "turn" => Scheme::Turn,
+            "turns" => Scheme::Turns,
+            _ => return Err(UriError::UnknownScheme(scheme.to_owned())),
+        };
+        // not allowed by the RFCs, but common in the wild
+        let rest = rest.strip_prefix("//").unwrap_or(rest);
+
+        let (authority, query) = match rest.split_once('?') {
+            Some((authority, query)) => (authority, Some(query)),
+            None => (rest, None),
+        };
+        let (userinfo, host_port) = match authority.rsplit_once('@') {
+            Some((userinfo, host_port)) => (Some(percent_decode(userinfo)?), host_port),
+            None => (None, authority),
+        };
+
+        let (host, port) = if let Some(bracketed) = host_port.strip_prefix('[') {
+            let (host, port) = bracketed
+                .split_once(']')
+                .ok_or_else(|| UriError::InvalidIpv6(host_port.to_owned()))?;
+            host.parse::<Ipv6Addr>()
+                .map_err(|_e| UriError::InvalidIpv6(host.to_owned()))?;
+            let port = match port {
+                "" => None,
+                port => Some(
+                    port.strip_prefix(':')
+                        .ok_or_else(|| UriError::InvalidPort(port.to_owned()))?,
+                ),
+            };
+            (host.to_owned(), port)
+        } else {
+            match host_port.split_once(':') {
+                Some((host, port)) => (percent_decode(host)?, Some(port)),
+                None => (percent_decode(host_port)?, None),
+            }
+        };
+        if host.is_empty() {
+            return Err(UriError::MissingHost);
+        }
+        let port = match port {
+            Some(port) => port
+                .parse::<u16>()
+                .map_err(|_e| UriError::InvalidPort(port.to_owned()))?,
+            None => scheme.default_port(),
+        };
+
+        let mut transport_hint = None;
+        for param in query.into_iter().flat_map(|query| query.split('&')) {
+            match param.split_once('=') {
+                Some(("transport", value)) => {
+                    transport_hint = Some(match value.to_ascii_lowercase().as_str() {
+                        "udp" => TransportHint::Udp,
+                        "tcp" => TransportHint::Tcp,
+                        _ => return Err(UriError::InvalidTransport(value.to_owned())),
+                    });
+                }
+                _ => return Err(UriError::UnknownParameter(param.to_owned())),
+            }
+        }
+
+        Ok(ServerUri {
+            scheme,
+            userinfo,
+            host,
+            port,
+            transport_hint,
+        })
+    }
+}
+
+fn percent_decode(input: &str) -> Result<String, UriError> {
+    let invalid = || UriError::InvalidPercentEncoding(input.to_owned());
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [
+            iter.next().ok_or_else(invalid)?,
+            iter.next().ok_or_else(invalid)?,
+        ];
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return Err(invalid());
+        }
+        let hex = std::str::from_utf8(&hex).map_err(|_e| invalid())?;
+        bytes.push(u8::from_str_radix(hex, 16).map_err(|_e| invalid())?);
+    }
+    String::from_utf8(bytes).map_err(|_e| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uris_with_default_ports() {
+        let uri: ServerUri = "stun:stun.example.org".parse().unwrap();
+        assert_eq!(
+            uri,
+            ServerUri {
+                scheme: Scheme::Stun,
+                userinfo: None,
+                host: "stun.example.org".to_owned(),
+                port: 3478,
+                transport_hint: None,
+            }
+        );
+        assert_eq!(uri.transport(), TransportHint::Udp);
+
+        let uri: ServerUri = "stuns:stun.example.org".parse().unwrap();
+        assert_eq!(uri.scheme, Scheme::Stuns);
+        assert_eq!(uri.port, 5349);
+        assert_eq!(uri.transport(), TransportHint::Tcp);
+
+        let uri: ServerUri = "turn:192.0.2.1".parse().unwrap();
+        assert_eq!(uri.scheme, Scheme::Turn);
+        assert_eq!(uri.port, 3478);
+
+        let uri: ServerUri = "TURNS:turn.example.org".parse().unwrap();
+        assert_eq!(uri.scheme, Scheme::Turns);
+        assert_eq!(uri.port, 5349);
+    }
+
+    #[test]
+    fn parse_uris_with_port_and_transport() {
+        let uri: ServerUri = "turn:turn.example.org:1234?transport=tcp".parse().unwrap();
+        assert_eq!(uri.host, "turn.example.org");
+        assert_eq!(uri.port, 1234);
+        assert_eq!(uri.transport_hint, Some(TransportHint::Tcp));
+        assert_eq!(uri.transport(), TransportHint::Tcp);
+
+        let uri: ServerUri = "turns:turn.example.org?transport=udp".parse().unwrap();
+        assert_eq!(uri.port, 5349);
+        assert_eq!(uri.transport(), TransportHint::Udp);
+
+        let uri: ServerUri = "stun:[2001:db8::1]:19302".parse().unwrap();
+        assert_eq!(uri.host, "2001:db8::1");
+        assert_eq!(uri.port, 19302);
+
+        let uri: ServerUri = "stun:[::1]".parse().unwrap();
+        assert_eq!(uri.host, "::1");
+        assert_eq!(uri.port, 3478);
+
+        let uri: ServerUri = "turn:alice%40home:p%3Ass@turn.example.org".parse().unwrap();
+        assert_eq!(uri.userinfo.as_deref(), Some("alice@home:p:ss"));
+        assert_eq!(uri.host, "turn.example.org");
+        assert_eq!(uri.port, 3478);
+    }
+
+    #[test]
+    fn reject_malformed_uris() {
+        let parse = |uri: &str| uri.parse::<ServerUri>().unwrap_err();
+
+        assert_eq!(
+            parse("http://example.org"),
+            UriError::UnknownScheme("http".to_owned())
+        );
+        assert_eq!(
+            parse("stun.example.org"),
+            UriError::UnknownScheme("stun.example.org".to_owned())
+        );
+        assert_eq!(parse("stun:"), UriError::MissingHost);
+        assert_eq!(parse("stun::3478"), UriError::MissingHost);
+        assert_eq!(
+            parse("stun:example.org:http"),
+            UriError::InvalidPort("http".to_owned())
+        );
+        assert_eq!(
+            parse("stun:example.org:99999"),
+            UriError::InvalidPort("99999".to_owned())
+        );
+        assert_eq!(parse("stun:[::1"), UriError::InvalidIpv6("[::1".to_owned()));
+        assert_eq!(parse("stun:[::g]"), UriError::InvalidIpv6("::g".to_owned()));
+        assert_eq!(
+            parse("stun:[::1]3478"),
+            UriError::InvalidPort("3478".to_owned())
+        );
+        assert_eq!(
+            parse("turn:example.org?transport=sctp"),
+            UriError::InvalidTransport("sctp".to_owned())
+        );
+        assert_eq!(
+            parse("turn:example.org?foo=bar"),
+            UriError::UnknownParameter("foo=bar".to_owned())
+        );
+        assert_eq!(
+            parse("turn:a%4@example.org"),
+            UriError::InvalidPercentEncoding("a%4".to_owned())
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use super::*;
+use std::net::SocketAddr;
+
+/// The outstanding request a response has been matched to by transaction id.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestDetails<'a> {
+    pub destination: SocketAddr,
+    pub method: u16,
+    /// Key the request was signed with, if any.
+    pub integrity_key: Option<&'a [u8]>,
+}
+
+#[derive(Debug)]
+pub enum Validation {
+    /// Complete the transaction with the response.
+    Accept,
+    /// Discard the response and keep waiting (and retransmitting) as if it never arrived.
+    Ignore,
+    /// Complete the transaction with an error instead of the response.
+    Reject(TransactionError),
+}
+
+/// Decides whether a response completes the transaction it's been matched to. Invoked
+/// synchronously from within `Processor::run()`, so it must not block.
+pub trait ResponseValidator {
+    /// `response` is as received, i.e. XOR-ed addresses are still XOR-ed and MESSAGE-INTEGRITY
+    /// can be verified.
+    fn validate(
+        &mut self,
+        request: &RequestDetails,
+        response: &Message,
+        source: SocketAddr,
+    ) -> Validation;
+}
+
+/// Ignores responses to signed requests with an invalid or missing MESSAGE-INTEGRITY, except
+/// for unsigned 400, 401, 420 and 438 errors (RFC 8489 section 10.1.3), and rejects
+/// responses whose method doesn't match the request. Responses from an unexpected address are
+/// accepted with a warning.
+#[derive(Default)]
+pub struct DefaultResponseValidator;
+
+impl ResponseValidator for DefaultResponseValidator {
+    fn validate(
+        &mut self,
+        request: &RequestDetails,
+        response: &Message,
+        source: SocketAddr,
+    ) -> Validation {
+        if let Some(key) = request.integrity_key {
+            match response.verify_message_integrity(key) {
+                Some(true) => (),
+                // a forged or corrupted MESSAGE-INTEGRITY mustn't complete the transaction
+                Some(false) => {
+                    log::warn!("Dropping response from {source}: invalid MESSAGE-INTEGRITY");
+                    return Validation::Ignore;
+                }
+                // only errors the server may send without knowing the key can be unsigned
+                None if !is_unsigned_error_allowed(response) => {
+                    log::warn!("Dropping response from {source}: missing MESSAGE-INTEGRITY");
+                    return Validation::Ignore;
+                }
+                None => (),
+            }
+        }
+        if !same_address(source, request.destination) {
+            log::warn!(
+                "Response to request to {} received from {source}",
+                request.destination
+            );
+        }
+        if request.method != response.header.method {
+            return Validation::Reject(TransactionError::MethodMismatch {
+                request_method: request.method,
+                response_method: response.header.method,
+            });
+        }
+        Validation::Accept
+    }
+}
+
+fn is_unsigned_error_allowed(response: &Message) -> bool {
+    response.header.class == Class::Error
+        && response
+            .attributes
+            .iter()
+            .find_map(Tlv::as_error_code)
+            .is_some_and(|error| matches!(error.code, 400 | 401 | 420 | 438))
+}
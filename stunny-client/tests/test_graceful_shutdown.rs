@@ -0,0 +1,64 @@
+#![cfg(feature = "udp")]
+use local_async_utils::sec;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use stunny_client::*;
+use stunny_core::transport::udp::setup_udp;
+use tokio::net::UdpSocket;
+use tokio::{task, time};
+
+#[tokio::test]
+async fn stop_processor_and_udp_driver() {
+    task::LocalSet::new()
+        .run_until(async {
+            let local_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7860);
+            // bound so that requests to it are neither answered nor rejected with ICMP errors
+            let server_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7861);
+            let _server_sock = UdpSocket::bind(server_addr).await.unwrap();
+
+            let socket = UdpSocket::bind(local_addr).await.unwrap();
+            let (message_channels, driver) = setup_udp(socket, 10);
+            let (request_sender, _, _, processor) = setup_transactions(
+                message_channels,
+                10,
+                NoRetransmissionsConstTimeout::new(sec!(30)),
+            );
+            let driver = task::spawn(driver.run());
+            let processor = task::spawn_local(processor.run());
+
+            let request = task::spawn_local({
+                let request_sender = request_sender.clone();
+                async move {
+                    request_sender
+                        .send_request(server_addr.into(), BINDING_METHOD, Vec::new())
+                        .await
+                }
+            });
+            time::timeout(sec!(5), async {
+                while request_sender
+                    .outstanding_transactions()
+                    .await
+                    .unwrap()
+                    .is_empty()
+                {
+                    task::yield_now().await;
+                }
+            })
+            .await
+            .unwrap();
+
+            // when
+            request_sender.shutdown().await;
+
+            // then: the processor fails the outstanding request, and the driver stops once the
+            // processor is gone
+            let result = time::timeout(sec!(5), processor).await.unwrap().unwrap();
+            assert!(result.is_ok());
+            assert!(matches!(
+                request.await.unwrap(),
+                Err(TransactionError::Shutdown)
+            ));
+            let result = time::timeout(sec!(5), driver).await.unwrap().unwrap();
+            assert!(result.is_ok());
+        })
+        .await;
+}
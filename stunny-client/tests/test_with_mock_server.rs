@@ -0,0 +1,96 @@
+#![cfg(feature = "tcp")]
+use futures::future::join_all;
+use local_async_utils::sec;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use stunny_client::*;
+use stunny_core::attributes::*;
+use stunny_core::message::*;
+use stunny_core::transport::tcp::{setup_tcp_with_config, Config};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::{join, task, time};
+
+macro_rules! local_test {
+    ($($arg:tt)+) => {{
+        task::LocalSet::new().run_until(time::timeout(sec!(10), async { $($arg)+ })).await.expect("test timeout");
+    }}
+}
+
+async fn read_message(stream: &mut TcpStream) -> Message {
+    let mut header_buffer = [0u8; Header::SIZE];
+    stream.read_exact(&mut header_buffer).await.unwrap();
+    let header = Header::decode_from(&mut &header_buffer[..]).unwrap();
+
+    let mut tlvs_buffer = vec![0u8; header.length as usize];
+    stream.read_exact(&mut tlvs_buffer).await.unwrap();
+    let attributes = Vec::<Tlv>::decode_from(&mut &tlvs_buffer[..]).unwrap();
+    Message::new(header, attributes)
+}
+
+async fn write_message(stream: &mut TcpStream, message: Message) {
+    let mut buffer = Vec::new();
+    message.header.encode_into(&mut buffer).unwrap();
+    message.attributes.encode_into(&mut buffer).unwrap();
+    stream.write_all(&buffer).await.unwrap();
+}
+
+#[tokio::test]
+async fn match_responses_received_in_reverse_order_over_tcp() {
+    local_test! {
+        const REQUEST_COUNT: usize = 3;
+
+        let server_addr: SocketAddr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7850).into();
+        let listener = TcpListener::bind(server_addr).await.unwrap();
+
+        let (message_channels, pool) = setup_tcp_with_config(
+            Config {
+                max_outstanding_requests: REQUEST_COUNT,
+                ..Default::default()
+            },
+            TcpSocket::new_v4,
+        );
+        let (request_sender, _, _, processor) = setup_transactions(
+            message_channels,
+            REQUEST_COUNT,
+            NoRetransmissionsConstTimeout::new(sec!(5)),
+        );
+        task::spawn_local(async move {
+            let _result = join!(pool.run(), processor.run());
+        });
+
+        // mock server answering all requests over one connection, the last one first
+        let server = task::spawn_local(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut requests = Vec::new();
+            for _ in 0..REQUEST_COUNT {
+                requests.push(read_message(&mut stream).await);
+            }
+            for request in requests.into_iter().rev() {
+                // echo the attributes so that the client can tell the responses apart
+                let response = Message::response(
+                    request.header.method,
+                    request.header.transaction_id,
+                    request.attributes,
+                );
+                write_message(&mut stream, response).await;
+            }
+        });
+
+        let results = join_all((0..REQUEST_COUNT).map(|i| {
+            let mut attributes = Vec::new();
+            attributes.append_attribute(Software(format!("request {i}")));
+            request_sender.send_request(server_addr, BINDING_METHOD, attributes)
+        }))
+        .await;
+        server.await.unwrap();
+
+        for (i, result) in results.into_iter().enumerate() {
+            let mut response = result.unwrap();
+            assert!(response.success);
+            assert_eq!(
+                response.attributes.extract_attribute::<Software>().unwrap().0,
+                format!("request {i}")
+            );
+        }
+    }
+}
@@ -34,8 +34,11 @@ async fn parse_server_addrs<'a>(
     urls: impl IntoIterator<Item = &'a str> + 'a,
 ) -> HashSet<SocketAddr> {
     let mut ret = HashSet::new();
-    for host in urls.into_iter().filter_map(|url| url.strip_prefix("stun:")) {
-        if let Ok(addrs) = lookup_host(host).await {
+    for uri in urls
+        .into_iter()
+        .map(|url| url.parse::<ServerUri>().unwrap())
+    {
+        if let Ok(addrs) = lookup_host((uri.host.as_str(), uri.port)).await {
             ret.extend(addrs.filter(SocketAddr::is_ipv4));
         }
     }
@@ -1,7 +1,9 @@
-use crate::message::Tlv;
+use crate::message::{xor_address_value, Tlv};
 use bytes::{Buf, BufMut};
 use core::str;
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use thiserror::Error;
 
@@ -25,6 +27,13 @@ impl ParseError {
     }
 }
 
+/// Specific causes of a `ParseError`, available via `Error::source()`.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttrError {
+    #[error("unknown address family {0:#04x}")]
+    UnknownAddressFamily(u8),
+}
+
 pub trait Attribute: Sized {
     const ID: u16;
 
@@ -70,6 +79,103 @@ impl AttributeCollection for Vec<Tlv> {
 
 // ------------------------------------------------------------------------------------------------
 
+const KNOWN_ATTRIBUTE_NAMES: &[(u16, &str)] = &[
+    (0x0001, "MAPPED-ADDRESS"),
+    (0x0006, "USERNAME"),
+    (0x0008, "MESSAGE-INTEGRITY"),
+    (0x0009, "ERROR-CODE"),
+    (0x000a, "UNKNOWN-ATTRIBUTES"),
+    (0x0012, "XOR-PEER-ADDRESS"),
+    (0x0013, "DATA"),
+    (0x0014, "REALM"),
+    (0x0015, "NONCE"),
+    (0x0018, "EVEN-PORT"),
+    (0x001c, "MESSAGE-INTEGRITY-SHA256"),
+    (0x001d, "PASSWORD-ALGORITHM"),
+    (0x001e, "USERHASH"),
+    (0x0020, "XOR-MAPPED-ADDRESS"),
+    (0x0022, "RESERVATION-TOKEN"),
+    (0x0024, "PRIORITY"),
+    (0x0025, "USE-CANDIDATE"),
+    (0x8002, "PASSWORD-ALGORITHMS"),
+    (0x8003, "ALTERNATE-DOMAIN"),
+    (0x8022, "SOFTWARE"),
+    (0x8023, "ALTERNATE-SERVER"),
+    (0x8028, "FINGERPRINT"),
+    (0x8029, "ICE-CONTROLLED"),
+    (0x802a, "ICE-CONTROLLING"),
+    (0x802b, "RESPONSE-ORIGIN"),
+    (0x802c, "OTHER-ADDRESS"),
+    (0xc057, "GOOG-NETWORK-INFO"),
+    (0xc058, "GOOG-LAST-ICE-CHECK-RECEIVED"),
+    (0xc059, "GOOG-MISC-INFO"),
+];
+
+/// Name of a standard or well-known vendor-specific attribute type.
+pub fn attribute_name(attribute_type: u16) -> Option<&'static str> {
+    KNOWN_ATTRIBUTE_NAMES
+        .iter()
+        .find(|(known_type, _)| *known_type == attribute_type)
+        .map(|(_, name)| *name)
+}
+
+/// Attribute type names for diagnostics, extending the ones known by `attribute_name()`, e.g.
+/// with vendor-specific attributes.
+#[derive(Debug, Clone, Default)]
+pub struct AttributeNames(BTreeMap<u16, &'static str>);
+
+impl AttributeNames {
+    /// Overrides any previously known name of `attribute_type`.
+    pub fn with_name(mut self, attribute_type: u16, name: &'static str) -> Self {
+        self.0.insert(attribute_type, name);
+        self
+    }
+
+    pub fn get(&self, attribute_type: u16) -> Option<&'static str> {
+        self.0
+            .get(&attribute_type)
+            .copied()
+            .or_else(|| attribute_name(attribute_type))
+    }
+
+    /// Same as `AttributeType`, but with these names.
+    pub fn display(&self, attribute_type: u16) -> impl fmt::Display {
+        NamedAttributeType {
+            attribute_type,
+            name: self.get(attribute_type),
+        }
+    }
+}
+
+/// Displays an attribute type as e.g. "SOFTWARE (0x8022)", or as bare hex if the name is unknown.
+pub struct AttributeType(pub u16);
+
+impl fmt::Display for AttributeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let named = NamedAttributeType {
+            attribute_type: self.0,
+            name: attribute_name(self.0),
+        };
+        fmt::Display::fmt(&named, f)
+    }
+}
+
+struct NamedAttributeType {
+    attribute_type: u16,
+    name: Option<&'static str>,
+}
+
+impl fmt::Display for NamedAttributeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{name} ({:#06x})", self.attribute_type),
+            None => write!(f, "{:#06x}", self.attribute_type),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+
 fn encode_socket_addr(addr: SocketAddr) -> Vec<u8> {
     let mut value = Vec::with_capacity(20);
     value.put_u8(0);
@@ -113,26 +219,23 @@ fn decode_socket_addr(
 
     let ip = match family {
         0x01 => {
-            if buffer.remaining() < 4 {
-                return Err(parse_error!("not enough bytes for IPv4 address"));
+            if buffer.remaining() != 4 {
+                return Err(parse_error!("incorrect length of IPv4 address"));
             }
             let mut octets = [0u8; 4];
             buffer.copy_to_slice(octets.as_mut_slice());
             IpAddr::V4(Ipv4Addr::from(octets))
         }
         0x02 => {
-            if buffer.remaining() < 16 {
-                return Err(parse_error!("not enough bytes for IPv6 address"));
+            if buffer.remaining() != 16 {
+                return Err(parse_error!("incorrect length of IPv6 address"));
             }
             let mut octets = [0u8; 16];
             buffer.copy_to_slice(octets.as_mut_slice());
             IpAddr::V6(Ipv6Addr::from(octets))
         }
         _ => {
-            return Err(parse_error!(format!(
-                "unexpected ip version {:#04x}",
-                family
-            )));
+            return Err(parse_error!(AttrError::UnknownAddressFamily(family)));
         }
     };
 
@@ -171,6 +274,105 @@ impl Attribute for XorMappedAddress {
     }
 }
 
+impl XorMappedAddress {
+    /// Encode as the TLV to put in a message with `transaction_id`, e.g. a response echoing the
+    /// source address of a Binding request. Unlike `encode_value()`, the value is XOR-ed, so the
+    /// message must not go through `Message::xor_socket_addr()` afterwards.
+    pub fn encode_xored(self, transaction_id: &[u8; 12]) -> Tlv {
+        let mut value = self.encode_value();
+        xor_address_value(&mut value, transaction_id);
+        Tlv {
+            attribute_type: Self::ID,
+            value,
+        }
+    }
+
+    /// Inverse of `encode_xored()`.
+    pub fn decode_xored(
+        mut tlv_value: Vec<u8>,
+        transaction_id: &[u8; 12],
+    ) -> Result<Self, ParseError> {
+        xor_address_value(&mut tlv_value, transaction_id);
+        Self::decode_value(tlv_value)
+    }
+}
+
+/// TURN (RFC 8656), XOR-ed like XOR-MAPPED-ADDRESS.
+#[derive(Debug)]
+pub struct XorPeerAddress(pub SocketAddr);
+
+impl Attribute for XorPeerAddress {
+    const ID: u16 = 0x0012;
+
+    fn encode_value(self) -> Vec<u8> {
+        encode_socket_addr(self.0)
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        Ok(Self(decode_socket_addr(tlv_value, "XOR-PEER-ADDRESS")?))
+    }
+}
+
+/// TURN (RFC 8656) application data.
+#[derive(Debug)]
+pub struct Data(pub Vec<u8>);
+
+impl Attribute for Data {
+    const ID: u16 = 0x0013;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        Ok(Self(tlv_value))
+    }
+}
+
+/// TURN (RFC 8656) request for an even relayed port. If `reserve` is set, the server also
+/// reserves the next higher port and returns a RESERVATION-TOKEN for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvenPort {
+    pub reserve: bool,
+}
+
+impl Attribute for EvenPort {
+    const ID: u16 = 0x0018;
+
+    fn encode_value(self) -> Vec<u8> {
+        vec![if self.reserve { 0x80 } else { 0x00 }]
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        match tlv_value.as_slice() {
+            [flags] => Ok(Self {
+                reserve: flags & 0x80 != 0,
+            }),
+            _ => Err(ParseError::new("EVEN-PORT", "incorrect length")),
+        }
+    }
+}
+
+/// TURN (RFC 8656) token identifying a relayed port reserved by a previous allocation, to be
+/// sent in a subsequent Allocate request instead of EVEN-PORT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReservationToken(pub [u8; 8]);
+
+impl Attribute for ReservationToken {
+    const ID: u16 = 0x0022;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let token: [u8; 8] = tlv_value
+            .try_into()
+            .map_err(|_| ParseError::new("RESERVATION-TOKEN", "incorrect length"))?;
+        Ok(Self(token))
+    }
+}
+
 #[derive(Debug)]
 pub struct ResponseOrigin(pub SocketAddr);
 
@@ -186,14 +388,49 @@ impl Attribute for ResponseOrigin {
     }
 }
 
+#[derive(Debug)]
+pub struct OtherAddress(pub SocketAddr);
+
+impl Attribute for OtherAddress {
+    const ID: u16 = 0x802c;
+
+    fn encode_value(self) -> Vec<u8> {
+        encode_socket_addr(self.0)
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        Ok(Self(decode_socket_addr(tlv_value, "OTHER-ADDRESS")?))
+    }
+}
+
 #[derive(Debug)]
 pub struct Software(pub String);
 
+impl Software {
+    /// Max length of the value in bytes (RFC 8489), longer descriptions are truncated.
+    pub const MAX_LEN: usize = 763;
+
+    pub fn tlv(description: &str) -> Tlv {
+        Tlv {
+            attribute_type: Self::ID,
+            value: Self(description.to_owned()).encode_value(),
+        }
+    }
+}
+
 impl Attribute for Software {
     const ID: u16 = 0x8022;
 
     fn encode_value(self) -> Vec<u8> {
-        self.0.into_bytes()
+        let mut text = self.0;
+        if text.len() > Self::MAX_LEN {
+            let end = (0..=Self::MAX_LEN)
+                .rev()
+                .find(|&i| text.is_char_boundary(i))
+                .unwrap_or(0);
+            text.truncate(end);
+        }
+        text.into_bytes()
     }
 
     fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
@@ -203,11 +440,295 @@ impl Attribute for Software {
 }
 
 #[derive(Debug)]
+pub struct Realm(pub String);
+
+impl Attribute for Realm {
+    const ID: u16 = 0x0014;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.into_bytes()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let text = String::from_utf8(tlv_value).map_err(|e| ParseError::new("REALM", e))?;
+        Ok(Self(text))
+    }
+}
+
+#[derive(Debug)]
+pub struct Nonce(pub String);
+
+impl Attribute for Nonce {
+    const ID: u16 = 0x0015;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.into_bytes()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let text = String::from_utf8(tlv_value).map_err(|e| ParseError::new("NONCE", e))?;
+        Ok(Self(text))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordAlgorithm {
+    pub algorithm: u16,
+    pub parameters: Vec<u8>,
+}
+
+impl PasswordAlgorithm {
+    pub const MD5: u16 = 0x0001;
+    pub const SHA256: u16 = 0x0002;
+}
+
+#[derive(Debug)]
+pub struct PasswordAlgorithms(pub Vec<PasswordAlgorithm>);
+
+impl Attribute for PasswordAlgorithms {
+    const ID: u16 = 0x8002;
+
+    fn encode_value(self) -> Vec<u8> {
+        let mut value = Vec::new();
+        for entry in self.0 {
+            value.put_u16(entry.algorithm);
+            value.put_u16(entry.parameters.len() as u16);
+            let padding = (4 - entry.parameters.len() % 4) % 4;
+            value.put_slice(&entry.parameters);
+            value.put_bytes(0, padding);
+        }
+        value
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let mut buffer = tlv_value.as_slice();
+        let mut algorithms = Vec::new();
+        while buffer.has_remaining() {
+            if buffer.remaining() < 4 {
+                return Err(ParseError::new("PASSWORD-ALGORITHMS", "buffer too short"));
+            }
+            let algorithm = buffer.get_u16();
+            let params_len = buffer.get_u16() as usize;
+            let padded_len = params_len + (4 - params_len % 4) % 4;
+            if buffer.remaining() < padded_len {
+                return Err(ParseError::new(
+                    "PASSWORD-ALGORITHMS",
+                    "parameters exceed buffer",
+                ));
+            }
+            let parameters = buffer[..params_len].to_vec();
+            buffer.advance(padded_len);
+            algorithms.push(PasswordAlgorithm {
+                algorithm,
+                parameters,
+            });
+        }
+        Ok(Self(algorithms))
+    }
+}
+
+impl Attribute for PasswordAlgorithm {
+    const ID: u16 = 0x001d;
+
+    fn encode_value(self) -> Vec<u8> {
+        PasswordAlgorithms(vec![self]).encode_value()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let PasswordAlgorithms(mut algorithms) = PasswordAlgorithms::decode_value(tlv_value)?;
+        match (algorithms.pop(), algorithms.is_empty()) {
+            (Some(algorithm), true) => Ok(algorithm),
+            _ => Err(ParseError::new(
+                "PASSWORD-ALGORITHM",
+                "expected exactly one algorithm",
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Fingerprint(pub u32);
+
+impl Attribute for Fingerprint {
+    const ID: u16 = 0x8028;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let bytes: [u8; 4] = tlv_value
+            .try_into()
+            .map_err(|_| ParseError::new("FINGERPRINT", "incorrect length"))?;
+        Ok(Self(u32::from_be_bytes(bytes)))
+    }
+}
+
+#[derive(Debug)]
+pub struct Username(pub String);
+
+impl Attribute for Username {
+    const ID: u16 = 0x0006;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.into_bytes()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let text = String::from_utf8(tlv_value).map_err(|e| ParseError::new("USERNAME", e))?;
+        Ok(Self(text))
+    }
+}
+
+#[derive(Debug)]
+pub struct MessageIntegrity(pub [u8; 20]);
+
+impl Attribute for MessageIntegrity {
+    const ID: u16 = 0x0008;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let hmac: [u8; 20] = tlv_value
+            .try_into()
+            .map_err(|_| ParseError::new("MESSAGE-INTEGRITY", "incorrect length"))?;
+        Ok(Self(hmac))
+    }
+}
+
+/// HMAC-SHA256, possibly truncated to as few as 16 bytes (RFC 8489 section 14.6).
+#[derive(Debug)]
+pub struct MessageIntegritySha256(pub Vec<u8>);
+
+impl Attribute for MessageIntegritySha256 {
+    const ID: u16 = 0x001c;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        if !(16..=32).contains(&tlv_value.len()) || tlv_value.len() % 4 != 0 {
+            return Err(ParseError::new(
+                "MESSAGE-INTEGRITY-SHA256",
+                "incorrect length",
+            ));
+        }
+        Ok(Self(tlv_value))
+    }
+}
+
+#[derive(Debug)]
+pub struct Userhash(pub [u8; 32]);
+
+impl Attribute for Userhash {
+    const ID: u16 = 0x001e;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let hash: [u8; 32] = tlv_value
+            .try_into()
+            .map_err(|_| ParseError::new("USERHASH", "incorrect length"))?;
+        Ok(Self(hash))
+    }
+}
+
+#[derive(Debug)]
+pub struct Priority(pub u32);
+
+impl Attribute for Priority {
+    const ID: u16 = 0x0024;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let bytes: [u8; 4] = tlv_value
+            .try_into()
+            .map_err(|_| ParseError::new("PRIORITY", "incorrect length"))?;
+        Ok(Self(u32::from_be_bytes(bytes)))
+    }
+}
+
+#[derive(Debug)]
+pub struct UseCandidate;
+
+impl Attribute for UseCandidate {
+    const ID: u16 = 0x0025;
+
+    fn encode_value(self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn decode_value(_tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        Ok(Self)
+    }
+}
+
+#[derive(Debug)]
+pub struct IceControlled(pub u64);
+
+impl Attribute for IceControlled {
+    const ID: u16 = 0x8029;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let bytes: [u8; 8] = tlv_value
+            .try_into()
+            .map_err(|_| ParseError::new("ICE-CONTROLLED", "incorrect length"))?;
+        Ok(Self(u64::from_be_bytes(bytes)))
+    }
+}
+
+#[derive(Debug)]
+pub struct IceControlling(pub u64);
+
+impl Attribute for IceControlling {
+    const ID: u16 = 0x802a;
+
+    fn encode_value(self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        let bytes: [u8; 8] = tlv_value
+            .try_into()
+            .map_err(|_| ParseError::new("ICE-CONTROLLING", "incorrect length"))?;
+        Ok(Self(u64::from_be_bytes(bytes)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ErrorCode {
+    /// `class * 100 + number`, e.g. 401.
     pub code: u16,
     pub reason: String,
 }
 
+impl ErrorCode {
+    /// Longest reason phrase allowed by RFC 8489, in characters.
+    pub const MAX_REASON_LEN: usize = 763;
+
+    /// Hundreds digit of the code, 3 to 6.
+    pub fn class(&self) -> u8 {
+        (self.code / 100) as u8
+    }
+
+    /// Code modulo 100, 0 to 99.
+    pub fn number(&self) -> u8 {
+        (self.code % 100) as u8
+    }
+}
+
 impl Attribute for ErrorCode {
     const ID: u16 = 0x0009;
 
@@ -236,7 +757,8 @@ impl Attribute for ErrorCode {
             return Err(err!("non-zero prefix bytes"));
         }
 
-        let class = buffer.get_u8();
+        // the upper 5 bits are reserved
+        let class = buffer.get_u8() & 0x07;
         if !(3..=6).contains(&class) {
             return Err(err!(format!("invalid error class {class}")));
         }
@@ -247,6 +769,10 @@ impl Attribute for ErrorCode {
         }
 
         let reason = str::from_utf8(buffer.chunk()).map_err(|e| err!(e))?;
+        let reason = match reason.char_indices().nth(Self::MAX_REASON_LEN) {
+            Some((end, _)) => &reason[..end],
+            None => reason,
+        };
 
         Ok(Self {
             code: class as u16 * 100 + number as u16,
@@ -255,9 +781,75 @@ impl Attribute for ErrorCode {
     }
 }
 
+/// Types of the comprehension-required attributes a request was rejected for with a 420 error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownAttributes(pub Vec<u16>);
+
+impl Attribute for UnknownAttributes {
+    const ID: u16 = 0x000a;
+
+    fn encode_value(self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(2 * self.0.len());
+        for attribute_type in self.0 {
+            value.put_u16(attribute_type);
+        }
+        value
+    }
+
+    fn decode_value(tlv_value: Vec<u8>) -> Result<Self, ParseError> {
+        if tlv_value.len() % 2 != 0 {
+            return Err(ParseError::new("UNKNOWN-ATTRIBUTES", "odd length"));
+        }
+        let mut buffer = tlv_value.as_slice();
+        let mut attribute_types = Vec::with_capacity(buffer.len() / 2);
+        while buffer.has_remaining() {
+            attribute_types.push(buffer.get_u16());
+        }
+        Ok(Self(attribute_types))
+    }
+}
+
+/// How a receiver must treat an attribute, see RFC 8489 section 14.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeClass {
+    Known,
+    /// Unknown type in the 0x0000-0x7FFF range, the message must be rejected.
+    UnknownRequired,
+    /// Unknown type in the 0x8000-0xFFFF range, safe to ignore.
+    UnknownOptional,
+}
+
+impl AttributeClass {
+    /// `known` are the attribute types the receiver understands.
+    pub fn of(attribute_type: u16, known: &[u16]) -> Self {
+        if known.contains(&attribute_type) {
+            Self::Known
+        } else if attribute_type < 0x8000 {
+            Self::UnknownRequired
+        } else {
+            Self::UnknownOptional
+        }
+    }
+}
+
+/// Types of the attributes not in `known` that the receiver must understand, without duplicates
+/// and in order of appearance, e.g. for an `UnknownAttributes` reply.
+pub fn unknown_required_attributes(attributes: &[Tlv], known: &[u16]) -> Vec<u16> {
+    let mut unknown = Vec::new();
+    for tlv in attributes {
+        if AttributeClass::of(tlv.attribute_type, known) == AttributeClass::UnknownRequired
+            && !unknown.contains(&tlv.attribute_type)
+        {
+            unknown.push(tlv.attribute_type);
+        }
+    }
+    unknown
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::message::Message;
 
     #[test]
     fn test_encode_decode_mapped_ipv4_address() {
@@ -282,6 +874,89 @@ mod tests {
         assert_eq!(decoded.0, addr);
     }
 
+    #[test]
+    fn test_software_tlv() {
+        use crate::message::EncodeDecode;
+
+        let tlv = Software::tlv("stunny");
+        assert_eq!(tlv.attribute_type, 0x8022);
+        assert_eq!(tlv.value, b"stunny");
+        assert_eq!(tlv.as_software().as_deref(), Some("stunny"));
+
+        // padded on the wire, but not counted in the length
+        let mut buffer = Vec::new();
+        vec![tlv].encode_into(&mut buffer).unwrap();
+        assert_eq!(buffer, b"\x80\x22\x00\x06stunny\x00\x00");
+
+        // truncated at a character boundary
+        let tlv = Software::tlv(&"\u{20ac}".repeat(300));
+        assert_eq!(tlv.value.len(), 762);
+        assert_eq!(tlv.as_software().unwrap().chars().count(), 254);
+    }
+
+    #[test]
+    fn test_encode_decode_xored_address_rfc5769() {
+        let tid = [
+            0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+        ];
+
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 32853);
+        let tlv = XorMappedAddress(addr).encode_xored(&tid);
+        assert_eq!(tlv.attribute_type, 0x0020);
+        assert_eq!(
+            tlv.value,
+            vec![0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]
+        );
+        let decoded = XorMappedAddress::decode_xored(tlv.value, &tid).unwrap();
+        assert_eq!(decoded.0, addr);
+
+        let addr = SocketAddr::new(
+            IpAddr::V6(Ipv6Addr::new(
+                0x2001, 0xdb8, 0x1234, 0x5678, 0x11, 0x2233, 0x4455, 0x6677,
+            )),
+            32853,
+        );
+        let tlv = XorMappedAddress(addr).encode_xored(&tid);
+        assert_eq!(
+            tlv.value,
+            vec![
+                0x00, 0x02, 0xa1, 0x47, 0x01, 0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25,
+                0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9
+            ]
+        );
+        let mut attributes = Message::response(0x0001, tid, vec![tlv])
+            .xor_socket_addr(XorMappedAddress::ID)
+            .attributes;
+        let decoded = attributes.extract_attribute::<XorMappedAddress>().unwrap();
+        assert_eq!(decoded.0, addr);
+    }
+
+    #[test]
+    fn test_decode_xor_mapped_address_with_unknown_family() {
+        let mut attributes = vec![
+            Tlv {
+                attribute_type: XorMappedAddress::ID,
+                value: vec![0, 3, 48, 57, 192, 168, 1, 1],
+            },
+            Tlv {
+                attribute_type: Software::ID,
+                value: b"stunny".to_vec(),
+            },
+        ];
+
+        let error = attributes
+            .extract_attribute::<XorMappedAddress>()
+            .unwrap_err();
+        let LookupError::Malformed(error) = error else {
+            panic!("unexpected error: {error}");
+        };
+        let cause = error.source().unwrap().downcast_ref::<AttrError>();
+        assert_eq!(cause, Some(&AttrError::UnknownAddressFamily(0x03)));
+
+        let software = attributes.extract_attribute::<Software>().unwrap();
+        assert_eq!(software.0, "stunny");
+    }
+
     #[test]
     fn test_encode_decode_software() {
         let software = Software("stunny".to_owned());
@@ -316,4 +991,161 @@ mod tests {
         assert_eq!(decoded.code, 420);
         assert_eq!(decoded.reason, "");
     }
+
+    #[test]
+    fn test_encode_decode_unknown_attributes() {
+        use crate::message::EncodeDecode;
+
+        let mut attributes = Vec::new();
+        attributes.append_attribute(UnknownAttributes(vec![0x0003, 0x7777, 0x0024]));
+        let tlv = attributes.pop().unwrap();
+        assert_eq!(tlv.attribute_type, 0x000a);
+        assert_eq!(tlv.value, b"\x00\x03\x77\x77\x00\x24");
+
+        // an odd number of types is padded on the wire
+        let mut buffer = Vec::new();
+        vec![tlv.clone()].encode_into(&mut buffer).unwrap();
+        assert_eq!(buffer, b"\x00\x0a\x00\x06\x00\x03\x77\x77\x00\x24\x00\x00");
+
+        let decoded = UnknownAttributes::decode_value(tlv.value).unwrap();
+        assert_eq!(decoded.0, vec![0x0003, 0x7777, 0x0024]);
+
+        assert!(UnknownAttributes::decode_value(vec![0, 3, 0]).is_err());
+    }
+
+    #[test]
+    fn test_decode_message_integrity_sha256_and_userhash() {
+        assert_eq!(
+            MessageIntegritySha256::decode_value(vec![0xab; 16])
+                .unwrap()
+                .0,
+            vec![0xab; 16]
+        );
+        assert!(MessageIntegritySha256::decode_value(vec![0xab; 32]).is_ok());
+        assert!(MessageIntegritySha256::decode_value(vec![0xab; 12]).is_err());
+        assert!(MessageIntegritySha256::decode_value(vec![0xab; 18]).is_err());
+        assert!(MessageIntegritySha256::decode_value(vec![0xab; 36]).is_err());
+
+        assert_eq!(
+            Userhash::decode_value(vec![0xcd; 32]).unwrap().0,
+            [0xcd; 32]
+        );
+        assert!(Userhash::decode_value(vec![0xcd; 20]).is_err());
+    }
+
+    #[test]
+    fn test_classify_attributes() {
+        let known = [Username::ID, MessageIntegrity::ID];
+        assert_eq!(AttributeClass::of(0x0006, &known), AttributeClass::Known);
+        assert_eq!(
+            AttributeClass::of(0x7777, &known),
+            AttributeClass::UnknownRequired
+        );
+        assert_eq!(
+            AttributeClass::of(0x8022, &known),
+            AttributeClass::UnknownOptional
+        );
+
+        let attributes =
+            [0x0006, 0x7777, 0x8022, 0x0003, 0x7777, 0x0008].map(|attribute_type| Tlv {
+                attribute_type,
+                value: Vec::new(),
+            });
+        assert_eq!(
+            unknown_required_attributes(&attributes, &known),
+            vec![0x7777, 0x0003]
+        );
+    }
+
+    #[test]
+    fn test_display_attribute_names() {
+        assert_eq!(AttributeType(0x8022).to_string(), "SOFTWARE (0x8022)");
+        assert_eq!(
+            AttributeType(0xc057).to_string(),
+            "GOOG-NETWORK-INFO (0xc057)"
+        );
+        assert_eq!(AttributeType(0xc0ff).to_string(), "0xc0ff");
+
+        let names = AttributeNames::default()
+            .with_name(0xc0ff, "VENDOR-TEST")
+            .with_name(0x8022, "VENDOR-SOFTWARE");
+        assert_eq!(names.display(0xc0ff).to_string(), "VENDOR-TEST (0xc0ff)");
+        assert_eq!(
+            names.display(0x8022).to_string(),
+            "VENDOR-SOFTWARE (0x8022)"
+        );
+        assert_eq!(
+            names.display(0xc057).to_string(),
+            "GOOG-NETWORK-INFO (0xc057)"
+        );
+        assert_eq!(names.display(0xc0fe).to_string(), "0xc0fe");
+        assert_eq!(AttributeType(0xc0ff).to_string(), "0xc0ff");
+
+        let tlv = Tlv {
+            attribute_type: 0xc057,
+            value: vec![0x00, 0x01],
+        };
+        assert_eq!(
+            format!("{tlv:?}"),
+            "Tlv { attribute_type: GOOG-NETWORK-INFO (0xc057), value: [0, 1] }"
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_password_algorithms() {
+        let algorithms = PasswordAlgorithms(vec![
+            PasswordAlgorithm {
+                algorithm: PasswordAlgorithm::SHA256,
+                parameters: Vec::new(),
+            },
+            PasswordAlgorithm {
+                algorithm: PasswordAlgorithm::MD5,
+                parameters: vec![0xaa],
+            },
+        ]);
+        let tlv = algorithms.encode_value();
+        assert_eq!(tlv, b"\x00\x02\x00\x00\x00\x01\x00\x01\xaa\x00\x00\x00");
+
+        let decoded = PasswordAlgorithms::decode_value(tlv).unwrap();
+        assert_eq!(decoded.0.len(), 2);
+        assert_eq!(decoded.0[0].algorithm, PasswordAlgorithm::SHA256);
+        assert!(decoded.0[0].parameters.is_empty());
+        assert_eq!(decoded.0[1].algorithm, PasswordAlgorithm::MD5);
+        assert_eq!(decoded.0[1].parameters, vec![0xaa]);
+    }
+
+    #[test]
+    fn test_encode_even_port_with_reserve_bit() {
+        use crate::message::EncodeDecode;
+
+        let mut attributes = Vec::new();
+        attributes.append_attribute(EvenPort { reserve: true });
+        let mut buffer = Vec::new();
+        attributes[0].encode_into(&mut buffer).unwrap();
+        assert_eq!(buffer, [0x00, 0x18, 0x00, 0x01, 0x80, 0x00, 0x00, 0x00]);
+
+        let decoded = attributes.extract_attribute::<EvenPort>().unwrap();
+        assert!(decoded.reserve);
+        assert!(!EvenPort::decode_value(vec![0x00]).unwrap().reserve);
+        assert!(EvenPort::decode_value(vec![0x80, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_decode_reservation_token_from_response() {
+        let mut attributes = vec![
+            Tlv {
+                attribute_type: 0x0016,
+                value: vec![0x00, 0x01, 0x31, 0x48, 0x5e, 0x12, 0xa4, 0x43],
+            },
+            Tlv {
+                attribute_type: 0x0022,
+                value: vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+            },
+        ];
+        let token = attributes.extract_attribute::<ReservationToken>().unwrap();
+        assert_eq!(token, ReservationToken([1, 2, 3, 4, 5, 6, 7, 8]));
+        assert_eq!(token.encode_value(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert!(ReservationToken::decode_value(vec![0x01; 4]).is_err());
+    }
 }
@@ -1,17 +1,31 @@
+use crate::attributes::{
+    Attribute, AttributeNames, AttributeType, ErrorCode, Fingerprint, MappedAddress,
+    MessageIntegrity, Software, XorMappedAddress,
+};
 use bitvec::prelude::*;
 use bytes::{Buf, BufMut};
 use derive_more::Debug;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
 use std::borrow::Cow;
-use std::{io, iter};
+use std::net::SocketAddr;
+use std::{fmt, io, iter};
 use thiserror::Error;
+use tokio::time::Instant;
 
 #[derive(Error, Debug)]
-#[error("failed to parse message ({0})")]
-pub struct ParseError(Cow<'static, str>);
+pub enum ParseError {
+    /// Not a STUN message at all, e.g. other traffic multiplexed on the same socket.
+    #[error("not a STUN message (magic cookie {0:02x?})")]
+    InvalidMagicCookie([u8; 4]),
+
+    #[error("failed to parse message ({0})")]
+    Malformed(Cow<'static, str>),
+}
 
 impl<T: Into<Cow<'static, str>>> From<T> for ParseError {
     fn from(value: T) -> Self {
-        ParseError(value.into())
+        ParseError::Malformed(value.into())
     }
 }
 
@@ -21,12 +35,28 @@ impl From<ParseError> for io::Error {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// Construct with `new()` or one of the per-class constructors, fields may be added.
+#[derive(Debug)]
+#[non_exhaustive]
 pub struct Message {
     pub header: Header,
     pub attributes: Vec<Tlv>,
+    /// When the transport received the message from the socket. `None` for messages created
+    /// locally. Not part of the message itself, so it's ignored by comparisons.
+    pub received_at: Option<Instant>,
+    /// CRC-32 of the bytes preceding FINGERPRINT as received, see `with_received_bytes()`.
+    #[debug(skip)]
+    pub(crate) received_crc: Option<u32>,
+}
+
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        self.header == other.header && self.attributes == other.attributes
+    }
 }
 
+impl Eq for Message {}
+
 #[derive(PartialEq, Eq, Debug)]
 pub struct Header {
     pub method: u16,
@@ -43,9 +73,13 @@ pub enum Class {
     Indication,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+/// Equality and hashing consider only the attribute type and the unpadded value. Padding is
+/// never stored, so a TLV compares equal to itself after a decode/encode round-trip regardless
+/// of the padding bytes on the wire. Trailing bytes within the declared length are part of the
+/// value, however.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub struct Tlv {
-    #[debug("{attribute_type:#06x}")]
+    #[debug("{}", AttributeType(*attribute_type))]
     pub attribute_type: u16,
     pub value: Vec<u8>,
 }
@@ -57,6 +91,26 @@ macro_rules! ceil_mul_4 {
 }
 
 impl Message {
+    /// Message with a header as is, e.g. decoded from a stream. Unlike the per-class
+    /// constructors, `header.length` is not recalculated.
+    pub fn new(header: Header, attributes: Vec<Tlv>) -> Self {
+        Self {
+            header,
+            attributes,
+            received_at: None,
+            received_crc: None,
+        }
+    }
+
+    /// For diagnostics, e.g. "Response 0x0001 [XOR-MAPPED-ADDRESS (0x0020): 8 bytes]", with
+    /// attribute types named by `names`.
+    pub fn display_with<'a>(&'a self, names: &'a AttributeNames) -> impl fmt::Display + 'a {
+        MessageDisplay {
+            message: self,
+            names,
+        }
+    }
+
     pub fn request(method: u16, transaction_id: [u8; 12], attributes: Vec<Tlv>) -> Self {
         Self {
             header: Header {
@@ -66,6 +120,8 @@ impl Message {
                 length: Self::calculate_len(&attributes),
             },
             attributes,
+            received_at: None,
+            received_crc: None,
         }
     }
 
@@ -78,6 +134,8 @@ impl Message {
                 length: Self::calculate_len(&attributes),
             },
             attributes,
+            received_at: None,
+            received_crc: None,
         }
     }
 
@@ -90,6 +148,8 @@ impl Message {
                 length: Self::calculate_len(&attributes),
             },
             attributes,
+            received_at: None,
+            received_crc: None,
         }
     }
 
@@ -102,6 +162,8 @@ impl Message {
                 length: Self::calculate_len(&attributes),
             },
             attributes,
+            received_at: None,
+            received_crc: None,
         }
     }
 
@@ -109,51 +171,151 @@ impl Message {
     pub fn xor_socket_addr(mut self, xored_attribute_id: u16) -> Self {
         let tid = self.header.transaction_id;
         for tlv in &mut self.attributes {
-            if tlv.attribute_type != xored_attribute_id {
-                continue;
+            if tlv.attribute_type == xored_attribute_id {
+                xor_address_value(&mut tlv.value, &tid);
             }
-            if let Some(port_bytes) = tlv.value.get_mut(2..4) {
-                port_bytes[0] ^= MAGIC_COOKIE[0];
-                port_bytes[1] ^= MAGIC_COOKIE[1];
-            }
-            if let Some(family) = tlv.value.get(1) {
-                match *family {
-                    0x01 => {
-                        // IPv4
-                        if let Some(addr_bytes) = tlv.value.get_mut(4..8) {
-                            for (lhs, rhs) in iter::zip(addr_bytes, MAGIC_COOKIE) {
-                                *lhs ^= rhs;
-                            }
-                        }
-                    }
-                    0x02 => {
-                        // IPv6
-                        if let Some(addr_bytes) = tlv.value.get_mut(4..20) {
-                            let mut xored_with = [0u8; 16];
-                            xored_with[0..4].copy_from_slice(MAGIC_COOKIE.as_slice());
-                            xored_with[4..].copy_from_slice(tid.as_slice());
-
-                            for (lhs, rhs) in iter::zip(addr_bytes, xored_with) {
-                                *lhs ^= rhs;
-                            }
-                        }
-                    }
-                    _ => {}
-                }
+        }
+        self
+    }
+
+    /// Append MESSAGE-INTEGRITY (HMAC-SHA1) computed with `key`. Only FINGERPRINT may be
+    /// appended after it.
+    pub fn append_message_integrity(&mut self, key: &[u8]) {
+        self.header.length = Self::calculate_len(&self.attributes) + (Tlv::HEADER_SIZE + 20) as u16;
+        let hmac = Self::calculate_hmac(&self.header, &self.attributes, key);
+        self.attributes.push(Tlv {
+            attribute_type: MessageIntegrity::ID,
+            value: MessageIntegrity(hmac).encode_value(),
+        });
+    }
+
+    /// Returns `None` if the message has no MESSAGE-INTEGRITY attribute. Only FINGERPRINT may
+    /// follow it. Must be called before `xor_socket_addr()`.
+    pub fn verify_message_integrity(&self, key: &[u8]) -> Option<bool> {
+        let position = self
+            .attributes
+            .iter()
+            .position(|tlv| tlv.attribute_type == MessageIntegrity::ID)?;
+        let (preceding, rest) = self.attributes.split_at(position);
+        let trailer_valid = match &rest[1..] {
+            [] => true,
+            [last] => last.attribute_type == Fingerprint::ID,
+            _ => false,
+        };
+        // constant-time comparison, so that the HMAC can't be guessed byte by byte
+        let valid = trailer_valid
+            && self
+                .own_hmac(preceding, key)
+                .verify_slice(&rest[0].value)
+                .is_ok();
+        Some(valid)
+    }
+
+    /// Append FINGERPRINT, which must be the last attribute of a message.
+    pub fn append_fingerprint(&mut self) {
+        self.header.length = Self::calculate_len(&self.attributes) + (Tlv::HEADER_SIZE + 4) as u16;
+        let crc = self.calculate_fingerprint(&self.attributes);
+        self.attributes.push(Tlv {
+            attribute_type: Fingerprint::ID,
+            value: Fingerprint(crc).encode_value(),
+        });
+    }
+
+    /// Returns `None` if the message doesn't end with a FINGERPRINT attribute. Received messages
+    /// are checked against the bytes as received, others must be checked before
+    /// `xor_socket_addr()`.
+    pub fn verify_fingerprint(&self) -> Option<bool> {
+        let (last, preceding) = self.attributes.split_last()?;
+        if last.attribute_type != Fingerprint::ID {
+            return None;
+        }
+        let expected = match self.received_crc {
+            Some(crc) => crc ^ FINGERPRINT_XOR,
+            None => self.calculate_fingerprint(preceding),
+        };
+        let valid = match Fingerprint::decode_value(last.value.clone()) {
+            Ok(Fingerprint(crc)) => crc == expected,
+            Err(_) => false,
+        };
+        Some(valid)
+    }
+
+    /// Keep the CRC of `bytes`, the whole message as received, up to a trailing FINGERPRINT.
+    /// Re-encoding the message may not reproduce them, e.g. if the sender padded attributes with
+    /// non-zero bytes.
+    pub(crate) fn with_received_bytes(mut self, bytes: &[u8]) -> Self {
+        if let Some(last) = self.attributes.last() {
+            if last.attribute_type == Fingerprint::ID {
+                let covered_len = bytes.len().saturating_sub(last.encoded_size());
+                self.received_crc = Some(crc32fast::hash(&bytes[..covered_len]));
             }
         }
         self
     }
 
+    fn calculate_fingerprint(&self, preceding: &[Tlv]) -> u32 {
+        crc32fast::hash(&Self::encode_with(&self.header, preceding)) ^ FINGERPRINT_XOR
+    }
+
+    /// HMAC of a received message, whose length field must be adjusted to end right after
+    /// MESSAGE-INTEGRITY.
+    fn own_hmac(&self, preceding: &[Tlv], key: &[u8]) -> Hmac<Sha1> {
+        let header = Header {
+            length: Self::calculate_len(preceding) + (Tlv::HEADER_SIZE + 20) as u16,
+            ..self.header
+        };
+        Self::hmac(&header, preceding, key)
+    }
+
+    fn calculate_hmac(header: &Header, preceding: &[Tlv], key: &[u8]) -> [u8; 20] {
+        Self::hmac(header, preceding, key)
+            .finalize()
+            .into_bytes()
+            .into()
+    }
+
+    fn hmac(header: &Header, preceding: &[Tlv], key: &[u8]) -> Hmac<Sha1> {
+        let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(&Self::encode_with(header, preceding));
+        mac
+    }
+
+    /// `header` followed by `attributes`, as covered by FINGERPRINT and MESSAGE-INTEGRITY.
+    fn encode_with(header: &Header, attributes: &[Tlv]) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(Header::SIZE + header.length as usize);
+        // writing to a Vec can't fail
+        let _ = header.encode_into(&mut buffer);
+        for tlv in attributes {
+            let _ = tlv.encode_into(&mut buffer);
+        }
+        buffer
+    }
+
     fn calculate_len<'t>(attributes: impl IntoIterator<Item = &'t Tlv>) -> u16 {
-        let len: usize = attributes
-            .into_iter()
-            .map(|tlv| ceil_mul_4!(tlv.value.len()) + Tlv::HEADER_SIZE)
-            .sum();
+        let len: usize = attributes.into_iter().map(Tlv::encoded_size).sum();
         len as u16
     }
 }
 
+/// Default limit on the number of attributes in a received message.
+pub const DEFAULT_MAX_ATTRIBUTES: usize = 128;
+
+/// Decode attributes until `buffer` is exhausted, rejecting the message if it contains more than
+/// `max_attributes` of them. Bounds the work spent on messages made up of many tiny attributes.
+pub fn decode_attributes<B: Buf>(
+    buffer: &mut B,
+    max_attributes: usize,
+) -> Result<Vec<Tlv>, ParseError> {
+    let mut ret = Vec::new();
+    while buffer.has_remaining() {
+        if ret.len() == max_attributes {
+            return Err(format!("more than {max_attributes} attributes").into());
+        }
+        ret.push(Tlv::decode_from(buffer)?);
+    }
+    Ok(ret)
+}
+
 pub trait EncodeDecode: Sized {
     fn decode_from<B: Buf>(buffer: &mut B) -> Result<Self, ParseError>;
 
@@ -162,12 +324,138 @@ pub trait EncodeDecode: Sized {
 
 const MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xA4, 0x42];
 
+/// XOR the port with the top 16 bits of the magic cookie, and the address with the magic cookie
+/// (IPv4) or the magic cookie followed by `transaction_id` (IPv6). Its own inverse.
+pub(crate) fn xor_address_value(value: &mut [u8], transaction_id: &[u8; 12]) {
+    if let Some(port_bytes) = value.get_mut(2..4) {
+        port_bytes[0] ^= MAGIC_COOKIE[0];
+        port_bytes[1] ^= MAGIC_COOKIE[1];
+    }
+    if let Some(family) = value.get(1) {
+        match *family {
+            0x01 => {
+                // IPv4
+                if let Some(addr_bytes) = value.get_mut(4..8) {
+                    for (lhs, rhs) in iter::zip(addr_bytes, MAGIC_COOKIE) {
+                        *lhs ^= rhs;
+                    }
+                }
+            }
+            0x02 => {
+                // IPv6
+                if let Some(addr_bytes) = value.get_mut(4..20) {
+                    let mut xored_with = [0u8; 16];
+                    xored_with[0..4].copy_from_slice(MAGIC_COOKIE.as_slice());
+                    xored_with[4..].copy_from_slice(transaction_id.as_slice());
+
+                    for (lhs, rhs) in iter::zip(addr_bytes, xored_with) {
+                        *lhs ^= rhs;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+const FINGERPRINT_XOR: u32 = 0x5354554e;
+
 impl Header {
     pub const SIZE: usize = 20;
 }
 
 impl Tlv {
     pub const HEADER_SIZE: usize = 4;
+
+    /// Size on the wire, including header and padding.
+    pub fn encoded_size(&self) -> usize {
+        Self::HEADER_SIZE + ceil_mul_4!(self.value.len())
+    }
+
+    /// For diagnostics, e.g. "GOOG-NETWORK-INFO (0xc057): 8 bytes", with the attribute type
+    /// named by `names`.
+    pub fn display_with<'a>(&'a self, names: &'a AttributeNames) -> impl fmt::Display + 'a {
+        TlvDisplay { tlv: self, names }
+    }
+
+    /// Decode an ERROR-CODE attribute. `None` if this is a different attribute or if the value
+    /// is malformed, including a reason phrase that is not valid UTF-8.
+    pub fn as_error_code(&self) -> Option<ErrorCode> {
+        if self.attribute_type != ErrorCode::ID {
+            return None;
+        }
+        ErrorCode::decode_value(self.value.clone()).ok()
+    }
+
+    /// Decode a SOFTWARE attribute. `None` if this is a different attribute or if the value is
+    /// not valid UTF-8.
+    pub fn as_software(&self) -> Option<String> {
+        if self.attribute_type != Software::ID {
+            return None;
+        }
+        Software::decode_value(self.value.clone())
+            .ok()
+            .map(|attr| attr.0)
+    }
+
+    /// Decode a MAPPED-ADDRESS attribute, which RFC 3489 servers send instead of
+    /// XOR-MAPPED-ADDRESS. `None` if this is a different attribute or if the value is malformed,
+    /// including an address length that doesn't match the family.
+    pub fn as_mapped_address(&self) -> Option<SocketAddr> {
+        if self.attribute_type != MappedAddress::ID {
+            return None;
+        }
+        MappedAddress::decode_value(self.value.clone())
+            .ok()
+            .map(|attr| attr.0)
+    }
+
+    /// Decode a raw XOR-MAPPED-ADDRESS attribute of the message with the given transaction id,
+    /// i.e. without `Message::xor_socket_addr()` having been applied. `None` if this is a
+    /// different attribute or if the value is malformed.
+    pub fn as_xor_mapped_address(&self, transaction_id: &[u8; 12]) -> Option<SocketAddr> {
+        if self.attribute_type != XorMappedAddress::ID {
+            return None;
+        }
+        XorMappedAddress::decode_xored(self.value.clone(), transaction_id)
+            .ok()
+            .map(|attr| attr.0)
+    }
+}
+
+struct MessageDisplay<'a> {
+    message: &'a Message,
+    names: &'a AttributeNames,
+}
+
+impl fmt::Display for MessageDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.message.header;
+        write!(f, "{:?} {:#06x} [", header.class, header.method)?;
+        for (i, tlv) in self.message.attributes.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", tlv.display_with(self.names))?;
+        }
+        f.write_str("]")
+    }
+}
+
+struct TlvDisplay<'a> {
+    tlv: &'a Tlv,
+    names: &'a AttributeNames,
+}
+
+impl fmt::Display for TlvDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} bytes",
+            self.names.display(self.tlv.attribute_type),
+            self.tlv.value.len()
+        )
+    }
 }
 
 impl EncodeDecode for Header {
@@ -187,7 +475,7 @@ impl EncodeDecode for Header {
         };
 
         if magic_cookie != MAGIC_COOKIE {
-            return Err("incorrect magic cookie".into());
+            return Err(ParseError::InvalidMagicCookie(magic_cookie));
         }
 
         let mut method_class_bits = BitArray::<u16, Lsb0>::from(method_class_bytes);
@@ -304,11 +592,7 @@ impl EncodeDecode for Tlv {
 
 impl EncodeDecode for Vec<Tlv> {
     fn decode_from<B: Buf>(buffer: &mut B) -> Result<Self, ParseError> {
-        let mut ret = Vec::new();
-        while buffer.has_remaining() {
-            ret.push(Tlv::decode_from(buffer)?);
-        }
-        Ok(ret)
+        decode_attributes(buffer, DEFAULT_MAX_ATTRIBUTES)
     }
 
     fn encode_into<B: BufMut>(&self, buffer: &mut B) -> Result<(), io::Error> {
@@ -357,6 +641,30 @@ mod tests {
         assert_eq!(header.transaction_id, [0xaa; 12]);
     }
 
+    #[test]
+    fn reject_header_with_invalid_magic_cookie() {
+        // STUN-like header from a different protocol
+        #[rustfmt::skip]
+        let buffer = [
+            0x00, 0x01, 0x00, 0x0c,
+            0x00, 0x00, 0x00, 0x01,
+            0xaa, 0xaa, 0xaa, 0xaa,
+            0xaa, 0xaa, 0xaa, 0xaa,
+            0xaa, 0xaa, 0xaa, 0xaa,
+        ];
+        let mut buffer = &buffer[..];
+        assert!(matches!(
+            Header::decode_from(&mut buffer),
+            Err(ParseError::InvalidMagicCookie([0x00, 0x00, 0x00, 0x01]))
+        ));
+
+        let mut buffer = &BIND_REQUEST_HEADER[..10];
+        assert!(matches!(
+            Header::decode_from(&mut buffer),
+            Err(ParseError::Malformed(_))
+        ));
+    }
+
     #[test]
     fn encode_bind_request_header() {
         let mut buffer = [0u8; Header::SIZE];
@@ -509,4 +817,429 @@ mod tests {
         ];
         assert_eq!(Message::calculate_len(attributes.iter()), 28);
     }
+
+    #[test]
+    fn append_message_integrity() {
+        let mut message = Message::response(
+            0x0001,
+            [0xbb; 12],
+            vec![Tlv {
+                attribute_type: 0x8022,
+                value: b"Ugh".to_vec(),
+            }],
+        );
+
+        message.append_message_integrity(b"key");
+        assert_eq!(message.header.length, 32);
+        assert_eq!(
+            message.attributes.last().unwrap(),
+            &Tlv {
+                attribute_type: 0x0008,
+                value: vec![
+                    0x95, 0xaa, 0xdf, 0x04, 0x99, 0xff, 0xf6, 0x35, 0xc1, 0xa3, 0xf5, 0xf1, 0x26,
+                    0xf9, 0xd4, 0x6f, 0x75, 0x71, 0x7d, 0xc6
+                ],
+            }
+        );
+
+        assert_eq!(message.verify_message_integrity(b"key"), Some(true));
+        assert_eq!(message.verify_message_integrity(b"other key"), Some(false));
+
+        message.append_fingerprint();
+        assert_eq!(message.header.length, 40);
+        assert_eq!(message.verify_fingerprint(), Some(true));
+        assert_eq!(message.verify_message_integrity(b"key"), Some(true));
+
+        message.attributes[0].value[0] = b'u';
+        assert_eq!(message.verify_message_integrity(b"key"), Some(false));
+
+        message.attributes.clear();
+        assert_eq!(message.verify_message_integrity(b"key"), None);
+    }
+
+    #[test]
+    fn verify_rfc5769_long_term_authentication_vector() {
+        // RFC 5769 section 2.4: username "\u{30de}\u{30c8}\u{30ea}\u{30c3}\u{30af}\u{30b9}",
+        // realm "example.org", password "TheMatrIX"
+        #[rustfmt::skip]
+        const REQUEST: [u8; 116] = [
+            0x00, 0x01, 0x00, 0x60,
+            0x21, 0x12, 0xa4, 0x42,
+            0x78, 0xad, 0x34, 0x33,
+            0xc6, 0xad, 0x72, 0xc0,
+            0x29, 0xda, 0x41, 0x2e,
+            0x00, 0x06, 0x00, 0x12,
+            0xe3, 0x83, 0x9e, 0xe3,
+            0x83, 0x88, 0xe3, 0x83,
+            0xaa, 0xe3, 0x83, 0x83,
+            0xe3, 0x82, 0xaf, 0xe3,
+            0x82, 0xb9, 0x00, 0x00,
+            0x00, 0x15, 0x00, 0x1c,
+            0x66, 0x2f, 0x2f, 0x34,
+            0x39, 0x39, 0x6b, 0x39,
+            0x35, 0x34, 0x64, 0x36,
+            0x4f, 0x4c, 0x33, 0x34,
+            0x6f, 0x4c, 0x39, 0x46,
+            0x53, 0x54, 0x76, 0x79,
+            0x36, 0x34, 0x73, 0x41,
+            0x00, 0x14, 0x00, 0x0b,
+            0x65, 0x78, 0x61, 0x6d,
+            0x70, 0x6c, 0x65, 0x2e,
+            0x6f, 0x72, 0x67, 0x00,
+            0x00, 0x08, 0x00, 0x14,
+            0xf6, 0x70, 0x24, 0x65,
+            0x6d, 0xd6, 0x4a, 0x3e,
+            0x02, 0xb8, 0xe0, 0x71,
+            0x2e, 0x85, 0xc9, 0xa2,
+            0x8c, 0xa8, 0x96, 0x66,
+        ];
+        // MD5("\u{30de}\u{30c8}\u{30ea}\u{30c3}\u{30af}\u{30b9}:example.org:TheMatrIX")
+        const KEY: [u8; 16] = [
+            0xe8, 0xca, 0x7a, 0xd5, 0x9d, 0x5e, 0xb0, 0x51, 0x8e, 0x31, 0x29, 0x11, 0xd2, 0xda,
+            0xb2, 0xa9,
+        ];
+
+        let mut buffer = &REQUEST[..];
+        let mut message = Message {
+            header: Header::decode_from(&mut buffer).unwrap(),
+            attributes: Vec::<Tlv>::decode_from(&mut buffer).unwrap(),
+            received_at: None,
+            received_crc: None,
+        };
+        assert_eq!(message.verify_message_integrity(&KEY), Some(true));
+        assert_eq!(message.verify_message_integrity(b"TheMatrIX"), Some(false));
+
+        // recomputing over the same attributes must reproduce the digest from the RFC
+        let expected = message.attributes.pop().unwrap();
+        message.append_message_integrity(&KEY);
+        assert_eq!(message.attributes.last(), Some(&expected));
+        assert_eq!(message.header.length, 0x60);
+        assert_eq!(
+            Message::encode_with(&message.header, &message.attributes),
+            REQUEST
+        );
+    }
+
+    #[test]
+    fn append_and_verify_fingerprint() {
+        let mut message = Message::response(
+            0x0001,
+            [0xbb; 12],
+            vec![Tlv {
+                attribute_type: 0x8022,
+                value: b"Ugh".to_vec(),
+            }],
+        );
+        assert_eq!(message.verify_fingerprint(), None);
+
+        message.append_fingerprint();
+        assert_eq!(message.header.length, 16);
+        assert_eq!(
+            message.attributes.last().unwrap(),
+            &Tlv {
+                attribute_type: 0x8028,
+                value: vec![0x55, 0x55, 0xa7, 0xdf],
+            }
+        );
+        assert_eq!(message.verify_fingerprint(), Some(true));
+
+        message.attributes[0].value[0] = b'u';
+        assert_eq!(message.verify_fingerprint(), Some(false));
+    }
+
+    #[test]
+    fn verify_fingerprint_over_received_bytes() {
+        #[rustfmt::skip]
+        let mut received = vec![
+            0x01, 0x01, 0x00, 0x10,
+            0x21, 0x12, 0xA4, 0x42,
+            0xbb, 0xbb, 0xbb, 0xbb,
+            0xbb, 0xbb, 0xbb, 0xbb,
+            0xbb, 0xbb, 0xbb, 0xbb,
+            // SOFTWARE padded with a non-zero byte
+            0x80, 0x22, 0x00, 0x03,
+            b'U', b'g', b'h', 0xff,
+        ];
+        let crc = crc32fast::hash(&received) ^ FINGERPRINT_XOR;
+        received.extend_from_slice(&[0x80, 0x28, 0x00, 0x04]);
+        received.extend_from_slice(&crc.to_be_bytes());
+
+        let decode = |bytes: &[u8]| {
+            let mut buffer = bytes;
+            Message::new(
+                Header::decode_from(&mut buffer).unwrap(),
+                Vec::<Tlv>::decode_from(&mut buffer).unwrap(),
+            )
+        };
+
+        // re-encoding pads with zeros, so it doesn't match what was received
+        assert_eq!(decode(&received).verify_fingerprint(), Some(false));
+        assert_eq!(
+            decode(&received)
+                .with_received_bytes(&received)
+                .verify_fingerprint(),
+            Some(true)
+        );
+
+        // when: the padding is corrupted in transit
+        received[27] = 0xfe;
+
+        // then
+        assert_eq!(
+            decode(&received)
+                .with_received_bytes(&received)
+                .verify_fingerprint(),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn pad_attributes_to_multiple_of_4() {
+        let attributes: Vec<Tlv> = (1..=4)
+            .map(|len| Tlv {
+                attribute_type: 0x8000 + len as u16,
+                value: vec![0xff; len],
+            })
+            .collect();
+        let message = Message::request(0x0001, [0x11; 12], attributes.clone());
+        assert_eq!(message.header.length, 4 * Tlv::HEADER_SIZE as u16 + 4 * 4);
+
+        let mut buffer = Vec::new();
+        message.attributes.encode_into(&mut buffer).unwrap();
+        #[rustfmt::skip]
+        let expected: [u8; 32] = [
+            0x80, 0x01, 0x00, 0x01, 0xff, 0x00, 0x00, 0x00,
+            0x80, 0x02, 0x00, 0x02, 0xff, 0xff, 0x00, 0x00,
+            0x80, 0x03, 0x00, 0x03, 0xff, 0xff, 0xff, 0x00,
+            0x80, 0x04, 0x00, 0x04, 0xff, 0xff, 0xff, 0xff,
+        ];
+        assert_eq!(buffer, expected);
+        assert_eq!(buffer.len(), message.header.length as usize);
+
+        let decoded = Vec::<Tlv>::decode_from(&mut &buffer[..]).unwrap();
+        assert_eq!(decoded, attributes);
+        for tlv in &decoded {
+            assert_eq!(tlv.encoded_size(), 8);
+        }
+    }
+
+    #[test]
+    fn tlv_identity_ignores_padding() {
+        use std::collections::HashSet;
+
+        let zero_padded: [u8; 8] = [0x80, 0x22, 0x00, 0x03, b'U', b'g', b'h', 0x00];
+        let space_padded: [u8; 8] = [0x80, 0x22, 0x00, 0x03, b'U', b'g', b'h', b' '];
+        let unpadded_value: [u8; 8] = [0x80, 0x22, 0x00, 0x04, b'U', b'g', b'h', 0x00];
+
+        let mut set = HashSet::new();
+        assert!(set.insert(Tlv::decode_from(&mut &zero_padded[..]).unwrap()));
+        assert!(!set.insert(Tlv::decode_from(&mut &space_padded[..]).unwrap()));
+        assert!(set.insert(Tlv::decode_from(&mut &unpadded_value[..]).unwrap()));
+        assert!(!set.insert(Tlv {
+            attribute_type: 0x8022,
+            value: b"Ugh".to_vec(),
+        }));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn reject_excessive_number_of_attributes() {
+        // 16k zero-length attributes
+        let buffer = [0x80, 0x22, 0x00, 0x00].repeat(16 * 1024);
+
+        let mut remaining = buffer.as_slice();
+        let result = Vec::<Tlv>::decode_from(&mut remaining);
+        assert!(result.is_err());
+        // bailed out right after the limit
+        assert_eq!(
+            remaining.len(),
+            buffer.len() - DEFAULT_MAX_ATTRIBUTES * Tlv::HEADER_SIZE
+        );
+
+        let mut remaining = &buffer[..8 * Tlv::HEADER_SIZE];
+        assert!(decode_attributes(&mut remaining, 7).is_err());
+        let mut remaining = &buffer[..8 * Tlv::HEADER_SIZE];
+        assert_eq!(decode_attributes(&mut remaining, 8).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn decode_raw_xor_mapped_address() {
+        // test vectors from RFC 5769
+        let transaction_id = [
+            0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+        ];
+        let tlv = |value: &[u8]| Tlv {
+            attribute_type: XorMappedAddress::ID,
+            value: value.to_vec(),
+        };
+
+        let ipv4 = tlv(&[0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]);
+        assert_eq!(
+            ipv4.as_xor_mapped_address(&transaction_id),
+            Some("192.0.2.1:32853".parse().unwrap())
+        );
+
+        let ipv6 = tlv(&[
+            0x00, 0x02, 0xa1, 0x47, 0x01, 0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25,
+            0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9,
+        ]);
+        assert_eq!(
+            ipv6.as_xor_mapped_address(&transaction_id),
+            Some(
+                "[2001:db8:1234:5678:11:2233:4455:6677]:32853"
+                    .parse()
+                    .unwrap()
+            )
+        );
+
+        // nonzero reserved byte
+        let malformed = tlv(&[0x01, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]);
+        assert_eq!(malformed.as_xor_mapped_address(&transaction_id), None);
+        // unknown family
+        let malformed = tlv(&[0x00, 0x03, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]);
+        assert_eq!(malformed.as_xor_mapped_address(&transaction_id), None);
+        // truncated
+        let malformed = tlv(&[0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6]);
+        assert_eq!(malformed.as_xor_mapped_address(&transaction_id), None);
+        let malformed = tlv(&ipv6.value[..12]);
+        assert_eq!(malformed.as_xor_mapped_address(&transaction_id), None);
+        let malformed = tlv(&[0x00, 0x01]);
+        assert_eq!(malformed.as_xor_mapped_address(&transaction_id), None);
+        // a different attribute
+        let other = Tlv {
+            attribute_type: 0x0001,
+            value: ipv4.value.clone(),
+        };
+        assert_eq!(other.as_xor_mapped_address(&transaction_id), None);
+    }
+
+    #[test]
+    fn decode_mapped_address() {
+        let tlv = |value: &[u8]| Tlv {
+            attribute_type: MappedAddress::ID,
+            value: value.to_vec(),
+        };
+
+        let ipv4 = tlv(&[0x00, 0x01, 0x80, 0x55, 192, 0, 2, 1]);
+        assert_eq!(
+            ipv4.as_mapped_address(),
+            Some("192.0.2.1:32853".parse().unwrap())
+        );
+
+        let ipv6 = tlv(&[
+            0x00, 0x02, 0x80, 0x55, 0x20, 0x01, 0x0d, 0xb8, 0x12, 0x34, 0x56, 0x78, 0x00, 0x11,
+            0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+        ]);
+        assert_eq!(
+            ipv6.as_mapped_address(),
+            Some(
+                "[2001:db8:1234:5678:11:2233:4455:6677]:32853"
+                    .parse()
+                    .unwrap()
+            )
+        );
+
+        // nonzero reserved byte
+        assert_eq!(
+            tlv(&[0x01, 0x01, 0x80, 0x55, 192, 0, 2, 1]).as_mapped_address(),
+            None
+        );
+        // unknown family
+        assert_eq!(
+            tlv(&[0x00, 0x03, 0x80, 0x55, 192, 0, 2, 1]).as_mapped_address(),
+            None
+        );
+        // family doesn't match length
+        assert_eq!(
+            tlv(&[0x00, 0x02, 0x80, 0x55, 192, 0, 2, 1]).as_mapped_address(),
+            None
+        );
+        let ipv4_with_ipv6_length = [&[0x00, 0x01], &ipv6.value[2..]].concat();
+        assert_eq!(tlv(&ipv4_with_ipv6_length).as_mapped_address(), None);
+        // truncated
+        assert_eq!(
+            tlv(&[0x00, 0x01, 0x80, 0x55, 192, 0, 2]).as_mapped_address(),
+            None
+        );
+        assert_eq!(tlv(&[0x00, 0x01]).as_mapped_address(), None);
+        // a different attribute
+        let other = Tlv {
+            attribute_type: XorMappedAddress::ID,
+            value: ipv4.value.clone(),
+        };
+        assert_eq!(other.as_mapped_address(), None);
+    }
+
+    #[test]
+    fn decode_error_code_from_tlv() {
+        let tlv = |value: Vec<u8>| Tlv {
+            attribute_type: ErrorCode::ID,
+            value,
+        };
+
+        let error_code = tlv([&[0, 0, 4, 1][..], b"Unauthenticated"].concat())
+            .as_error_code()
+            .unwrap();
+        assert_eq!(error_code.code, 401);
+        assert_eq!(error_code.class(), 4);
+        assert_eq!(error_code.number(), 1);
+        assert_eq!(error_code.reason, "Unauthenticated");
+
+        // reserved bits around the class are ignored
+        let error_code = tlv(vec![0, 0, 0xf8 | 5, 0]).as_error_code().unwrap();
+        assert_eq!(error_code.code, 500);
+        assert_eq!(error_code.reason, "");
+
+        // the reason phrase is capped
+        let long_reason = "é".repeat(ErrorCode::MAX_REASON_LEN + 10);
+        let error_code = tlv([&[0, 0, 3, 0][..], long_reason.as_bytes()].concat())
+            .as_error_code()
+            .unwrap();
+        assert_eq!(error_code.reason.chars().count(), ErrorCode::MAX_REASON_LEN);
+
+        assert_eq!(
+            tlv([&[0, 0, 4, 0][..], &[0xff, 0xfe]].concat()).as_error_code(),
+            None
+        );
+        assert_eq!(tlv(vec![0, 0, 7, 0]).as_error_code(), None);
+        assert_eq!(tlv(vec![0, 0, 4, 100]).as_error_code(), None);
+        assert_eq!(tlv(vec![0, 0, 4]).as_error_code(), None);
+        let other = Tlv {
+            attribute_type: 0x8022,
+            value: vec![0, 0, 4, 1],
+        };
+        assert_eq!(other.as_error_code(), None);
+    }
+
+    #[test]
+    fn display_message_with_attribute_names() {
+        let names = AttributeNames::default().with_name(0xc0ff, "VENDOR-TEST");
+        let message = Message::response(
+            0x0001,
+            [0xbb; 12],
+            vec![
+                Tlv {
+                    attribute_type: 0xc0ff,
+                    value: vec![1, 2, 3],
+                },
+                Tlv {
+                    attribute_type: 0xc057,
+                    value: vec![0; 4],
+                },
+                Tlv {
+                    attribute_type: 0xc0fe,
+                    value: vec![],
+                },
+            ],
+        );
+        assert_eq!(
+            message.attributes[0].display_with(&names).to_string(),
+            "VENDOR-TEST (0xc0ff): 3 bytes"
+        );
+        assert_eq!(
+            message.display_with(&names).to_string(),
+            "Response 0x0001 [VENDOR-TEST (0xc0ff): 3 bytes, \
+             GOOG-NETWORK-INFO (0xc057): 4 bytes, 0xc0fe: 0 bytes]"
+        );
+    }
 }
@@ -19,7 +19,44 @@ pub mod tls;
 #[cfg(feature = "udp")]
 pub mod udp;
 
+/// Not derived from `max_outstanding_requests` because a single failure, e.g. a lost connection
+/// or a burst of ICMP errors, may have to complete many transactions at once.
+#[cfg(any(feature = "udp", feature = "tcp", feature = "tls"))]
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
 pub struct MessageChannels {
     pub egress_sink: mpsc::Sender<(Message, SocketAddr)>,
     pub ingress_source: mpsc::Receiver<(Message, SocketAddr)>,
+    pub event_source: mpsc::Receiver<TransportEvent>,
+}
+
+/// Out-of-band notifications from the transport about messages it could not deliver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEvent {
+    /// No connection to the address could be opened within the configured wait because the
+    /// connection limit was reached. Messages to it have been dropped.
+    ConnectionLimitReached(SocketAddr),
+    /// The connection to the address was closed locally. Messages awaiting a reply from it will
+    /// not receive one.
+    ConnectionClosed(SocketAddr),
+    /// The connection to the address was closed by the remote end, reset, or timed out. Messages
+    /// awaiting a reply from it will not receive one. Only emitted by connection pools, which
+    /// reconnect on the next message to the address.
+    ConnectionLost(SocketAddr),
+    /// The OS reported an ICMP error (e.g. port unreachable) for a datagram sent to the address.
+    /// Only emitted by UDP on Linux.
+    Unreachable(SocketAddr),
+    /// A response from the address was cut short, i.e. its header declares more attributes than
+    /// the datagram holds. The message itself has been dropped. Only emitted by UDP.
+    Truncated {
+        remote_addr: SocketAddr,
+        transaction_id: [u8; 12],
+    },
+    /// An indication has been written to a connection-oriented transport, if enabled with
+    /// `Config::confirm_delivery` of the connection pool. Never emitted by UDP, where delivery
+    /// is best-effort.
+    Delivered {
+        remote_addr: SocketAddr,
+        transaction_id: [u8; 12],
+    },
 }
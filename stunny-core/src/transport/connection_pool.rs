@@ -1,37 +1,110 @@
 use super::*;
 use futures_util::TryFutureExt;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::{split, AsyncBufReadExt, BufReader};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot, Notify, Semaphore};
+use tokio::task::JoinSet;
 use tokio::time::{self, Instant};
-use tokio::{task, try_join};
+use tokio::{select, try_join};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub max_outstanding_requests: usize,
+    /// Connections are closed and removed from the pool after being idle for this long, or
+    /// never if `None`.
+    pub idle_timeout: Option<Duration>,
+    /// A connection isn't idle while a request sent over it has been awaiting its response for
+    /// less than this.
+    pub pending_request_timeout: Duration,
+    /// Max number of simultaneously open connections, unlimited if `None`.
+    pub max_connections: Option<usize>,
+    /// How long a new connection waits for another one to close when `max_connections` has been
+    /// reached. Its messages are dropped and `TransportEvent::ConnectionLimitReached` is
+    /// emitted if none closes in time.
+    pub connection_limit_wait: Duration,
+    /// Incoming messages with more attributes than this are treated as malformed, which closes
+    /// the connection.
+    pub max_attributes: usize,
+    /// Drop responses whose transaction id doesn't match a request sent over the same
+    /// connection, so that a server can't answer transactions sent over another connection.
+    pub reject_unsolicited_responses: bool,
+    /// Emit `TransportEvent::Delivered` once an indication has been written to its connection,
+    /// for callers that wait for delivery confirmations.
+    pub confirm_delivery: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_outstanding_requests: 64,
+            idle_timeout: Some(Duration::from_secs(10)),
+            pending_request_timeout: TRANSACTION_TIMEOUT,
+            max_connections: None,
+            connection_limit_wait: Duration::from_secs(10),
+            max_attributes: DEFAULT_MAX_ATTRIBUTES,
+            reject_unsolicited_responses: false,
+            confirm_delivery: false,
+        }
+    }
+}
+
+/// The parts of `Config` that apply to each connection.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ConnectionParams {
+    inactivity_timeout: Option<Duration>,
+    pending_request_timeout: Duration,
+    max_attributes: usize,
+    reject_unsolicited_responses: bool,
+    confirm_delivery: bool,
+}
+
+impl From<&Config> for ConnectionParams {
+    fn from(config: &Config) -> Self {
+        Self {
+            inactivity_timeout: config.idle_timeout,
+            pending_request_timeout: config.pending_request_timeout,
+            max_attributes: config.max_attributes,
+            reject_unsolicited_responses: config.reject_unsolicited_responses,
+            confirm_delivery: config.confirm_delivery,
+        }
+    }
+}
 
 pub(super) fn setup_connection_pool<F: StreamFactory>(
-    max_outstanding_requests: usize,
-    connection_keep_alive: Duration,
+    config: Config,
     stream_factory: F,
 ) -> (MessageChannels, ConnectionPool<F>) {
-    let (ingress_sender, ingress_receiver) = mpsc::channel(max_outstanding_requests);
+    let (ingress_sender, ingress_receiver) = mpsc::channel(config.max_outstanding_requests);
     let (egress_sender, egress_receiver) = mpsc::channel(1);
+    let (event_sender, event_receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+    let (close_sender, close_receiver) = mpsc::channel(1);
     (
         MessageChannels {
             egress_sink: egress_sender,
             ingress_source: ingress_receiver,
+            event_source: event_receiver,
         },
         ConnectionPool {
             connections: Default::default(),
+            connection_tasks: JoinSet::new(),
+            connection_slots: config
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
             egress_source: egress_receiver,
             ingress_sink: ingress_sender,
-            max_outstanding_requests,
-            connection_keep_alive,
+            event_sink: event_sender,
+            close_sink: close_sender,
+            close_source: close_receiver,
+            config,
             stream_factory,
         },
     )
@@ -39,10 +112,14 @@ pub(super) fn setup_connection_pool<F: StreamFactory>(
 
 pub(super) struct ConnectionPool<F: StreamFactory> {
     connections: HashMap<SocketAddr, mpsc::Sender<Message>>,
+    connection_tasks: JoinSet<io::Result<()>>,
+    connection_slots: Option<Arc<Semaphore>>,
     egress_source: mpsc::Receiver<(Message, SocketAddr)>,
     ingress_sink: mpsc::Sender<(Message, SocketAddr)>,
-    max_outstanding_requests: usize,
-    connection_keep_alive: Duration,
+    event_sink: mpsc::Sender<TransportEvent>,
+    close_sink: mpsc::Sender<oneshot::Sender<()>>,
+    close_source: mpsc::Receiver<oneshot::Sender<()>>,
+    config: Config,
     stream_factory: F,
 }
 
@@ -56,74 +133,223 @@ pub(super) trait StreamFactory {
 }
 
 impl<F: StreamFactory + Clone + 'static> ConnectionPool<F> {
+    pub(super) fn closer(&self) -> PoolCloser {
+        PoolCloser(self.close_sink.clone())
+    }
+
     pub(super) async fn run(mut self) {
-        while let Some((message, remote_addr)) = self.egress_source.recv().await {
-            if let Entry::Occupied(occupied_entry) = self.connections.entry(remote_addr) {
-                match occupied_entry.get().try_reserve() {
-                    Ok(sender) => {
-                        sender.send(message);
-                        continue;
-                    }
-                    Err(mpsc::error::TrySendError::Full(_)) => {
-                        log::error!("Dropping message to {remote_addr}: tx channel is full");
-                        continue;
-                    }
-                    Err(mpsc::error::TrySendError::Closed(_)) => {
-                        log::info!("Reconnecting to {remote_addr}");
-                    }
+        loop {
+            select! {
+                egress = self.egress_source.recv() => match egress {
+                    Some((message, remote_addr)) => self.send_message(message, remote_addr),
+                    None => break,
+                },
+                Some(done_sink) = self.close_source.recv() => {
+                    self.close_all().await;
+                    let _ = done_sink.send(());
+                    break;
                 }
-                occupied_entry.remove();
             }
-            // if we ended up here, we need to create a new connection
-            match self.launch_new_connection(remote_addr) {
-                Ok(egress_sink) if egress_sink.try_send(message).is_ok() => {
-                    self.connections.insert(remote_addr, egress_sink);
+        }
+    }
+
+    fn send_message(&mut self, message: Message, remote_addr: SocketAddr) {
+        if let Entry::Occupied(occupied_entry) = self.connections.entry(remote_addr) {
+            match occupied_entry.get().try_reserve() {
+                Ok(sender) => {
+                    sender.send(message);
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    log::error!("Dropping message to {remote_addr}: tx channel is full");
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    log::info!("Reconnecting to {remote_addr}");
                 }
-                _ => log::error!("Failed to connect to {remote_addr}, dropping tx message"),
             }
+            occupied_entry.remove();
+        }
+        // if we ended up here, we need to create a new connection
+        match self.launch_new_connection(remote_addr) {
+            Ok(egress_sink) if egress_sink.try_send(message).is_ok() => {
+                self.connections.insert(remote_addr, egress_sink);
+            }
+            _ => log::error!("Failed to connect to {remote_addr}, dropping tx message"),
         }
     }
 
+    async fn close_all(&mut self) {
+        for (remote_addr, egress_sink) in self.connections.drain() {
+            if egress_sink.is_closed() {
+                continue;
+            }
+            if self
+                .event_sink
+                .try_send(TransportEvent::ConnectionClosed(remote_addr))
+                .is_err()
+            {
+                log::error!("Failed to report closing of {remote_addr}: channel is full");
+            }
+        }
+        self.connection_tasks.shutdown().await;
+        log::debug!("All connections closed");
+    }
+
     fn launch_new_connection(
         &mut self,
         remote_addr: SocketAddr,
     ) -> io::Result<mpsc::Sender<Message>> {
-        let (egress_sink, egress_source) = mpsc::channel(self.max_outstanding_requests);
+        let (egress_sink, egress_source) = mpsc::channel(self.config.max_outstanding_requests);
         let ingress_sink = self.ingress_sink.clone();
+        let event_sink = self.event_sink.clone();
+        let connection_slots = self.connection_slots.clone();
         let mut stream_factory = self.stream_factory.clone();
-        let inactivity_timeout = self.connection_keep_alive;
-        task::spawn_local(
+        let limit_wait = self.config.connection_limit_wait;
+        let params = ConnectionParams::from(&self.config);
+        // reap finished connections
+        while self.connection_tasks.try_join_next().is_some() {}
+        self.connection_tasks.spawn_local(
             async move {
+                // held for as long as the connection is open
+                let _slot = match connection_slots {
+                    None => None,
+                    Some(slots) => match time::timeout(limit_wait, slots.acquire_owned()).await {
+                        Ok(Ok(slot)) => Some(slot),
+                        _ => {
+                            let event = TransportEvent::ConnectionLimitReached(remote_addr);
+                            if event_sink.send(event).await.is_err() {
+                                log::debug!("Failed to report connection limit: channel closed");
+                            }
+                            return Err(io::Error::other("connection limit reached"));
+                        }
+                    },
+                };
                 log::trace!("Connecting to {remote_addr}");
                 let stream =
                     time::timeout(IO_TIMEOUT, stream_factory.new_connected_stream(remote_addr))
                         .await??;
                 log::debug!("Successfully connected to {remote_addr}");
-                stream
-                    .run(remote_addr, ingress_sink, egress_source, inactivity_timeout)
-                    .await?;
-                io::Result::Ok(())
+                let lost_event_sink = event_sink.clone();
+                let result = stream
+                    .run(remote_addr, ingress_sink, event_sink, egress_source, params)
+                    .await;
+                // the next message to remote_addr will open a new connection
+                if lost_event_sink
+                    .send(TransportEvent::ConnectionLost(remote_addr))
+                    .await
+                    .is_err()
+                {
+                    log::debug!("Failed to report loss of {remote_addr}: channel closed");
+                }
+                result
             }
             .inspect_err(move |e| log::warn!("Connection to {remote_addr} exited with error: {e}")),
         );
         Ok(egress_sink)
     }
 }
+/// Handle for shutting down a running connection pool.
+#[derive(Clone)]
+pub struct PoolCloser(mpsc::Sender<oneshot::Sender<()>>);
+
+impl PoolCloser {
+    /// Close all connections and make the pool's `run()` return. Outstanding messages are
+    /// dropped and `TransportEvent::ConnectionClosed` is emitted for every open connection.
+    /// Completes once all connections have been closed.
+    pub async fn close(&self) {
+        let (done_sink, done_source) = oneshot::channel();
+        if self.0.send(done_sink).await.is_ok() {
+            let _ = done_source.await;
+        }
+    }
+}
+
+/// A single connection owned by the user rather than by a pool.
+#[cfg(feature = "tcp")]
+pub(super) struct DedicatedConnection<S: Connection> {
+    stream: S,
+    remote_addr: SocketAddr,
+    egress_source: mpsc::Receiver<(Message, SocketAddr)>,
+    ingress_sink: mpsc::Sender<(Message, SocketAddr)>,
+    event_sink: mpsc::Sender<TransportEvent>,
+    params: ConnectionParams,
+}
+
+#[cfg(feature = "tcp")]
+pub(super) fn setup_dedicated_connection<S: Connection>(
+    stream: S,
+    remote_addr: SocketAddr,
+    config: &Config,
+) -> (MessageChannels, DedicatedConnection<S>) {
+    let (ingress_sender, ingress_receiver) = mpsc::channel(config.max_outstanding_requests);
+    let (egress_sender, egress_receiver) = mpsc::channel(1);
+    let (event_sender, event_receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+    (
+        MessageChannels {
+            egress_sink: egress_sender,
+            ingress_source: ingress_receiver,
+            event_source: event_receiver,
+        },
+        DedicatedConnection {
+            stream,
+            remote_addr,
+            egress_source: egress_receiver,
+            ingress_sink: ingress_sender,
+            event_sink: event_sender,
+            params: ConnectionParams::from(config),
+        },
+    )
+}
+
+#[cfg(feature = "tcp")]
+impl<S: Connection> DedicatedConnection<S> {
+    pub(super) async fn run(self) -> io::Result<()> {
+        let remote_addr = self.remote_addr;
+        let (egress_sink, egress_source) = mpsc::channel(1);
+        let mut source = self.egress_source;
+        let forward_egress = async move {
+            while let Some((message, destination)) = source.recv().await {
+                if destination != remote_addr {
+                    log::warn!("Sending message for {destination} to {remote_addr} instead");
+                }
+                if egress_sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+            io::Result::<()>::Err(io::Error::new(io::ErrorKind::BrokenPipe, "Channel closed"))
+        };
+        try_join!(
+            self.stream.run(
+                remote_addr,
+                self.ingress_sink,
+                self.event_sink,
+                egress_source,
+                self.params,
+            ),
+            forward_egress,
+        )?;
+        Ok(())
+    }
+}
+
 pub(super) trait Connection: AsyncRead + AsyncWrite + Sized {
     fn run(
         self,
         remote_addr: SocketAddr,
         ingress_sink: mpsc::Sender<(Message, SocketAddr)>,
+        event_sink: mpsc::Sender<TransportEvent>,
         egress_source: mpsc::Receiver<Message>,
-        inactivity_timeout: Duration,
+        params: ConnectionParams,
     ) -> impl Future<Output = io::Result<()>> {
         let io = split(self);
         run_connection(
             io,
             remote_addr,
             ingress_sink,
+            event_sink,
             egress_source,
-            inactivity_timeout,
+            params,
         )
     }
 }
@@ -132,26 +358,104 @@ pub(super) async fn run_connection(
     (rx, tx): (impl AsyncRead + Unpin, impl AsyncWrite + Unpin),
     remote_addr: SocketAddr,
     ingress_sink: mpsc::Sender<(Message, SocketAddr)>,
+    event_sink: mpsc::Sender<TransportEvent>,
     egress_source: mpsc::Receiver<Message>,
-    inactivity_timeout: Duration,
+    params: ConnectionParams,
 ) -> io::Result<()> {
-    let last_active = Cell::new(Instant::now());
+    let activity = Activity::new(params.pending_request_timeout);
     try_join!(
-        process_ingress(rx, ingress_sink, remote_addr, &last_active),
-        process_egress(tx, egress_source, &last_active),
-        detect_inactivity(inactivity_timeout, &last_active),
+        process_ingress(rx, ingress_sink, remote_addr, params, &activity),
+        process_egress(
+            tx,
+            egress_source,
+            event_sink,
+            remote_addr,
+            params,
+            &activity
+        ),
+        detect_inactivity(params.inactivity_timeout, &activity),
     )?;
     Ok(())
 }
 
 const BUFFER_LEN: usize = 1500;
+
+/// Largest message that can be sent over a connection.
+pub const MAX_MESSAGE_SIZE: usize = BUFFER_LEN;
 const IO_TIMEOUT: Duration = Duration::from_secs(39);
+/// RFC 5389 transaction timeout with the default RTO and retransmission count.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_millis(39500);
+
+struct Activity {
+    last_active: Cell<Instant>,
+    /// Requests sent over the connection and not answered yet, by transaction id.
+    pending_requests: RefCell<HashMap<[u8; 12], Instant>>,
+    pending_request_timeout: Duration,
+    request_answered: Notify,
+}
+
+impl Activity {
+    fn new(pending_request_timeout: Duration) -> Self {
+        Self {
+            last_active: Cell::new(Instant::now()),
+            pending_requests: Default::default(),
+            pending_request_timeout,
+            request_answered: Notify::new(),
+        }
+    }
+
+    /// Forget requests the transaction layer has given up on by now.
+    fn remove_expired_requests(&self, now: Instant) {
+        let lifetime = self.pending_request_timeout.max(TRANSACTION_TIMEOUT);
+        self.pending_requests
+            .borrow_mut()
+            .retain(|_, sent_at| *sent_at + lifetime > now);
+    }
+
+    /// Returns false for a response to a request that hasn't been sent over the connection or
+    /// has already been answered.
+    fn on_received(&self, header: &Header, received_at: Instant) -> bool {
+        self.last_active.set(received_at);
+        if matches!(header.class, Class::Response | Class::Error) {
+            let removed = self
+                .pending_requests
+                .borrow_mut()
+                .remove(&header.transaction_id);
+            if removed.is_none() {
+                return false;
+            }
+            self.request_answered.notify_one();
+        }
+        true
+    }
+
+    fn on_sent(&self, header: &Header, sent_at: Instant) {
+        self.last_active.set(sent_at);
+        if header.class == Class::Request {
+            self.remove_expired_requests(sent_at);
+            self.pending_requests
+                .borrow_mut()
+                .insert(header.transaction_id, sent_at);
+        }
+    }
+
+    /// When the connection becomes idle unless there's more activity before then.
+    fn idle_deadline(&self, timeout: Duration) -> Instant {
+        self.remove_expired_requests(Instant::now());
+        self.pending_requests
+            .borrow()
+            .values()
+            .map(|sent_at| *sent_at + self.pending_request_timeout)
+            .fold(self.last_active.get() + timeout, Instant::max)
+    }
+}
 
 async fn process_ingress(
     socket: impl AsyncRead + Unpin,
     ingress_sink: mpsc::Sender<(Message, SocketAddr)>,
     remote_addr: SocketAddr,
-    last_active: &Cell<Instant>,
+    params: ConnectionParams,
+    activity: &Activity,
 ) -> io::Result<()> {
     let mut reader = BufReader::with_capacity(BUFFER_LEN, socket);
     let mut buffer = [0u8; BUFFER_LEN];
@@ -162,15 +466,28 @@ async fn process_ingress(
         time::timeout(IO_TIMEOUT, reader.read_exact(header_buffer)).await??;
         let header = Header::decode_from(&mut &*header_buffer)?;
 
-        let tlvs_len = header.length as usize;
-        let tlvs_buffer = &mut buffer[..tlvs_len];
+        let message_len = Header::SIZE + header.length as usize;
+        let tlvs_buffer = buffer
+            .get_mut(Header::SIZE..message_len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message too long"))?;
         time::timeout(IO_TIMEOUT, reader.read_exact(tlvs_buffer)).await??;
 
-        let mut tlvs_buffer = &*tlvs_buffer;
-        let attributes = Vec::decode_from(&mut tlvs_buffer)?;
+        let mut tlvs_buffer = &buffer[Header::SIZE..message_len];
+        let attributes = decode_attributes(&mut tlvs_buffer, params.max_attributes)?;
 
-        last_active.set(Instant::now());
-        if let Err(e) = ingress_sink.try_send((Message { header, attributes }, remote_addr)) {
+        let received_at = Instant::now();
+        if !activity.on_received(&header, received_at) && params.reject_unsolicited_responses {
+            log::warn!("Dropping unsolicited response from {remote_addr}");
+            continue;
+        }
+        let message = Message {
+            header,
+            attributes,
+            received_at: Some(received_at),
+            received_crc: None,
+        }
+        .with_received_bytes(&buffer[..message_len]);
+        if let Err(e) = ingress_sink.try_send((message, remote_addr)) {
             match e {
                 mpsc::error::TrySendError::Full(_) => {
                     log::error!("Dropping message from {remote_addr}: rx channel is full");
@@ -186,7 +503,10 @@ async fn process_ingress(
 async fn process_egress(
     mut socket: impl AsyncWrite + Unpin,
     mut egress_source: mpsc::Receiver<Message>,
-    last_active: &Cell<Instant>,
+    event_sink: mpsc::Sender<TransportEvent>,
+    remote_addr: SocketAddr,
+    params: ConnectionParams,
+    activity: &Activity,
 ) -> io::Result<()> {
     let mut buffer = [0u8; BUFFER_LEN];
     loop {
@@ -201,19 +521,35 @@ async fn process_egress(
 
         let encoded_bytes = BUFFER_LEN - remaining_buffer.len();
         time::timeout(IO_TIMEOUT, socket.write_all(&buffer[..encoded_bytes])).await??;
-        last_active.set(Instant::now());
+        activity.on_sent(&message.header, Instant::now());
+
+        if params.confirm_delivery && message.header.class == Class::Indication {
+            let event = TransportEvent::Delivered {
+                remote_addr,
+                transaction_id: message.header.transaction_id,
+            };
+            if event_sink.send(event).await.is_err() {
+                log::debug!("Failed to report delivery to {remote_addr}: channel closed");
+            }
+        }
     }
 }
 
-async fn detect_inactivity(timeout: Duration, last_active: &Cell<Instant>) -> io::Result<()> {
+async fn detect_inactivity(timeout: Option<Duration>, activity: &Activity) -> io::Result<()> {
+    let Some(timeout) = timeout else {
+        return std::future::pending().await;
+    };
     loop {
-        let idle_period = last_active.get().elapsed();
-        if idle_period >= timeout {
+        let deadline = activity.idle_deadline(timeout);
+        if deadline <= Instant::now() {
             return Err(io::Error::new(
                 io::ErrorKind::TimedOut,
                 "inactivity timeout",
             ));
         }
-        time::sleep(timeout - idle_period).await;
+        select! {
+            _ = time::sleep_until(deadline) => {}
+            _ = activity.request_answered.notified() => {}
+        }
     }
 }
@@ -1,4 +1,5 @@
 use super::connection_pool::*;
+pub use super::connection_pool::{Config, PoolCloser, MAX_MESSAGE_SIZE};
 use super::*;
 use std::io;
 use std::net::SocketAddr;
@@ -7,14 +8,30 @@ use std::time::Duration;
 use tokio::net::{TcpSocket, TcpStream};
 use tokio::sync::mpsc;
 
+/// Connections are closed after `connection_keep_alive` of inactivity, even if a request sent
+/// over them is still awaiting its response. See `setup_tcp_with_config()` for more control.
 pub fn setup_tcp(
     max_outstanding_requests: usize,
     connection_keep_alive: Duration,
     socket_factory: impl Fn() -> io::Result<TcpSocket> + 'static,
+) -> (MessageChannels, TcpConnectionPool) {
+    setup_tcp_with_config(
+        Config {
+            max_outstanding_requests,
+            idle_timeout: Some(connection_keep_alive),
+            pending_request_timeout: Duration::ZERO,
+            ..Default::default()
+        },
+        socket_factory,
+    )
+}
+
+pub fn setup_tcp_with_config(
+    config: Config,
+    socket_factory: impl Fn() -> io::Result<TcpSocket> + 'static,
 ) -> (MessageChannels, TcpConnectionPool) {
     let (channels, pool) = setup_connection_pool(
-        max_outstanding_requests,
-        connection_keep_alive,
+        config,
         TcpStreamFactory {
             socket_factory: Rc::new(socket_factory),
         },
@@ -25,26 +42,53 @@ pub fn setup_tcp(
 pub struct TcpConnectionPool(ConnectionPool<TcpStreamFactory>);
 
 impl TcpConnectionPool {
+    pub fn closer(&self) -> PoolCloser {
+        self.0.closer()
+    }
+
     pub async fn run(self) {
         self.0.run().await;
     }
 }
 
+/// Connect to `remote_addr` over a connection of its own that is not shared via any pool, e.g.
+/// when several connections to the same TURN server carry different allocations. Everything sent
+/// through the returned channels goes over this connection regardless of destination address.
+pub async fn setup_tcp_connection(
+    socket: TcpSocket,
+    remote_addr: SocketAddr,
+    config: Config,
+) -> io::Result<(MessageChannels, TcpConnection)> {
+    let stream = socket.connect(remote_addr).await?;
+    let (channels, connection) = setup_dedicated_connection(stream, remote_addr, &config);
+    Ok((channels, TcpConnection(connection)))
+}
+
+pub struct TcpConnection(DedicatedConnection<TcpStream>);
+
+impl TcpConnection {
+    pub async fn run(self) -> io::Result<()> {
+        self.0.run().await
+    }
+}
+
 impl Connection for TcpStream {
     async fn run(
         mut self,
         remote_addr: SocketAddr,
         ingress_sink: mpsc::Sender<(Message, SocketAddr)>,
+        event_sink: mpsc::Sender<TransportEvent>,
         egress_source: mpsc::Receiver<Message>,
-        inactivity_timeout: Duration,
+        params: ConnectionParams,
     ) -> io::Result<()> {
         let io = self.split();
         run_connection(
             io,
             remote_addr,
             ingress_sink,
+            event_sink,
             egress_source,
-            inactivity_timeout,
+            params,
         )
         .await
     }
@@ -73,6 +117,7 @@ mod tests {
     use super::*;
     use local_async_utils::sec;
     use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::time::Duration;
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::{join, net::TcpStream, task, time};
 
@@ -209,7 +254,7 @@ mod tests {
             .with_level(log::LevelFilter::Trace)
             .init();
         local_test! {
-            let channels = setup();
+            let mut channels = setup();
             let farend_addr = local_addr(7003);
             let accept_task = task::spawn_local(accept(farend_addr));
 
@@ -222,7 +267,10 @@ mod tests {
             verify_egress!(farend_sock, BIND_REQUEST_BYTES);
 
             drop(farend_sock);
-            task::yield_now().await;
+            assert_eq!(
+                channels.event_source.recv().await.unwrap(),
+                TransportEvent::ConnectionLost(farend_addr)
+            );
             let accept_task = task::spawn_local(accept(farend_addr));
 
             channels
@@ -324,4 +372,379 @@ mod tests {
             assert_eq!(farend_sock.try_read(&mut [0u8]).unwrap_err().kind(), io::ErrorKind::ConnectionReset);
         }
     }
+
+    #[tokio::test]
+    async fn connection_limit_reached() {
+        local_test! {
+            let (mut channels, pool) = setup_tcp_with_config(
+                Config {
+                    max_outstanding_requests: 10,
+                    idle_timeout: Some(Duration::from_secs(5)),
+                    max_connections: Some(2),
+                    connection_limit_wait: Duration::from_millis(500),
+                    ..Default::default()
+                },
+                new_socket,
+            );
+            task::spawn_local(pool.run());
+
+            let farend1_addr = local_addr(7007);
+            let farend2_addr = local_addr(7008);
+            let farend3_addr = local_addr(7009);
+            let accept_task =
+                task::spawn_local(async move { join!(accept(farend1_addr), accept(farend2_addr)) });
+            channels.egress_sink.send((bind_request_msg(), farend1_addr)).await.unwrap();
+            channels.egress_sink.send((bind_request_msg(), farend2_addr)).await.unwrap();
+            let (mut farend_sock1, mut farend_sock2) = accept_task.await.unwrap();
+            verify_egress!(farend_sock1, BIND_REQUEST_BYTES);
+            verify_egress!(farend_sock2, BIND_REQUEST_BYTES);
+
+            // when: a third destination is contacted while both connections are open
+            channels.egress_sink.send((bind_request_msg(), farend3_addr)).await.unwrap();
+
+            // then: the message is dropped after the wait
+            let event = time::timeout(Duration::from_secs(5), channels.event_source.recv())
+                .await
+                .expect("timeout")
+                .unwrap();
+            assert_eq!(event, TransportEvent::ConnectionLimitReached(farend3_addr));
+
+            // when: one of the connections is closed
+            drop(farend_sock1);
+            task::yield_now().await;
+            let accept_task = task::spawn_local(accept(farend3_addr));
+            channels.egress_sink.send((bind_indication_msg(), farend3_addr)).await.unwrap();
+
+            // then: the third destination can be connected to
+            let mut farend_sock3 = accept_task.await.unwrap();
+            verify_egress!(farend_sock3, BIND_INDICATION_BYTES);
+            assert_eq!(
+                channels.event_source.try_recv().unwrap(),
+                TransportEvent::ConnectionLost(farend1_addr)
+            );
+            assert!(channels.event_source.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn route_messages_over_dedicated_connections() {
+        local_test! {
+            let server_addr = local_addr(7010);
+            let listener = {
+                let socket = new_socket().unwrap();
+                socket.bind(server_addr).unwrap();
+                socket.listen(2).unwrap()
+            };
+
+            let (mut channels1, connection1) =
+                setup_tcp_connection(new_socket().unwrap(), server_addr, Config::default())
+                    .await
+                    .unwrap();
+            let (mut server_sock1, _) = listener.accept().await.unwrap();
+            let (mut channels2, connection2) =
+                setup_tcp_connection(new_socket().unwrap(), server_addr, Config::default())
+                    .await
+                    .unwrap();
+            let (mut server_sock2, _) = listener.accept().await.unwrap();
+            task::spawn_local(connection1.run());
+            task::spawn_local(connection2.run());
+
+            channels1.egress_sink.send((bind_request_msg(), server_addr)).await.unwrap();
+            verify_egress!(server_sock1, BIND_REQUEST_BYTES);
+
+            channels2.egress_sink.send((bind_indication_msg(), server_addr)).await.unwrap();
+            verify_egress!(server_sock2, BIND_INDICATION_BYTES);
+
+            server_sock2.write_all(&BIND_RESPONSE_BYTES).await.unwrap();
+            verify_ingress!(channels2, bind_response_msg(), server_addr);
+            assert!(channels1.ingress_source.try_recv().is_err());
+
+            server_sock1.write_all(&BIND_INDICATION_BYTES).await.unwrap();
+            verify_ingress!(channels1, bind_indication_msg(), server_addr);
+            assert!(channels2.ingress_source.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn close_pool_with_open_connection() {
+        local_test! {
+            let (mut channels, pool) = setup_tcp_with_config(Config::default(), new_socket);
+            let closer = pool.closer();
+            let pool_task = task::spawn_local(pool.run());
+
+            let farend_addr = local_addr(7011);
+            let accept_task = task::spawn_local(accept(farend_addr));
+            channels.egress_sink.send((bind_request_msg(), farend_addr)).await.unwrap();
+            let mut farend_sock = accept_task.await.unwrap();
+            verify_egress!(farend_sock, BIND_REQUEST_BYTES);
+
+            // when
+            closer.close().await;
+
+            // then
+            assert!(pool_task.is_finished());
+            assert_eq!(
+                channels.event_source.try_recv().unwrap(),
+                TransportEvent::ConnectionClosed(farend_addr)
+            );
+            let read_result = farend_sock.read(&mut [0u8; 1]).await;
+            assert!(!matches!(read_result, Ok(n) if n > 0));
+            assert!(channels.ingress_source.recv().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn report_delivery_of_indications() {
+        local_test! {
+            let (mut channels, pool) = setup_tcp_with_config(
+                Config {
+                    max_outstanding_requests: 10,
+                    confirm_delivery: true,
+                    ..Default::default()
+                },
+                new_socket,
+            );
+            task::spawn_local(pool.run());
+            let farend_addr = local_addr(7012);
+            let accept_task = task::spawn_local(accept(farend_addr));
+
+            channels.egress_sink.send((bind_request_msg(), farend_addr)).await.unwrap();
+            let mut farend_sock = accept_task.await.unwrap();
+            verify_egress!(farend_sock, BIND_REQUEST_BYTES);
+            assert!(channels.event_source.try_recv().is_err());
+
+            // when
+            channels.egress_sink.send((bind_indication_msg(), farend_addr)).await.unwrap();
+
+            // then
+            let event = channels.event_source.recv().await.unwrap();
+            assert_eq!(
+                event,
+                TransportEvent::Delivered {
+                    remote_addr: farend_addr,
+                    transaction_id: bind_indication_msg().header.transaction_id,
+                }
+            );
+            let mut read_buffer = [0u8; BIND_INDICATION_BYTES.len()];
+            assert_eq!(farend_sock.try_read(&mut read_buffer).unwrap(), read_buffer.len());
+            assert_eq!(read_buffer, BIND_INDICATION_BYTES);
+        }
+    }
+
+    #[tokio::test]
+    async fn dont_report_delivery_unless_enabled() {
+        local_test! {
+            let mut channels = setup();
+            let farend_addr = local_addr(7016);
+            let accept_task = task::spawn_local(accept(farend_addr));
+
+            // when
+            channels.egress_sink.send((bind_indication_msg(), farend_addr)).await.unwrap();
+
+            // then
+            let mut farend_sock = accept_task.await.unwrap();
+            verify_egress!(farend_sock, BIND_INDICATION_BYTES);
+            assert!(channels.event_source.try_recv().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn report_every_delivery_in_a_burst() {
+        local_test! {
+            let (mut channels, pool) = setup_tcp_with_config(
+                Config {
+                    max_outstanding_requests: 1,
+                    confirm_delivery: true,
+                    ..Default::default()
+                },
+                new_socket,
+            );
+            task::spawn_local(pool.run());
+            let farend_addr = local_addr(7017);
+            let accept_task = task::spawn_local(accept(farend_addr));
+
+            channels.egress_sink.send((bind_indication_msg(), farend_addr)).await.unwrap();
+            let mut farend_sock = accept_task.await.unwrap();
+            verify_egress!(farend_sock, BIND_INDICATION_BYTES);
+
+            // when: more deliveries than max_outstanding_requests before events are read
+            for _ in 0..2 {
+                channels.egress_sink.send((bind_indication_msg(), farend_addr)).await.unwrap();
+                verify_egress!(farend_sock, BIND_INDICATION_BYTES);
+            }
+
+            // then
+            for _ in 0..3 {
+                assert!(matches!(
+                    channels.event_source.recv().await.unwrap(),
+                    TransportEvent::Delivered { .. }
+                ));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn keep_connection_with_pending_request_open() {
+        local_test! {
+            const INACTIVITY_TIMEOUT: Duration = sec!(1);
+
+            let (mut channels, pool) = setup_tcp_with_config(
+                Config {
+                    max_outstanding_requests: 1,
+                    idle_timeout: Some(INACTIVITY_TIMEOUT),
+                    ..Default::default()
+                },
+                new_socket,
+            );
+            task::spawn_local(pool.run());
+
+            let farend_addr = local_addr(7013);
+            let accept_task = task::spawn_local(accept(farend_addr));
+
+            channels
+                .egress_sink
+                .send((bind_request_msg(), farend_addr))
+                .await
+                .unwrap();
+            let mut farend_sock = accept_task.await.unwrap();
+            verify_egress!(farend_sock, BIND_REQUEST_BYTES);
+
+            // when: the response takes longer than the inactivity timeout
+            time::sleep(INACTIVITY_TIMEOUT + Duration::from_millis(500)).await;
+            assert_eq!(farend_sock.try_read(&mut [0u8]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+            let mut response_bytes = BIND_RESPONSE_BYTES;
+            response_bytes[8..20].copy_from_slice(&BIND_REQUEST_BYTES[8..20]);
+            farend_sock.write_all(&response_bytes).await.unwrap();
+            let (message, source) = channels.ingress_source.recv().await.unwrap();
+            assert_eq!(source, farend_addr);
+            assert_eq!(message.header.transaction_id, bind_request_msg().header.transaction_id);
+
+            // then: the connection is closed once idle after the response
+            time::sleep(INACTIVITY_TIMEOUT).await;
+            task::yield_now().await;
+            assert_eq!(farend_sock.try_read(&mut [0u8]).unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn never_close_idle_connection_without_idle_timeout() {
+        local_test! {
+            let (channels, pool) = setup_tcp_with_config(
+                Config {
+                    max_outstanding_requests: 1,
+                    idle_timeout: None,
+                    ..Default::default()
+                },
+                new_socket,
+            );
+            task::spawn_local(pool.run());
+
+            let farend_addr = local_addr(7018);
+            let accept_task = task::spawn_local(accept(farend_addr));
+
+            channels
+                .egress_sink
+                .send((bind_indication_msg(), farend_addr))
+                .await
+                .unwrap();
+            let mut farend_sock = accept_task.await.unwrap();
+            verify_egress!(farend_sock, BIND_INDICATION_BYTES);
+
+            time::sleep(Duration::from_secs(3600)).await;
+            task::yield_now().await;
+            assert_eq!(farend_sock.try_read(&mut [0u8]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_connection_with_request_pending_for_too_long() {
+        local_test! {
+            const INACTIVITY_TIMEOUT: Duration = sec!(1);
+            const PENDING_REQUEST_TIMEOUT: Duration = sec!(3);
+
+            let (channels, pool) = setup_tcp_with_config(
+                Config {
+                    max_outstanding_requests: 1,
+                    idle_timeout: Some(INACTIVITY_TIMEOUT),
+                    pending_request_timeout: PENDING_REQUEST_TIMEOUT,
+                    ..Default::default()
+                },
+                new_socket,
+            );
+            task::spawn_local(pool.run());
+
+            let farend_addr = local_addr(7019);
+            let accept_task = task::spawn_local(accept(farend_addr));
+
+            channels
+                .egress_sink
+                .send((bind_request_msg(), farend_addr))
+                .await
+                .unwrap();
+            let mut farend_sock = accept_task.await.unwrap();
+            verify_egress!(farend_sock, BIND_REQUEST_BYTES);
+
+            time::sleep(INACTIVITY_TIMEOUT + Duration::from_millis(500)).await;
+            task::yield_now().await;
+            assert_eq!(farend_sock.try_read(&mut [0u8]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+            time::sleep(PENDING_REQUEST_TIMEOUT - INACTIVITY_TIMEOUT).await;
+            task::yield_now().await;
+            assert_eq!(farend_sock.try_read(&mut [0u8]).unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_response_to_request_sent_over_another_connection() {
+        local_test! {
+            let (mut channels, pool) = setup_tcp_with_config(
+                Config {
+                    max_outstanding_requests: 10,
+                    reject_unsolicited_responses: true,
+                    ..Default::default()
+                },
+                new_socket,
+            );
+            task::spawn_local(pool.run());
+
+            let farend1_addr = local_addr(7014);
+            let farend2_addr = local_addr(7015);
+            let accept_task =
+                task::spawn_local(async move { join!(accept(farend1_addr), accept(farend2_addr)) });
+
+            let mut request2 = bind_request_msg();
+            request2.header.transaction_id = [0xdd; 12];
+            let mut request2_bytes = BIND_REQUEST_BYTES;
+            request2_bytes[8..20].copy_from_slice(&[0xdd; 12]);
+            channels.egress_sink.send((bind_request_msg(), farend1_addr)).await.unwrap();
+            channels.egress_sink.send((request2, farend2_addr)).await.unwrap();
+            let (mut farend_sock1, mut farend_sock2) = accept_task.await.unwrap();
+            verify_egress!(farend_sock1, BIND_REQUEST_BYTES);
+            verify_egress!(farend_sock2, request2_bytes);
+
+            let mut response1_bytes = BIND_RESPONSE_BYTES;
+            response1_bytes[8..20].copy_from_slice(&BIND_REQUEST_BYTES[8..20]);
+            let mut response1 = bind_response_msg();
+            response1.header.transaction_id = bind_request_msg().header.transaction_id;
+
+            // when: farend2 answers the request sent to farend1
+            farend_sock2.write_all(&response1_bytes).await.unwrap();
+            farend_sock2.write_all(&BIND_INDICATION_BYTES).await.unwrap();
+
+            // then
+            verify_ingress!(channels, bind_indication_msg(), farend2_addr);
+
+            // when: farend1 answers its own request, twice
+            farend_sock1.write_all(&response1_bytes).await.unwrap();
+            let (message, source) = channels.ingress_source.recv().await.unwrap();
+            assert_eq!(source, farend1_addr);
+            assert_eq!(message, response1);
+            farend_sock1.write_all(&response1_bytes).await.unwrap();
+            farend_sock1.write_all(&BIND_INDICATION_BYTES).await.unwrap();
+
+            // then: only the first response is let through
+            verify_ingress!(channels, bind_indication_msg(), farend1_addr);
+        }
+    }
 }
@@ -45,6 +45,8 @@ pub(super) fn bind_request_msg() -> Message {
             attribute_type: 0x8022,
             value: b"Ugh".to_vec(),
         }],
+        received_at: None,
+        received_crc: None,
     }
 }
 
@@ -66,6 +68,8 @@ pub(super) fn bind_response_msg() -> Message {
                 value: b"Ugh!".to_vec(),
             },
         ],
+        received_at: None,
+        received_crc: None,
     }
 }
 
@@ -78,6 +82,8 @@ pub(super) fn bind_indication_msg() -> Message {
             length: 0,
         },
         attributes: Vec::new(),
+        received_at: None,
+        received_crc: None,
     }
 }
 
@@ -1,4 +1,5 @@
 use super::connection_pool::*;
+pub use super::connection_pool::{Config, PoolCloser, MAX_MESSAGE_SIZE};
 use super::MessageChannels;
 use std::io;
 use std::net::SocketAddr;
@@ -9,18 +10,53 @@ use tokio::net::{TcpSocket, TcpStream};
 use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig};
 use tokio_rustls::{client::TlsStream, TlsConnector};
 
+/// Servers are authenticated by their IP address, see `setup_tls_with_server_names()` for
+/// authenticating them by hostname instead. Connections are closed after `connection_keep_alive`
+/// of inactivity, even if a request sent over them is still awaiting its response.
 pub fn setup_tls(
     max_outstanding_requests: usize,
     connection_keep_alive: Duration,
     socket_factory: impl Fn() -> io::Result<TcpSocket> + 'static,
     tls_config: Arc<ClientConfig>,
+) -> (MessageChannels, TlsConnectionPool) {
+    setup_tls_with_config(
+        Config {
+            max_outstanding_requests,
+            idle_timeout: Some(connection_keep_alive),
+            pending_request_timeout: Duration::ZERO,
+            ..Default::default()
+        },
+        socket_factory,
+        tls_config,
+    )
+}
+
+/// Same as `setup_tls()`, but takes the full connection pool `Config`.
+pub fn setup_tls_with_config(
+    config: Config,
+    socket_factory: impl Fn() -> io::Result<TcpSocket> + 'static,
+    tls_config: Arc<ClientConfig>,
+) -> (MessageChannels, TlsConnectionPool) {
+    setup_tls_with_server_names(config, socket_factory, tls_config, |remote_addr| {
+        ServerName::IpAddress(remote_addr.ip().into())
+    })
+}
+
+/// Same as `setup_tls_with_config()`, but the name sent in SNI and verified against the server
+/// certificate is given by `server_name` for each address a connection is opened to, e.g. the
+/// hostname the address was resolved from.
+pub fn setup_tls_with_server_names(
+    config: Config,
+    socket_factory: impl Fn() -> io::Result<TcpSocket> + 'static,
+    tls_config: Arc<ClientConfig>,
+    server_name: impl Fn(SocketAddr) -> ServerName<'static> + 'static,
 ) -> (MessageChannels, TlsConnectionPool) {
     let (channels, pool) = setup_connection_pool(
-        max_outstanding_requests,
-        connection_keep_alive,
+        config,
         TlsStreamFactory {
             tls_connector: TlsConnector::from(tls_config),
             socket_factory: Rc::new(socket_factory),
+            server_name: Rc::new(server_name),
         },
     );
     (channels, TlsConnectionPool(pool))
@@ -29,6 +65,10 @@ pub fn setup_tls(
 pub struct TlsConnectionPool(ConnectionPool<TlsStreamFactory>);
 
 impl TlsConnectionPool {
+    pub fn closer(&self) -> PoolCloser {
+        self.0.closer()
+    }
+
     pub async fn run(self) {
         self.0.run().await;
     }
@@ -40,6 +80,7 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> Connection for TlsStream<IO> {}
 struct TlsStreamFactory {
     tls_connector: TlsConnector,
     socket_factory: Rc<dyn Fn() -> io::Result<TcpSocket>>,
+    server_name: Rc<dyn Fn(SocketAddr) -> ServerName<'static>>,
 }
 
 impl StreamFactory for TlsStreamFactory {
@@ -53,7 +94,7 @@ impl StreamFactory for TlsStreamFactory {
         let stream = socket.connect(remote_addr).await?;
         let stream = self
             .tls_connector
-            .connect(ServerName::IpAddress(remote_addr.ip().into()), stream)
+            .connect((self.server_name)(remote_addr), stream)
             .await?;
         Ok(stream)
     }
@@ -1,5 +1,6 @@
 use super::*;
 use bytes::{Buf, BufMut};
+use socket2::{Domain, Protocol, Socket, Type};
 use std::future::Future;
 use std::io;
 use std::mem::MaybeUninit;
@@ -8,8 +9,9 @@ use std::pin::Pin;
 use std::task::{ready, Context, Poll};
 use tokio::io::ReadBuf;
 use tokio::net::UdpSocket;
-use tokio::sync::mpsc;
-use tokio::try_join;
+use tokio::select;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
 
 pub fn setup_udp(
     socket: UdpSocket,
@@ -17,53 +19,229 @@ pub fn setup_udp(
 ) -> (MessageChannels, IoDriver) {
     let (ingress_sender, ingress_receiver) = mpsc::channel(max_outstanding_requests);
     let (egress_sender, egress_receiver) = mpsc::channel(max_outstanding_requests);
+    let (event_sender, event_receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+    let (close_sender, close_receiver) = mpsc::channel(1);
+    #[cfg(target_os = "linux")]
+    if let Err(e) = enable_icmp_errors(&socket) {
+        log::warn!("Failed to enable reporting of ICMP errors: {e}");
+    }
     (
         MessageChannels {
             egress_sink: egress_sender,
             ingress_source: ingress_receiver,
+            event_source: event_receiver,
         },
         IoDriver {
             socket,
             ingress_sender,
             egress_receiver,
+            event_sender,
+            ingress_tap: None,
+            max_attributes: DEFAULT_MAX_ATTRIBUTES,
+            trailing_data: TrailingData::default(),
+            close_sink: close_sender,
+            close_source: close_receiver,
         },
     )
 }
 
+/// Socket options applied before binding in `setup_udp_bind()`.
+#[derive(Debug, Clone, Default)]
+pub struct BindOptions {
+    /// Set SO_REUSEADDR.
+    pub reuse_address: bool,
+    /// Set SO_REUSEPORT. Only available on Unix-like systems, elsewhere binding fails with
+    /// `io::ErrorKind::Unsupported`. On Linux every socket sharing the port must set it, and
+    /// incoming datagrams are distributed between them. On BSD and macOS the most recently
+    /// bound socket receives all unicast datagrams.
+    pub reuse_port: bool,
+    /// Bind to a network interface by name (e.g. "eth0") with SO_BINDTODEVICE, so that all
+    /// traffic goes over that link regardless of the routing table. Only available on Linux,
+    /// elsewhere binding fails with `io::ErrorKind::Unsupported`. Kernels older than 5.7 require
+    /// CAP_NET_RAW, without it binding fails with `io::ErrorKind::PermissionDenied`.
+    pub device: Option<String>,
+    /// Clear IPV6_V6ONLY, so that a socket bound to an IPv6 address (typically `[::]`) can reach
+    /// IPv4 destinations too. Ignored when binding to an IPv4 address.
+    pub dual_stack: bool,
+}
+
+/// Same as `setup_udp()` but creates and binds the socket itself, applying `options` first.
+/// Must be called from within a Tokio runtime.
+pub fn setup_udp_bind(
+    local_addr: SocketAddr,
+    options: BindOptions,
+    max_outstanding_requests: usize,
+) -> io::Result<(MessageChannels, IoDriver)> {
+    let socket = bind_socket(local_addr, &options)?;
+    Ok(setup_udp(socket, max_outstanding_requests))
+}
+
+fn bind_socket(local_addr: SocketAddr, options: &BindOptions) -> io::Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(local_addr),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(options.reuse_address)?;
+    if options.dual_stack && local_addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+    if options.reuse_port {
+        set_reuse_port(&socket)?;
+    }
+    if let Some(device) = &options.device {
+        bind_device(&socket, device)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&local_addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+fn set_reuse_port(_socket: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn bind_device(socket: &Socket, device: &str) -> io::Result<()> {
+    socket.bind_device(Some(device.as_bytes()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_device(_socket: &Socket, _device: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_BINDTODEVICE is not supported on this platform",
+    ))
+}
+
+/// How to treat bytes after the end of a message (as declared in its header) within the same
+/// datagram, e.g. padding added by some middleboxes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrailingData {
+    /// Discard the whole datagram.
+    #[default]
+    Reject,
+    /// Decode the message and ignore the rest of the datagram.
+    Ignore,
+}
+
+type IngressTap = Box<dyn FnMut(&[u8], SocketAddr) + Send>;
+
 pub struct IoDriver {
     socket: UdpSocket,
     ingress_sender: mpsc::Sender<(Message, SocketAddr)>,
     egress_receiver: mpsc::Receiver<(Message, SocketAddr)>,
+    event_sender: mpsc::Sender<TransportEvent>,
+    ingress_tap: Option<IngressTap>,
+    max_attributes: usize,
+    trailing_data: TrailingData,
+    close_sink: mpsc::Sender<oneshot::Sender<()>>,
+    close_source: mpsc::Receiver<oneshot::Sender<()>>,
 }
 
 impl IoDriver {
-    pub async fn run(self) -> io::Result<()> {
+    /// Address the socket is bound to, e.g. to learn the port picked by the OS when binding to
+    /// port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Register a callback receiving the raw bytes and source of every incoming datagram before
+    /// it is parsed, including malformed ones. Invoked synchronously, so it must not block.
+    pub fn set_ingress_tap(&mut self, tap: impl FnMut(&[u8], SocketAddr) + Send + 'static) {
+        self.ingress_tap = Some(Box::new(tap));
+    }
+
+    /// Discard incoming messages with more than `max_attributes` attributes. Defaults to
+    /// `DEFAULT_MAX_ATTRIBUTES`.
+    pub fn set_max_attributes(&mut self, max_attributes: usize) {
+        self.max_attributes = max_attributes;
+    }
+
+    /// Defaults to `TrailingData::Reject`. Datagrams shorter than the declared message length are
+    /// always discarded.
+    pub fn set_trailing_data(&mut self, trailing_data: TrailingData) {
+        self.trailing_data = trailing_data;
+    }
+
+    pub fn closer(&self) -> DriverCloser {
+        DriverCloser(self.close_sink.clone())
+    }
+
+    /// Returns `Ok(())` once closed with `DriverCloser::close()` or once the other end of the
+    /// message channels has been dropped, e.g. when the transaction processor has exited.
+    pub async fn run(mut self) -> io::Result<()> {
         let ingress = Ingress {
             socket: &self.socket,
             buffer: [MaybeUninit::uninit(); BUFFER_LEN],
             sink: self.ingress_sender,
+            event_sink: self.event_sender,
+            tap: self.ingress_tap,
+            max_attributes: self.max_attributes,
+            trailing_data: self.trailing_data,
         };
         let egress = Egress {
             socket: &self.socket,
+            map_ipv4: self.socket.local_addr()?.is_ipv6(),
             buffer_pos: ([0u8; BUFFER_LEN], 0),
             pending_recipient: None,
             source: self.egress_receiver,
         };
-        try_join!(ingress, egress)?;
+        let done_sink = select! {
+            result = ingress => return result,
+            result = egress => return result,
+            Some(done_sink) = self.close_source.recv() => done_sink,
+        };
+        log::debug!("UDP driver closed");
+        let _ = done_sink.send(());
         Ok(())
     }
 }
 
+/// Handle for stopping a running `IoDriver`.
+#[derive(Clone)]
+pub struct DriverCloser(mpsc::Sender<oneshot::Sender<()>>);
+
+impl DriverCloser {
+    /// Make the driver's `run()` return. Messages that haven't been sent yet are dropped.
+    /// Completes once the driver has stopped.
+    pub async fn close(&self) {
+        let (done_sink, done_source) = oneshot::channel();
+        if self.0.send(done_sink).await.is_ok() {
+            let _ = done_source.await;
+        }
+    }
+}
+
 const BUFFER_LEN: usize = 1500;
 
+/// Largest message that avoids IP fragmentation on a standard Ethernet link over both IPv4 and
+/// IPv6 (1500 bytes MTU minus 40 bytes IPv6 header and 8 bytes UDP header).
+pub const MAX_MESSAGE_SIZE: usize = 1452;
+
 struct Ingress<'s> {
     socket: &'s UdpSocket,
     buffer: [MaybeUninit<u8>; BUFFER_LEN],
     sink: mpsc::Sender<(Message, SocketAddr)>,
+    event_sink: mpsc::Sender<TransportEvent>,
+    tap: Option<IngressTap>,
+    max_attributes: usize,
+    trailing_data: TrailingData,
 }
 
 struct Egress<'s> {
     socket: &'s UdpSocket,
+    /// Send to IPv4 destinations via their IPv4-mapped IPv6 address, as required on IPv6 sockets.
+    map_ipv4: bool,
     buffer_pos: ([u8; BUFFER_LEN], usize),
     pending_recipient: Option<SocketAddr>,
     source: mpsc::Receiver<(Message, SocketAddr)>,
@@ -73,36 +251,97 @@ impl Future for Ingress<'_> {
     type Output = io::Result<()>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        fn decode_msg(mut buffer: &[u8]) -> Result<Message, ParseError> {
+        fn decode_msg(
+            datagram: &[u8],
+            received_at: Instant,
+            max_attributes: usize,
+            trailing_data: TrailingData,
+        ) -> Result<Message, ParseError> {
+            let mut buffer = datagram;
             let header = Header::decode_from(&mut buffer)?;
+            let body_len = header.length as usize;
+            if buffer.len() < body_len {
+                return Err("message length exceeds datagram size".into());
+            }
+            if buffer.len() > body_len {
+                // STUN over UDP is strictly one message per datagram
+                match trailing_data {
+                    TrailingData::Reject => return Err("trailing data after message".into()),
+                    TrailingData::Ignore => {
+                        log::debug!("Ignoring {} bytes after message", buffer.len() - body_len)
+                    }
+                }
+            }
             let mut buffer = Buf::take(buffer, header.length as usize);
-            let attributes = Vec::decode_from(&mut buffer)?;
-            Ok(Message { header, attributes })
+            let attributes = decode_attributes(&mut buffer, max_attributes)?;
+            let message = Message {
+                header,
+                attributes,
+                received_at: Some(received_at),
+                received_crc: None,
+            };
+            Ok(message.with_received_bytes(&datagram[..Header::SIZE + body_len]))
         }
 
         let Ingress {
             socket,
             buffer,
             sink,
+            event_sink,
+            tap,
+            max_attributes,
+            trailing_data,
         } = self.get_mut();
         let mut buffer = ReadBuf::uninit(buffer);
         loop {
             buffer.clear();
-            let src_addr = ready!(socket.poll_recv_from(cx, &mut buffer))
-                .inspect_err(|e| log::error!("Failed to receive UDP packet: {e}"))?;
-            let message = match decode_msg(buffer.filled()) {
+            let src_addr = match ready!(socket.poll_recv_from(cx, &mut buffer)) {
+                Ok(src_addr) => unmap_ipv4(src_addr),
+                Err(e) if is_icmp_error(&e) => {
+                    report_icmp_error(socket, event_sink, &e);
+                    continue;
+                }
+                Err(e) => {
+                    log::error!("Failed to receive UDP packet: {e}");
+                    return Poll::Ready(Err(e));
+                }
+            };
+            let received_at = Instant::now();
+            if let Some(tap) = tap {
+                tap(buffer.filled(), src_addr);
+            }
+            let message = match decode_msg(
+                buffer.filled(),
+                received_at,
+                *max_attributes,
+                *trailing_data,
+            ) {
+                Err(e @ ParseError::InvalidMagicCookie(_)) => {
+                    // anyone can send us datagrams, don't flood the log with them
+                    log::debug!("Discarding datagram from {src_addr}: {e}");
+                    continue;
+                }
                 Err(e) => {
                     log::error!("Discarding message from {src_addr}: {e}");
+                    if let Some(transaction_id) = truncated_response_id(buffer.filled()) {
+                        let event = TransportEvent::Truncated {
+                            remote_addr: src_addr,
+                            transaction_id,
+                        };
+                        if event_sink.try_send(event).is_err() {
+                            log::error!(
+                                "Failed to report truncated response from {src_addr}: channel is full"
+                            );
+                        }
+                    }
                     continue;
                 }
                 Ok(msg) => msg,
             };
             match sink.try_send((message, src_addr)) {
                 Err(mpsc::error::TrySendError::Closed(_)) => {
-                    return Poll::Ready(Err(io::Error::new(
-                        io::ErrorKind::BrokenPipe,
-                        "Channel closed",
-                    )));
+                    log::debug!("Ingress channel closed");
+                    return Poll::Ready(Ok(()));
                 }
                 Err(mpsc::error::TrySendError::Full(_)) => {
                     log::error!("Dropping message from {src_addr}: rx channel is full");
@@ -114,6 +353,143 @@ impl Future for Ingress<'_> {
     }
 }
 
+/// An ICMP error in response to an earlier datagram, surfaced by the OS on the next receive.
+/// Port unreachable is reported as `ConnectionRefused` on Linux and `ConnectionReset` on Windows.
+/// With IP_RECVERR, Linux also surfaces every other ICMP error, e.g. host unreachable.
+fn is_icmp_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+    ) || is_recverr_icmp_error(e)
+}
+
+/// Errnos the kernel converts ICMP errors to, see `icmp_err_convert` in net/ipv4/icmp.c.
+#[cfg(target_os = "linux")]
+fn is_recverr_icmp_error(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(
+            libc::ENETUNREACH
+                | libc::EHOSTUNREACH
+                | libc::EHOSTDOWN
+                | libc::ENONET
+                | libc::ENOPROTOOPT
+                | libc::EOPNOTSUPP
+                | libc::EMSGSIZE
+                | libc::EPROTO
+        )
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_recverr_icmp_error(_e: &io::Error) -> bool {
+    false
+}
+
+/// Enable IP_RECVERR so that ICMP errors are reported on an unconnected socket, and queued
+/// together with the destination of the offending datagram.
+#[cfg(target_os = "linux")]
+fn enable_icmp_errors(socket: &UdpSocket) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let (level, option) = match socket.local_addr()? {
+        SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_RECVERR),
+        SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_RECVERR),
+    };
+    let enable: libc::c_int = 1;
+    // SAFETY: valid fd, and the option value is a c_int of the given size
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            option,
+            &enable as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Sources received on a dual-stack socket as IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) in
+/// their IPv4 form, so that they match the destinations the user sent to.
+fn unmap_ipv4(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(ip.into(), v6.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
+    }
+}
+
+fn map_to_ipv6(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(v4) => SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port()),
+        SocketAddr::V6(_) => addr,
+    }
+}
+
+/// Transaction id of a response (success or error) whose declared length exceeds the datagram.
+fn truncated_response_id(mut datagram: &[u8]) -> Option<[u8; 12]> {
+    let header = Header::decode_from(&mut datagram).ok()?;
+    let is_response = matches!(header.class, Class::Response | Class::Error);
+    (is_response && datagram.len() < header.length as usize).then_some(header.transaction_id)
+}
+
+/// Drain the socket error queue and emit `Unreachable` for the destination of every datagram
+/// that triggered an ICMP error, except for fragmentation needed (`EMSGSIZE`), which only means
+/// that the datagram exceeded the path MTU.
+#[cfg(target_os = "linux")]
+fn report_icmp_error(
+    socket: &UdpSocket,
+    event_sink: &mpsc::Sender<TransportEvent>,
+    error: &io::Error,
+) {
+    let unreachable = error.raw_os_error() != Some(libc::EMSGSIZE);
+    let socket = socket2::SockRef::from(socket);
+    // only the address matters, the returned payload (a copy of the datagram) may be truncated
+    let mut buffer = [MaybeUninit::uninit(); 64];
+    loop {
+        match socket.recv_from_with_flags(&mut buffer, libc::MSG_ERRQUEUE) {
+            Ok((_, addr)) => {
+                let Some(dest_addr) = addr.as_socket().map(unmap_ipv4) else {
+                    continue;
+                };
+                if !unreachable {
+                    log::warn!("Datagram to {dest_addr} dropped: {error}");
+                    continue;
+                }
+                log::warn!("{dest_addr} is unreachable ({error})");
+                if event_sink
+                    .try_send(TransportEvent::Unreachable(dest_addr))
+                    .is_err()
+                {
+                    log::error!("Failed to report unreachable {dest_addr}: channel is full");
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                log::error!("Failed to read socket error queue: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Elsewhere the destination can't be determined, so the error is only logged.
+#[cfg(not(target_os = "linux"))]
+fn report_icmp_error(
+    _socket: &UdpSocket,
+    _event_sink: &mpsc::Sender<TransportEvent>,
+    error: &io::Error,
+) {
+    log::warn!("Received ICMP error in response to an earlier datagram: {error}");
+}
+
 impl Future for Egress<'_> {
     type Output = io::Result<()>;
 
@@ -126,6 +502,7 @@ impl Future for Egress<'_> {
 
         let Egress {
             socket,
+            map_ipv4,
             buffer_pos: (buffer, pos),
             pending_recipient,
             source,
@@ -134,14 +511,20 @@ impl Future for Egress<'_> {
             match pending_recipient {
                 None => match ready!(source.poll_recv(cx)) {
                     None => {
-                        return Poll::Ready(Err(io::Error::new(
-                            io::ErrorKind::BrokenPipe,
-                            "Channel closed",
-                        )));
+                        log::debug!("Egress channel closed");
+                        return Poll::Ready(Ok(()));
                     }
                     Some((message, dest_addr)) => {
                         let mut remaining_buffer = buffer.as_mut_slice();
                         match encode_msg(&message, &mut remaining_buffer) {
+                            Ok(_)
+                                if BUFFER_LEN - remaining_buffer.len()
+                                    != Header::SIZE + message.header.length as usize =>
+                            {
+                                log::error!(
+                                    "Discarding message to {dest_addr}: length field doesn't match attributes"
+                                );
+                            }
                             Ok(_) => {
                                 *pos = BUFFER_LEN - remaining_buffer.len();
                                 *pending_recipient = Some(dest_addr);
@@ -156,7 +539,12 @@ impl Future for Egress<'_> {
                 Some(dest_addr) => {
                     let dest_addr = *dest_addr;
                     let data_len = *pos;
-                    let send_result = ready!(socket.poll_send_to(cx, &buffer[..*pos], dest_addr));
+                    let send_addr = if *map_ipv4 {
+                        map_to_ipv6(dest_addr)
+                    } else {
+                        dest_addr
+                    };
+                    let send_result = ready!(socket.poll_send_to(cx, &buffer[..*pos], send_addr));
                     *pending_recipient = None;
                     *pos = 0;
 
@@ -181,6 +569,7 @@ mod tests {
     use super::*;
     use local_async_utils::sec;
     use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::sync::{Arc, Mutex};
     use tokio::{task, time::timeout};
 
     async fn create_ipv4_socket(port: u16) -> io::Result<UdpSocket> {
@@ -201,6 +590,7 @@ mod tests {
             MessageChannels {
                 egress_sink: _tx_channel,
                 ingress_source: mut rx_channel,
+                ..
             },
             runner,
         ) = setup_udp(socket, 10);
@@ -252,6 +642,7 @@ mod tests {
             MessageChannels {
                 egress_sink: _tx_channel,
                 ingress_source: mut rx_channel,
+                ..
             },
             runner,
         ) = setup_udp(socket, 10);
@@ -275,6 +666,123 @@ mod tests {
         assert_eq!(receved_msg, bind_response_msg());
     }
 
+    #[tokio::test]
+    async fn discard_concatenated_messages() {
+        let sender_port = 7798u16;
+        let receiver_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7799);
+
+        let sender_sock = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, sender_port))
+            .await
+            .unwrap();
+
+        let socket = create_ipv4_socket(receiver_addr.port()).await.unwrap();
+        let (
+            MessageChannels {
+                egress_sink: _tx_channel,
+                ingress_source: mut rx_channel,
+                ..
+            },
+            runner,
+        ) = setup_udp(socket, 10);
+        task::spawn(runner.run());
+
+        let concatenated = [BIND_REQUEST_BYTES.as_slice(), &BIND_INDICATION_BYTES].concat();
+        sender_sock
+            .send_to(&concatenated, receiver_addr)
+            .await
+            .unwrap();
+        sender_sock
+            .send_to(&BIND_RESPONSE_BYTES, receiver_addr)
+            .await
+            .unwrap();
+
+        let (receved_msg, _) = timeout(sec!(5), rx_channel.recv()).await.unwrap().unwrap();
+        assert_eq!(receved_msg, bind_response_msg());
+    }
+
+    #[tokio::test]
+    async fn handle_trailing_data_as_configured() {
+        let sender_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7805);
+        let strict_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7806);
+        let lenient_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7807);
+
+        let sender_sock = UdpSocket::bind(sender_addr).await.unwrap();
+
+        let (mut strict_channels, strict_runner) =
+            setup_udp(create_ipv4_socket(strict_addr.port()).await.unwrap(), 10);
+        task::spawn(strict_runner.run());
+        let (mut lenient_channels, mut lenient_runner) =
+            setup_udp(create_ipv4_socket(lenient_addr.port()).await.unwrap(), 10);
+        lenient_runner.set_trailing_data(TrailingData::Ignore);
+        task::spawn(lenient_runner.run());
+
+        let padded = [BIND_REQUEST_BYTES.as_slice(), &[0u8; 4]].concat();
+        let truncated = &BIND_REQUEST_BYTES[..BIND_REQUEST_BYTES.len() - 4];
+        for receiver_addr in [strict_addr, lenient_addr] {
+            sender_sock.send_to(&padded, receiver_addr).await.unwrap();
+            sender_sock.send_to(truncated, receiver_addr).await.unwrap();
+            sender_sock
+                .send_to(&BIND_RESPONSE_BYTES, receiver_addr)
+                .await
+                .unwrap();
+        }
+
+        // strict: only the well-formed message gets through
+        let (receved_msg, _) = timeout(sec!(5), strict_channels.ingress_source.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(receved_msg, bind_response_msg());
+
+        // lenient: padding is ignored, but the truncated message is still discarded
+        let (receved_msg, _) = timeout(sec!(5), lenient_channels.ingress_source.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(receved_msg, bind_request_msg());
+        let (receved_msg, _) = timeout(sec!(5), lenient_channels.ingress_source.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(receved_msg, bind_response_msg());
+    }
+
+    #[tokio::test]
+    async fn discard_message_with_inconsistent_length() {
+        let sender_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7800);
+        let receiver_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7801);
+
+        let receiver_sock = UdpSocket::bind(receiver_addr).await.unwrap();
+
+        let socket = create_ipv4_socket(sender_addr.port()).await.unwrap();
+        let (
+            MessageChannels {
+                egress_sink: tx_channel,
+                ..
+            },
+            runner,
+        ) = setup_udp(socket, 10);
+        task::spawn(runner.run());
+
+        let mut bad_msg = bind_indication_msg();
+        bad_msg.header.length += 20;
+        tx_channel
+            .send((bad_msg, receiver_addr.into()))
+            .await
+            .unwrap();
+        tx_channel
+            .send((bind_response_msg(), receiver_addr.into()))
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 1500];
+        let (len, _) = timeout(sec!(5), receiver_sock.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], &BIND_RESPONSE_BYTES);
+    }
+
     #[tokio::test]
     async fn send_messages() {
         let sender_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7786);
@@ -287,6 +795,7 @@ mod tests {
             MessageChannels {
                 egress_sink: tx_channel,
                 ingress_source: _rx_channel,
+                ..
             },
             runner,
         ) = setup_udp(socket, 10);
@@ -332,6 +841,7 @@ mod tests {
             MessageChannels {
                 egress_sink: tx_channel,
                 ingress_source: _rx_channel,
+                ..
             },
             runner,
         ) = setup_udp(socket, 10);
@@ -378,6 +888,7 @@ mod tests {
             MessageChannels {
                 egress_sink: _tx_channel,
                 ingress_source: mut rx_channel,
+                ..
             },
             runner,
         ) = setup_udp(socket, 1);
@@ -409,4 +920,279 @@ mod tests {
         assert_eq!(src_addr, second_sender_addr.into());
         assert_eq!(receved_msg, bind_request_msg());
     }
+
+    #[tokio::test]
+    async fn ingress_tap_receives_raw_bytes() {
+        let sender_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7794);
+        let receiver_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7795);
+
+        let sender_sock = UdpSocket::bind(sender_addr).await.unwrap();
+
+        let socket = create_ipv4_socket(receiver_addr.port()).await.unwrap();
+        let (
+            MessageChannels {
+                ingress_source: mut rx_channel,
+                ..
+            },
+            mut runner,
+        ) = setup_udp(socket, 10);
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        runner.set_ingress_tap({
+            let tapped = tapped.clone();
+            move |bytes, src_addr| tapped.lock().unwrap().push((bytes.to_vec(), src_addr))
+        });
+        task::spawn(runner.run());
+
+        sender_sock
+            .send_to(&BIND_RESPONSE_BYTES, receiver_addr)
+            .await
+            .unwrap();
+        let (received_msg, _) = timeout(sec!(5), rx_channel.recv()).await.unwrap().unwrap();
+        assert_eq!(received_msg, bind_response_msg());
+        assert_eq!(
+            *tapped.lock().unwrap(),
+            vec![(BIND_RESPONSE_BYTES.to_vec(), SocketAddr::from(sender_addr))]
+        );
+    }
+
+    #[tokio::test]
+    async fn report_local_address_of_socket_bound_to_port_0() {
+        let socket = create_ipv4_socket(0).await.unwrap();
+        let (_channels, driver) = setup_udp(socket, 10);
+
+        let local_addr = driver.local_addr().unwrap();
+        assert_ne!(local_addr.port(), 0);
+        assert_eq!(local_addr.ip(), Ipv4Addr::UNSPECIFIED);
+    }
+
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    #[tokio::test]
+    async fn bind_two_sockets_to_same_port_with_reuse() {
+        let local_addr: SocketAddr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7793).into();
+        let options = BindOptions {
+            reuse_address: true,
+            reuse_port: true,
+            ..Default::default()
+        };
+
+        let (_channels1, _driver1) = setup_udp_bind(local_addr, options.clone(), 10).unwrap();
+        let (_channels2, _driver2) = setup_udp_bind(local_addr, options, 10).unwrap();
+
+        let result = setup_udp_bind(local_addr, BindOptions::default(), 10);
+        assert!(matches!(result, Err(e) if e.kind() == io::ErrorKind::AddrInUse));
+    }
+
+    #[tokio::test]
+    async fn report_truncated_response() {
+        let sender_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7808);
+        let receiver_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7809);
+        let sender_sock = UdpSocket::bind(sender_addr).await.unwrap();
+
+        let socket = create_ipv4_socket(receiver_addr.port()).await.unwrap();
+        let (
+            MessageChannels {
+                egress_sink: _tx_channel,
+                ingress_source: mut rx_channel,
+                event_source: mut event_channel,
+            },
+            runner,
+        ) = setup_udp(socket, 10);
+        task::spawn(runner.run());
+
+        sender_sock
+            .send_to(&BIND_RESPONSE_BYTES[..30], receiver_addr)
+            .await
+            .unwrap();
+
+        let event = timeout(sec!(1), event_channel.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            TransportEvent::Truncated {
+                remote_addr: sender_addr.into(),
+                transaction_id: [0xbb; 12],
+            }
+        );
+        assert!(rx_channel.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn stop_driver_when_closed_or_abandoned() {
+        let socket = create_ipv4_socket(7813).await.unwrap();
+        let (channels, driver) = setup_udp(socket, 10);
+        let closer = driver.closer();
+        let driver = task::spawn(driver.run());
+
+        closer.close().await;
+        assert!(driver.is_finished());
+        driver.await.unwrap().unwrap();
+        drop(channels);
+
+        let socket = create_ipv4_socket(7814).await.unwrap();
+        let (channels, driver) = setup_udp(socket, 10);
+        let driver = task::spawn(driver.run());
+
+        drop(channels);
+        timeout(sec!(5), driver).await.unwrap().unwrap().unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn report_unreachable_destination() {
+        let closed_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7796);
+
+        let socket = create_ipv4_socket(7797).await.unwrap();
+        let (
+            MessageChannels {
+                egress_sink: tx_channel,
+                ingress_source: _rx_channel,
+                event_source: mut event_channel,
+            },
+            runner,
+        ) = setup_udp(socket, 10);
+        let runner = task::spawn(runner.run());
+
+        tx_channel
+            .send((bind_request_msg(), closed_addr.into()))
+            .await
+            .unwrap();
+
+        let event = timeout(sec!(1), event_channel.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, TransportEvent::Unreachable(closed_addr.into()));
+        assert!(!runner.is_finished());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn keep_receiving_after_any_icmp_error() {
+        for errno in [
+            libc::ECONNREFUSED,
+            libc::ENETUNREACH,
+            libc::EHOSTUNREACH,
+            libc::EHOSTDOWN,
+            libc::EMSGSIZE,
+            libc::EPROTO,
+        ] {
+            assert!(
+                is_icmp_error(&io::Error::from_raw_os_error(errno)),
+                "{errno}"
+            );
+        }
+        assert!(!is_icmp_error(&io::Error::from_raw_os_error(libc::EBADF)));
+    }
+
+    #[tokio::test]
+    async fn reach_ipv4_and_ipv6_destinations_over_dual_stack_socket() {
+        use crate::attributes::{Attribute, AttributeCollection, XorMappedAddress};
+        use std::net::{Ipv6Addr, SocketAddrV6};
+
+        let ipv4_peer_addr: SocketAddr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7811).into();
+        let ipv6_peer_addr: SocketAddr = SocketAddrV6::new(Ipv6Addr::LOCALHOST, 7812, 0, 0).into();
+        let Ok(ipv6_peer) = UdpSocket::bind(ipv6_peer_addr).await else {
+            log::warn!("Skipping test: IPv6 is not available");
+            return;
+        };
+        let ipv4_peer = UdpSocket::bind(ipv4_peer_addr).await.unwrap();
+
+        let options = BindOptions {
+            dual_stack: true,
+            ..Default::default()
+        };
+        let local_addr: SocketAddr = SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 7810, 0, 0).into();
+        let (
+            MessageChannels {
+                egress_sink: tx_channel,
+                ingress_source: mut rx_channel,
+                ..
+            },
+            driver,
+        ) = setup_udp_bind(local_addr, options, 10).unwrap();
+        task::spawn(driver.run());
+
+        for (peer, peer_addr) in [(ipv4_peer, ipv4_peer_addr), (ipv6_peer, ipv6_peer_addr)] {
+            tx_channel
+                .send((bind_request_msg(), peer_addr))
+                .await
+                .unwrap();
+
+            let mut buffer = [0u8; 1024];
+            let (len, src_addr) = timeout(sec!(5), peer.recv_from(&mut buffer))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buffer[..len], &BIND_REQUEST_BYTES);
+            assert_eq!(src_addr.is_ipv4(), peer_addr.is_ipv4());
+            assert_eq!(src_addr.port(), 7810);
+
+            let mut attributes = Vec::new();
+            attributes.append_attribute(XorMappedAddress(src_addr));
+            let response = Message::response(0x0001, [0xaa; 12], attributes)
+                .xor_socket_addr(XorMappedAddress::ID);
+            let mut buffer = Vec::new();
+            response.header.encode_into(&mut buffer).unwrap();
+            response.attributes.encode_into(&mut buffer).unwrap();
+            peer.send_to(&buffer, src_addr).await.unwrap();
+
+            // IPv4 sources are not reported as IPv4-mapped IPv6 addresses
+            let (received_msg, received_from) =
+                timeout(sec!(5), rx_channel.recv()).await.unwrap().unwrap();
+            assert_eq!(received_from, peer_addr);
+            let mapped_addr = received_msg
+                .xor_socket_addr(XorMappedAddress::ID)
+                .attributes
+                .extract_attribute::<XorMappedAddress>()
+                .unwrap();
+            assert_eq!(mapped_addr.0, src_addr);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn bind_to_device_by_name() {
+        let local_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7802);
+        let receiver_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7803);
+        let options = BindOptions {
+            device: Some("lo".to_owned()),
+            ..Default::default()
+        };
+
+        let (channels, driver) = match setup_udp_bind(local_addr.into(), options, 10) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                log::warn!("Skipping test: binding to a device requires CAP_NET_RAW");
+                return;
+            }
+            result => result.unwrap(),
+        };
+        task::spawn(driver.run());
+        let receiver_sock = UdpSocket::bind(receiver_addr).await.unwrap();
+
+        channels
+            .egress_sink
+            .send((bind_request_msg(), receiver_addr.into()))
+            .await
+            .unwrap();
+
+        let mut buffer = [0u8; 1024];
+        let (len, src_addr) = timeout(sec!(5), receiver_sock.recv_from(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buffer[..len], &BIND_REQUEST_BYTES);
+        assert_eq!(src_addr, local_addr.into());
+
+        let result = setup_udp_bind(
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7804).into(),
+            BindOptions {
+                device: Some("no-such-device".to_owned()),
+                ..Default::default()
+            },
+            10,
+        );
+        assert!(result.is_err());
+    }
 }
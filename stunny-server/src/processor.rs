@@ -9,6 +9,7 @@ use tokio::task;
 pub struct Processor {
     handlers: HashMap<u16, Arc<dyn Handler>>,
     default_handler: Arc<dyn Handler>,
+    key_resolver: Option<Arc<dyn KeyResolver>>,
     receiver: RequestReceiver,
 }
 
@@ -17,6 +18,7 @@ impl Processor {
         Self {
             handlers: [(0x0001u16, Arc::new(DefaultBindHandler) as Arc<dyn Handler>)].into(),
             default_handler: Arc::new(UnrecognizedMethodHandler),
+            key_resolver: None,
             receiver,
         }
     }
@@ -29,8 +31,16 @@ impl Processor {
         self.default_handler = default_handler;
     }
 
+    /// Sign responses to authenticated requests with the key of the requesting user.
+    pub fn set_key_resolver(&mut self, key_resolver: Arc<dyn KeyResolver>) {
+        self.key_resolver = Some(key_resolver);
+    }
+
     pub async fn run(mut self) {
-        while let Some(request) = self.receiver.next().await {
+        while let Some(mut request) = self.receiver.next().await {
+            if let Some(key_resolver) = &self.key_resolver {
+                request.resolve_integrity_key(key_resolver.as_ref());
+            }
             let handler = match self.handlers.get(&request.method()) {
                 Some(h) => h.clone(),
                 None => self.default_handler.clone(),
@@ -48,6 +58,21 @@ pub trait Handler: Send + Sync {
     async fn handle_request(&self, request: Request);
 }
 
+/// Looks up the MESSAGE-INTEGRITY key of a user, e.g. the ICE password or the long-term
+/// credential key.
+pub trait KeyResolver: Send + Sync {
+    fn resolve_key(&self, username: &str) -> Option<Vec<u8>>;
+}
+
+impl<F> KeyResolver for F
+where
+    F: Fn(&str) -> Option<Vec<u8>> + Send + Sync,
+{
+    fn resolve_key(&self, username: &str) -> Option<Vec<u8>> {
+        self(username)
+    }
+}
+
 // ------------------------------------------------------------------------------------------------
 
 struct UnrecognizedMethodHandler;
@@ -68,9 +93,32 @@ impl Handler for UnrecognizedMethodHandler {
 
 struct DefaultBindHandler;
 
+impl DefaultBindHandler {
+    const KNOWN_ATTRIBUTES: &'static [u16] = &[
+        Username::ID,
+        Userhash::ID,
+        MessageIntegrity::ID,
+        MessageIntegritySha256::ID,
+        Realm::ID,
+        Nonce::ID,
+        PasswordAlgorithm::ID,
+        Priority::ID,
+        UseCandidate::ID,
+    ];
+}
+
 #[async_trait]
 impl Handler for DefaultBindHandler {
     async fn handle_request(&self, request: Request) {
+        let unknown = request.unknown_required_attributes(Self::KNOWN_ATTRIBUTES);
+        if !unknown.is_empty() {
+            let _ = request
+                .build_response()
+                .with_unknown_attributes(unknown)
+                .send_error()
+                .await;
+            return;
+        }
         let client_addr = request.source_addr();
         let _ = request
             .build_response()
@@ -101,6 +149,7 @@ mod tests {
         let request_receiver = RequestReceiver::from(MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         });
         Test {
             egress_source,
@@ -139,6 +188,72 @@ mod tests {
         assert!(attributes.is_empty());
     }
 
+    #[tokio::test]
+    async fn reject_bind_request_with_unknown_required_attribute() {
+        let Test {
+            mut egress_source,
+            ingress_sink,
+            processor,
+        } = setup();
+        task::spawn(processor.run());
+
+        // when
+        let ip = SocketAddr::new(Ipv4Addr::new(1, 2, 3, 4).into(), 3478);
+        let attributes = [Priority::ID, 0x7777, 0xc057]
+            .map(|attribute_type| Tlv {
+                attribute_type,
+                value: vec![0; 4],
+            })
+            .to_vec();
+        ingress_sink
+            .try_send((Message::request(0x0001, [0xaf; 12], attributes), ip))
+            .unwrap();
+        task::yield_now().await;
+
+        // then
+        let (mut response, addr) = egress_source.try_recv().unwrap();
+        assert_eq!(addr, ip);
+        assert_eq!(response.header.class, Class::Error);
+        let error_code = response
+            .attributes
+            .extract_attribute::<ErrorCode>()
+            .unwrap();
+        assert_eq!(error_code.code, 420);
+        let unknown_attributes = response
+            .attributes
+            .extract_attribute::<UnknownAttributes>()
+            .unwrap();
+        assert_eq!(unknown_attributes.0, vec![0x7777]);
+    }
+
+    #[tokio::test]
+    async fn accept_bind_request_with_long_term_credentials() {
+        let Test {
+            mut egress_source,
+            ingress_sink,
+            processor,
+        } = setup();
+        task::spawn(processor.run());
+
+        // when
+        let ip = SocketAddr::new(Ipv4Addr::new(1, 2, 3, 4).into(), 3478);
+        let attributes = [Username::ID, Realm::ID, Nonce::ID, PasswordAlgorithm::ID]
+            .map(|attribute_type| Tlv {
+                attribute_type,
+                value: vec![0; 4],
+            })
+            .to_vec();
+        ingress_sink
+            .try_send((Message::request(0x0001, [0xaf; 12], attributes), ip))
+            .unwrap();
+        task::yield_now().await;
+
+        // then
+        let (response, addr) = egress_source.try_recv().unwrap();
+        assert_eq!(addr, ip);
+        assert_eq!(response.header.class, Class::Response);
+    }
+
     #[tokio::test]
     async fn default_response_to_unknown_method() {
         let Test {
@@ -1,6 +1,7 @@
-use super::TransactionError;
 use super::{attributes::*, message::*};
+use super::{KeyResolver, TransactionError};
 use derive_more::Debug;
+use std::mem;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{ready, Context, Poll};
@@ -17,6 +18,10 @@ pub struct Request {
     method: u16,
     attributes: Vec<Tlv>,
     #[debug(skip)]
+    integrity_key: Option<Vec<u8>>,
+    #[debug(skip)]
+    authentication_failed: bool,
+    #[debug(skip)]
     response_sink: mpsc::Sender<(Message, SocketAddr)>,
 }
 
@@ -32,10 +37,59 @@ impl Request {
             transaction_id: message.header.transaction_id,
             method: message.header.method,
             attributes: message.attributes,
+            integrity_key: None,
+            authentication_failed: false,
             response_sink,
         }
     }
 
+    /// If the request carries USERNAME and MESSAGE-INTEGRITY, look up the key of the user and
+    /// verify MESSAGE-INTEGRITY with it. Only if it matches, the response is signed with the key.
+    pub(crate) fn resolve_integrity_key(&mut self, resolver: &dyn KeyResolver) {
+        if !self
+            .attributes
+            .iter()
+            .any(|tlv| tlv.attribute_type == MessageIntegrity::ID)
+        {
+            return;
+        }
+        let key = self
+            .attributes
+            .iter()
+            .find(|tlv| tlv.attribute_type == Username::ID)
+            .and_then(|tlv| Username::decode_value(tlv.value.clone()).ok())
+            .and_then(|Username(username)| resolver.resolve_key(&username));
+        match key {
+            Some(key) if self.verify_message_integrity(&key) => self.integrity_key = Some(key),
+            _ => self.authentication_failed = true,
+        }
+    }
+
+    fn verify_message_integrity(&mut self, key: &[u8]) -> bool {
+        // MESSAGE-INTEGRITY covers the attributes as they were received
+        let message = Message::request(
+            self.method,
+            self.transaction_id,
+            mem::take(&mut self.attributes),
+        )
+        .xor_socket_addr(XorMappedAddress::ID);
+        let verified = message.verify_message_integrity(key) == Some(true);
+        self.attributes = message.xor_socket_addr(XorMappedAddress::ID).attributes;
+        verified
+    }
+
+    /// Whether MESSAGE-INTEGRITY of the request has been verified with the key of its USERNAME.
+    pub fn is_authenticated(&self) -> bool {
+        self.integrity_key.is_some()
+    }
+
+    /// Whether the request carries MESSAGE-INTEGRITY that could not be verified, either because
+    /// USERNAME is missing or unknown or because the HMAC doesn't match. Such requests should be
+    /// rejected with 401.
+    pub fn authentication_failed(&self) -> bool {
+        self.authentication_failed
+    }
+
     pub fn source_addr(&self) -> SocketAddr {
         self.source_addr
     }
@@ -48,10 +102,17 @@ impl Request {
         &mut self.attributes
     }
 
+    /// Comprehension-required attributes of the request that are not in `known`. If there are
+    /// any, the request must be rejected with `Response::with_unknown_attributes()`.
+    pub fn unknown_required_attributes(&self, known: &[u16]) -> Vec<u16> {
+        unknown_required_attributes(&self.attributes, known)
+    }
+
     pub fn build_response(self) -> Response {
         Response {
             request: self,
             attributes: Vec::new(),
+            fingerprint: false,
         }
     }
 }
@@ -59,6 +120,7 @@ impl Request {
 pub struct Response {
     request: Request,
     attributes: Vec<Tlv>,
+    fingerprint: bool,
 }
 
 impl Response {
@@ -67,13 +129,30 @@ impl Response {
         self
     }
 
+    /// Turn into a 420 error listing `attribute_types` in UNKNOWN-ATTRIBUTES, to be sent with
+    /// `send_error()`.
+    pub fn with_unknown_attributes(self, attribute_types: Vec<u16>) -> Self {
+        self.with_attribute(ErrorCode {
+            code: 420,
+            reason: "Unknown Attribute".into(),
+        })
+        .with_attribute(UnknownAttributes(attribute_types))
+    }
+
+    /// Append FINGERPRINT when sending, after MESSAGE-INTEGRITY if the request was authenticated.
+    pub fn with_fingerprint(mut self) -> Self {
+        self.fingerprint = true;
+        self
+    }
+
     pub async fn send(self) -> Result<(), TransactionError> {
-        let response_message = Message::response(
+        let mut response_message = Message::response(
             self.request.method,
             self.request.transaction_id,
             self.attributes,
         )
         .xor_socket_addr(XorMappedAddress::ID);
+        Self::sign(&mut response_message, &self.request, self.fingerprint);
         self.request
             .response_sink
             .send((response_message, self.request.source_addr))
@@ -82,17 +161,27 @@ impl Response {
     }
 
     pub async fn send_error(self) -> Result<(), TransactionError> {
-        let response_message = Message::error(
+        let mut response_message = Message::error(
             self.request.method,
             self.request.transaction_id,
             self.attributes,
         );
+        Self::sign(&mut response_message, &self.request, self.fingerprint);
         self.request
             .response_sink
             .send((response_message, self.request.source_addr))
             .await?;
         Ok(())
     }
+
+    fn sign(message: &mut Message, request: &Request, fingerprint: bool) {
+        if let Some(key) = &request.integrity_key {
+            message.append_message_integrity(key);
+        }
+        if fingerprint {
+            message.append_fingerprint();
+        }
+    }
 }
 
 pub(crate) struct RequestReceiver(MessageChannels);
@@ -110,6 +199,7 @@ impl futures_util::Stream for RequestReceiver {
         let MessageChannels {
             egress_sink,
             ingress_source,
+            ..
         } = &mut self.get_mut().0;
         loop {
             let msg_addr = match ready!(ingress_source.poll_recv(cx)) {
@@ -139,6 +229,7 @@ mod tests {
         let request_receiver = RequestReceiver(MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         });
         let mut receive_fut = spawn(request_receiver);
         assert_pending!(receive_fut.poll_next());
@@ -179,6 +270,148 @@ mod tests {
         assert_eq!(xor_mapped_attr.0, ip);
     }
 
+    #[test]
+    fn sign_response_to_authenticated_request() {
+        let (egress_sink, mut egress_source) = mpsc::channel(10);
+        let (ingress_sink, ingress_source) = mpsc::channel(10);
+        let request_receiver = RequestReceiver(MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        });
+        let mut receive_fut = spawn(request_receiver);
+        let resolver = |username: &str| (username == "user").then(|| b"secret".to_vec());
+
+        let ip = SocketAddr::new(Ipv4Addr::new(1, 2, 3, 4).into(), 5349);
+        let mut attributes = Vec::new();
+        attributes.append_attribute(Username("user".to_owned()));
+        let mut bind_request = Message::request(0x0001, [0xaf; 12], attributes);
+        bind_request.append_message_integrity(b"secret");
+        ingress_sink.try_send((bind_request, ip)).unwrap();
+
+        // when
+        let mut request = assert_ready!(receive_fut.poll_next()).unwrap();
+        request.resolve_integrity_key(&resolver);
+        assert!(request.is_authenticated());
+        let response = request
+            .build_response()
+            .with_attribute(XorMappedAddress(ip))
+            .with_fingerprint();
+        assert_ready!(spawn(response.send()).poll()).unwrap();
+
+        // then
+        let (response_message, _) = egress_source.try_recv().unwrap();
+        let attribute_types: Vec<u16> = response_message
+            .attributes
+            .iter()
+            .map(|tlv| tlv.attribute_type)
+            .collect();
+        assert_eq!(
+            attribute_types,
+            vec![XorMappedAddress::ID, MessageIntegrity::ID, Fingerprint::ID]
+        );
+        assert_eq!(
+            response_message.header.length as usize,
+            response_message
+                .attributes
+                .iter()
+                .map(Tlv::encoded_size)
+                .sum::<usize>()
+        );
+        assert_eq!(response_message.verify_fingerprint(), Some(true));
+        assert_eq!(
+            response_message.verify_message_integrity(b"secret"),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn dont_sign_response_to_request_with_tampered_message_integrity() {
+        let (egress_sink, mut egress_source) = mpsc::channel(10);
+        let (ingress_sink, ingress_source) = mpsc::channel(10);
+        let request_receiver = RequestReceiver(MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        });
+        let mut receive_fut = spawn(request_receiver);
+        let resolver = |username: &str| (username == "user").then(|| b"secret".to_vec());
+
+        let ip = SocketAddr::new(Ipv4Addr::new(1, 2, 3, 4).into(), 5349);
+        let mut attributes = Vec::new();
+        attributes.append_attribute(Username("user".to_owned()));
+        let mut bind_request = Message::request(0x0001, [0xaf; 12], attributes);
+        bind_request.append_message_integrity(b"secret");
+        bind_request.attributes.last_mut().unwrap().value[0] ^= 0xff;
+        ingress_sink.try_send((bind_request, ip)).unwrap();
+
+        // when
+        let mut request = assert_ready!(receive_fut.poll_next()).unwrap();
+        request.resolve_integrity_key(&resolver);
+        assert!(!request.is_authenticated());
+        assert!(request.authentication_failed());
+        let response = request
+            .build_response()
+            .with_attribute(XorMappedAddress(ip))
+            .with_fingerprint();
+        assert_ready!(spawn(response.send()).poll()).unwrap();
+
+        // then
+        let (response_message, _) = egress_source.try_recv().unwrap();
+        let attribute_types: Vec<u16> = response_message
+            .attributes
+            .iter()
+            .map(|tlv| tlv.attribute_type)
+            .collect();
+        assert_eq!(attribute_types, vec![XorMappedAddress::ID, Fingerprint::ID]);
+        assert_eq!(response_message.verify_message_integrity(b"secret"), None);
+    }
+
+    #[test]
+    fn reject_request_with_unknown_required_attributes() {
+        let (egress_sink, mut egress_source) = mpsc::channel(10);
+        let (ingress_sink, ingress_source) = mpsc::channel(10);
+        let request_receiver = RequestReceiver(MessageChannels {
+            egress_sink,
+            ingress_source,
+            event_source: mpsc::channel(1).1,
+        });
+        let mut receive_fut = spawn(request_receiver);
+
+        let ip = SocketAddr::new(Ipv4Addr::new(1, 2, 3, 4).into(), 5349);
+        let attributes = [0x7777, Software::ID, 0x0003]
+            .map(|attribute_type| Tlv {
+                attribute_type,
+                value: vec![0xff; 4],
+            })
+            .to_vec();
+        let bind_request = Message::request(0x0001, [0xaf; 12], attributes);
+        ingress_sink.try_send((bind_request, ip)).unwrap();
+
+        // when
+        let request = assert_ready!(receive_fut.poll_next()).unwrap();
+        let unknown = request.unknown_required_attributes(&[Username::ID]);
+        assert_eq!(unknown, vec![0x7777, 0x0003]);
+        let response = request.build_response().with_unknown_attributes(unknown);
+        assert_ready!(spawn(response.send_error()).poll()).unwrap();
+
+        // then
+        let (mut response_message, _) = egress_source.try_recv().unwrap();
+        assert_eq!(response_message.header.class, Class::Error);
+        assert_eq!(response_message.header.transaction_id, [0xaf; 12]);
+        let error_code = response_message
+            .attributes
+            .extract_attribute::<ErrorCode>()
+            .unwrap();
+        assert_eq!(error_code.code, 420);
+        let unknown_attributes = response_message
+            .attributes
+            .extract_attribute::<UnknownAttributes>()
+            .unwrap();
+        assert_eq!(unknown_attributes.0, vec![0x7777, 0x0003]);
+        assert!(response_message.attributes.is_empty());
+    }
+
     #[test]
     fn ignore_indication_and_response() {
         let (egress_sink, _egress_source) = mpsc::channel(10);
@@ -186,6 +419,7 @@ mod tests {
         let request_receiver = RequestReceiver(MessageChannels {
             egress_sink,
             ingress_source,
+            event_source: mpsc::channel(1).1,
         });
         let mut receive_fut = spawn(request_receiver);
         assert_pending!(receive_fut.poll_next());